@@ -0,0 +1,409 @@
+//! # Command line interface
+//!
+//! With a subcommand the app runs it and exits without opening the window,
+//! e.g. `spodjfy play spotify:album:...` or `spodjfy now-playing --json`.
+//! Logging in is only possible in the window, the commands use the token it
+//! has saved for the active account.
+//!
+//! Exit codes:
+//!   - 0 - command succeeded,
+//!   - 1 - command failed, e.g. Spotify rejected a request,
+//!   - 2 - invalid arguments,
+//!   - 3 - not logged in, or the saved token can't be refreshed.
+
+use crate::{
+    config::Config,
+    loaders::{ContainerLoader, PlaylistLoader},
+    models::{PageLike, TrackLike},
+    services::{api::PlaybackControlApi, store::StorageError, PlaybackQueue, Spotify, SpotifyRef},
+    utils::{csv_field, humanize_time},
+};
+use chrono::Utc;
+use rspotify::{
+    client::{ClientError, ClientResult},
+    model::{CurrentPlaybackContext, Device},
+};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::{
+    io::{self, Write},
+    str::FromStr,
+    sync::Arc,
+};
+use structopt::{clap::ErrorKind, StructOpt};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::UnixStream,
+    sync::RwLock,
+};
+
+const EXIT_SUCCESS: i32 = 0;
+
+const EXIT_FAILURE: i32 = 1;
+
+const EXIT_USAGE: i32 = 2;
+
+const EXIT_NOT_LOGGED_IN: i32 = 3;
+
+/// Access token is refreshed before a command if it expires sooner than this
+const TOKEN_REFRESH_AHEAD_SECS: i64 = 60;
+
+#[derive(StructOpt)]
+#[structopt(
+    name = "spodjfy",
+    about = "Spotify client for DJs, opens the app window if no command is given"
+)]
+pub struct Cli {
+    #[structopt(subcommand)]
+    pub command: Option<Command>,
+}
+
+impl Cli {
+    /// Parses command line, prints help or usage error and exits if needed
+    pub fn from_args_or_exit() -> Cli {
+        Cli::from_args_safe().unwrap_or_else(|error| match error.kind {
+            ErrorKind::HelpDisplayed | ErrorKind::VersionDisplayed => {
+                println!("{}", error.message);
+                std::process::exit(EXIT_SUCCESS);
+            }
+            _ => {
+                eprintln!("{}", error.message);
+                std::process::exit(EXIT_USAGE);
+            }
+        })
+    }
+}
+
+#[derive(StructOpt)]
+pub enum Command {
+    /// Plays a track, an episode or a context (album, playlist, artist, show),
+    /// resumes playback if no URI is given
+    Play { uri: Option<String> },
+    /// Pauses playback
+    Pause,
+    /// Skips to the next track
+    Next,
+    /// Goes back to the previous track
+    Previous,
+    /// Manages the app playback queue
+    Queue(QueueCommand),
+    /// Lists devices available for playback
+    Devices {
+        #[structopt(long)]
+        json: bool,
+    },
+    /// Works with playlists
+    Playlist(PlaylistCommand),
+    /// Shows the current track and playback state
+    NowPlaying {
+        #[structopt(long)]
+        json: bool,
+    },
+}
+
+#[derive(StructOpt)]
+pub enum QueueCommand {
+    /// Adds tracks to the end of the queue
+    Add {
+        #[structopt(required = true)]
+        uris: Vec<String>,
+    },
+}
+
+#[derive(StructOpt)]
+pub enum PlaylistCommand {
+    /// Prints playlist tracks
+    Export {
+        uri: String,
+        #[structopt(long, default_value = "csv", possible_values = &["csv", "json"])]
+        format: ExportFormat,
+    },
+}
+
+pub enum ExportFormat {
+    Csv,
+    Json,
+}
+
+impl FromStr for ExportFormat {
+    type Err = String;
+
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        match format {
+            "csv" => Ok(ExportFormat::Csv),
+            "json" => Ok(ExportFormat::Json),
+            _ => Err(format!("unknown format: {}", format)),
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum CliError {
+    #[error("not logged in, log in to Spotify in the app window first")]
+    NotLoggedIn,
+    #[error("Spotify request failed: {0}")]
+    Client(#[from] ClientError),
+    #[error("queue error: {0}")]
+    Storage(#[from] StorageError),
+    #[error("app refused the command: {0}")]
+    Control(String),
+    #[error("invalid JSON: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("{0}")]
+    Io(#[from] io::Error),
+}
+
+impl CliError {
+    fn exit_code(&self) -> i32 {
+        match self {
+            CliError::NotLoggedIn
+            | CliError::Client(ClientError::InvalidAuth(_))
+            | CliError::Client(ClientError::Unauthorized) => EXIT_NOT_LOGGED_IN,
+            _ => EXIT_FAILURE,
+        }
+    }
+}
+
+/// Track as exported from a playlist
+#[derive(Serialize)]
+struct TrackRecord<'a> {
+    uri: &'a str,
+    name: &'a str,
+    artists: String,
+    album: &'a str,
+    duration_ms: u32,
+}
+
+impl<'a, T: TrackLike> From<&'a T> for TrackRecord<'a> {
+    fn from(track: &'a T) -> Self {
+        TrackRecord {
+            uri: track.uri(),
+            name: track.name(),
+            artists: track
+                .artists()
+                .iter()
+                .map(|artist| &*artist.name)
+                .collect::<Vec<_>>()
+                .join(", "),
+            album: track.album().map_or("", |album| &album.name),
+            duration_ms: track.duration(),
+        }
+    }
+}
+
+impl Command {
+    /// Runs the command, returns process exit code
+    pub fn run(self) -> i32 {
+        let mut runtime = match tokio::runtime::Builder::new().basic_scheduler().enable_all().build() {
+            Ok(runtime) => runtime,
+            Err(error) => {
+                eprintln!("spodjfy: failed to start runtime: {}", error);
+                return EXIT_FAILURE;
+            }
+        };
+
+        match runtime.block_on(self.execute(Config::new())) {
+            Ok(()) => EXIT_SUCCESS,
+            Err(error) => {
+                eprintln!("spodjfy: {}", error);
+                error.exit_code()
+            }
+        }
+    }
+
+    async fn execute(self, config: Config) -> Result<(), CliError> {
+        if let Command::Queue(QueueCommand::Add { uris }) = self {
+            return enqueue(&config, uris).await;
+        }
+
+        let spotify = connect(&config).await?;
+
+        match self {
+            Command::Play { uri: Some(uri) } => spotify.read().await.play_uri(uri).await?,
+            Command::Play { uri: None } => spotify.read().await.start_playback().await?,
+            Command::Pause => spotify.read().await.pause_playback().await?,
+            Command::Next => spotify.read().await.play_next_track().await?,
+            Command::Previous => spotify.read().await.play_prev_track().await?,
+            Command::Devices { json } => print_devices(&spotify.read().await.get_my_devices().await?, json)?,
+            Command::Playlist(PlaylistCommand::Export { uri, format }) => {
+                let items = load_all::<PlaylistLoader>(spotify, uri).await?;
+                let tracks = items
+                    .iter()
+                    .map(TrackRecord::from)
+                    .filter(|track| !track.uri.is_empty())
+                    .collect::<Vec<_>>();
+
+                print_tracks(&tracks, format)?;
+            }
+            Command::NowPlaying { json } => print_now_playing(&spotify.read().await.get_playback_state().await?, json)?,
+            Command::Queue(_) => unreachable!("queue is managed without Spotify client"),
+        }
+
+        Ok(())
+    }
+}
+
+/// Creates client for the active account with its saved token
+async fn connect(config: &Config) -> Result<SpotifyRef, CliError> {
+    let settings = config.load_settings();
+    let account = settings.account().cloned().unwrap_or_default();
+    let mut spotify = Spotify::new(
+        account.client_id,
+        account.client_secret,
+        config.spotify_token_file(&account.name),
+    );
+
+    spotify.load_token_from_cache().await;
+
+    if !spotify.has_token() {
+        return Err(CliError::NotLoggedIn);
+    }
+
+    if let Some(expires_at) = spotify.token_expires_at() {
+        if (expires_at - Utc::now()).num_seconds() < TOKEN_REFRESH_AHEAD_SECS {
+            let refreshed = spotify.refresh_user_token().await?;
+            spotify.save_refreshed_token(refreshed).await?;
+        }
+    }
+
+    Ok(Arc::new(RwLock::new(spotify)))
+}
+
+/// Adds tracks to the queue, through the running app if there is one, as it
+/// holds the queue storage open
+async fn enqueue(config: &Config, uris: Vec<String>) -> Result<(), CliError> {
+    if let Ok(stream) = UnixStream::connect(config.control_socket_file()).await {
+        return send_control_command(stream, json!({ "command": "enqueue", "uris": uris })).await;
+    }
+
+    let account = config.load_settings().account().cloned().unwrap_or_default();
+    PlaybackQueue::open(config.playback_queue_dir(&account.name))?.push(&uris)?;
+
+    Ok(())
+}
+
+async fn send_control_command(mut stream: UnixStream, command: Value) -> Result<(), CliError> {
+    let mut line = command.to_string();
+    line.push('\n');
+    stream.write_all(line.as_bytes()).await?;
+
+    let mut response = String::new();
+    BufReader::new(stream).read_line(&mut response).await?;
+
+    let response: Value = serde_json::from_str(&response)?;
+
+    match response["ok"].as_bool() {
+        Some(true) => Ok(()),
+        _ => Err(CliError::Control(
+            response["error"].as_str().unwrap_or("unknown error").to_owned(),
+        )),
+    }
+}
+
+/// Loads all pages of a container
+async fn load_all<Loader>(spotify: SpotifyRef, id: Loader::ParentId) -> ClientResult<Vec<Loader::Item>>
+where
+    Loader: ContainerLoader,
+    Loader::ParentId: Clone,
+    Loader::Item: Clone,
+{
+    let mut items = Vec::new();
+    let mut offset = <Loader::Page as PageLike<Loader::Item>>::init_offset();
+
+    loop {
+        let page = Loader::new(id.clone()).load_page(spotify.clone(), offset).await?;
+        items.extend_from_slice(page.items());
+
+        match page.next_offset() {
+            Some(next_offset) => offset = next_offset,
+            None => break Ok(items),
+        }
+    }
+}
+
+fn print_devices(devices: &[Device], json: bool) -> Result<(), CliError> {
+    let mut out = io::stdout();
+
+    if json {
+        serde_json::to_writer_pretty(&mut out, devices)?;
+        writeln!(out)?;
+        return Ok(());
+    }
+
+    for device in devices {
+        writeln!(
+            out,
+            "{} {}\t{}",
+            if device.is_active { '*' } else { ' ' },
+            device.name,
+            device.id.as_deref().unwrap_or("-")
+        )?;
+    }
+
+    Ok(())
+}
+
+fn print_tracks(tracks: &[TrackRecord], format: ExportFormat) -> Result<(), CliError> {
+    let mut out = io::stdout();
+
+    match format {
+        ExportFormat::Json => {
+            serde_json::to_writer_pretty(&mut out, tracks)?;
+            writeln!(out)?;
+        }
+        ExportFormat::Csv => {
+            writeln!(out, "uri,name,artists,album,duration_ms")?;
+
+            for track in tracks {
+                writeln!(
+                    out,
+                    "{},{},{},{},{}",
+                    csv_field(track.uri),
+                    csv_field(track.name),
+                    csv_field(&track.artists),
+                    csv_field(track.album),
+                    track.duration_ms
+                )?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn print_now_playing(state: &Option<CurrentPlaybackContext>, json: bool) -> Result<(), CliError> {
+    let mut out = io::stdout();
+
+    if json {
+        serde_json::to_writer_pretty(&mut out, state)?;
+        writeln!(out)?;
+        return Ok(());
+    }
+
+    let (state, item) = match state.as_ref().and_then(|state| Some((state, state.item.as_ref()?))) {
+        Some(playing) => playing,
+        None => {
+            writeln!(out, "Nothing is playing")?;
+            return Ok(());
+        }
+    };
+
+    let track = TrackRecord::from(item);
+
+    writeln!(out, "{} - {}", track.artists, track.name)?;
+
+    if !track.album.is_empty() {
+        writeln!(out, "{}", track.album)?;
+    }
+
+    writeln!(
+        out,
+        "{} / {}, {} on {}",
+        humanize_time(state.progress_ms.unwrap_or(0)),
+        humanize_time(track.duration_ms),
+        if state.is_playing { "playing" } else { "paused" },
+        state.device.name
+    )?;
+
+    Ok(())
+}
@@ -0,0 +1,292 @@
+//! # Audio analysis component
+//!
+//! A panel to show track structure from Spotify audio analysis:
+//! loudness curve, sections with their key and tempo, bar and beat markers.
+//! Clicking on the curve seeks the player to the clicked position,
+//! starting the track first if it's not the one currently playing.
+//!
+//! Parameters:
+//!   - `Handle` - tokio runtime handle to run API requests
+//!   - `SpotifyRef` - a reference to spotify client
+
+use crate::{
+    models::{analysis::TrackAnalysis, KeyNotation},
+    services::{
+        api::{PlaybackControlApi, TracksStorageApi},
+        SpotifyRef,
+    },
+    utils::{Extract, Spawn},
+};
+use gtk::{prelude::*, RevealerExt, WidgetExt};
+use relm::{EventStream, Relm, Widget};
+use relm_derive::{widget, Msg};
+use rspotify::model::PlayingItem;
+use std::{cell::RefCell, rc::Rc};
+use tokio::runtime::Handle;
+
+#[doc(hidden)]
+const CANVAS_HEIGHT: i32 = 160;
+
+#[doc(hidden)]
+const LABELS_HEIGHT: f64 = 18.0;
+
+#[doc(hidden)]
+const SECTION_COLORS: [(f64, f64, f64); 2] = [(0.20, 0.40, 0.70), (0.30, 0.55, 0.45)];
+
+#[derive(Msg)]
+pub enum AnalysisMsg {
+    Load(String, String),
+    NewAnalysis(String, Box<TrackAnalysis>),
+    Click(f64, i32),
+    SeekTrack(String, u32),
+    Seeked,
+    Close,
+}
+
+#[doc(hidden)]
+pub struct AnalysisModel {
+    pool: Handle,
+    stream: EventStream<AnalysisMsg>,
+    spotify: SpotifyRef,
+    track: Option<(String, String)>,
+    title: String,
+    analysis: Rc<RefCell<Option<TrackAnalysis>>>,
+}
+
+#[widget]
+impl Widget for AnalysisView {
+    view! {
+        #[name="revealer"]
+        gtk::Revealer {
+            gtk::Box(gtk::Orientation::Vertical, 5) {
+                margin_start: 10,
+                margin_end: 10,
+
+                gtk::Box(gtk::Orientation::Horizontal, 10) {
+                    gtk::Label {
+                        hexpand: true,
+                        halign: gtk::Align::Start,
+                        ellipsize: pango::EllipsizeMode::End,
+                        text: &self.model.title,
+                    },
+                    gtk::Button {
+                        tooltip_text: Some("Close audio analysis"),
+                        relief: gtk::ReliefStyle::None,
+                        image: Some(&gtk::Image::from_icon_name(Some("window-close-symbolic"), gtk::IconSize::SmallToolbar)),
+                        clicked(_) => AnalysisMsg::Close,
+                    },
+                },
+
+                #[name="canvas"]
+                gtk::DrawingArea {
+                    property_height_request: CANVAS_HEIGHT,
+                    hexpand: true,
+                    tooltip_text: Some("Click to seek"),
+
+                    button_press_event(area, event) => (AnalysisMsg::Click(event.get_position().0, area.get_allocated_width()), Inhibit(false)),
+                },
+            },
+        }
+    }
+
+    fn model(relm: &Relm<Self>, (pool, spotify): (Handle, SpotifyRef)) -> AnalysisModel {
+        AnalysisModel {
+            pool,
+            stream: relm.stream().clone(),
+            spotify,
+            track: None,
+            title: String::new(),
+            analysis: Rc::new(RefCell::new(None)),
+        }
+    }
+
+    fn update(&mut self, event: AnalysisMsg) {
+        use AnalysisMsg::*;
+
+        match event {
+            Load(uri, name) => {
+                self.model.title = format!("Audio analysis: {} (loading…)", name);
+                self.model.track = Some((uri.clone(), name));
+                self.model.analysis.replace(None);
+                self.canvas.queue_draw();
+                self.revealer.set_reveal_child(true);
+
+                self.spawn_args(
+                    uri,
+                    async move |pool, (stream, spotify): (EventStream<_>, SpotifyRef), uri| {
+                        let analysis = pool
+                            .spawn({
+                                let uri = uri.clone();
+                                async move { spotify.read().await.get_track_analysis(&uri).await }
+                            })
+                            .await??;
+
+                        stream.emit(NewAnalysis(uri, Box::new(analysis.into())));
+                        Ok(())
+                    },
+                );
+            }
+            NewAnalysis(uri, analysis) => {
+                // Another track was requested while this one was loading
+                match self.model.track {
+                    Some((ref track_uri, ref name)) if *track_uri == uri => {
+                        self.model.title = format!("Audio analysis: {}", name);
+                    }
+                    _ => return,
+                }
+
+                self.model.analysis.replace(Some(*analysis));
+                self.canvas.queue_draw();
+            }
+            Click(x, width) => {
+                let duration_ms = match *self.model.analysis.borrow() {
+                    Some(ref analysis) if width > 0 => analysis.duration_ms,
+                    _ => return,
+                };
+
+                if let Some((uri, _)) = self.model.track.clone() {
+                    let pos = (x / width as f64).max(0.0).min(1.0) * duration_ms as f64;
+                    self.model.stream.emit(SeekTrack(uri, pos as u32));
+                }
+            }
+            SeekTrack(uri, pos) => {
+                self.spawn_args(
+                    uri,
+                    async move |pool, (stream, spotify): (EventStream<_>, SpotifyRef), uri| {
+                        pool.spawn(async move {
+                            let spotify = spotify.read().await;
+
+                            let is_playing = match spotify.get_playback_state().await? {
+                                Some(state) => matches!(state.item, Some(PlayingItem::Track(track)) if track.uri == uri),
+                                None => false,
+                            };
+
+                            if !is_playing {
+                                spotify.play_tracks(&[uri]).await?;
+                            }
+
+                            spotify.seek_track(pos).await
+                        })
+                        .await??;
+
+                        stream.emit(Seeked);
+                        Ok(())
+                    },
+                );
+            }
+            Seeked => {}
+            Close => {
+                self.revealer.set_reveal_child(false);
+            }
+        }
+    }
+
+    fn init_view(&mut self) {
+        self.canvas.add_events(gdk::EventMask::BUTTON_PRESS_MASK);
+
+        let analysis = self.model.analysis.clone();
+
+        self.canvas.connect_draw(move |area, cr| {
+            if let Some(ref analysis) = *analysis.borrow() {
+                draw_analysis(
+                    cr,
+                    analysis,
+                    area.get_allocated_width() as f64,
+                    area.get_allocated_height() as f64,
+                );
+            }
+
+            Inhibit(false)
+        });
+    }
+}
+
+/// Draws sections as alternating background stripes with key and tempo labels,
+/// loudness as a curve mirrored around the middle line, and bar and beat ticks
+/// along the bottom edge
+fn draw_analysis(cr: &cairo::Context, analysis: &TrackAnalysis, width: f64, height: f64) {
+    if analysis.duration_ms == 0 {
+        return;
+    }
+
+    let x_at = |time_ms: u32| time_ms as f64 / analysis.duration_ms as f64 * width;
+    let notation = KeyNotation::current();
+
+    cr.set_font_size(LABELS_HEIGHT * 0.7);
+
+    for (index, section) in analysis.sections.iter().enumerate() {
+        let (x, section_width) = (x_at(section.start_ms), x_at(section.duration_ms));
+        let (r, g, b) = SECTION_COLORS[index % SECTION_COLORS.len()];
+
+        cr.set_source_rgba(r, g, b, 0.25);
+        cr.rectangle(x, 0.0, section_width, height);
+        cr.fill();
+
+        cr.set_source_rgba(r, g, b, 0.9);
+        cr.set_line_width(1.0);
+        cr.move_to(x, 0.0);
+        cr.line_to(x, height);
+        cr.stroke();
+
+        let label = match section.key {
+            Some(key) => format!("{} {:.0}", key.display(notation), section.tempo),
+            None => format!("{:.0}", section.tempo),
+        };
+
+        cr.save();
+        cr.rectangle(x, 0.0, section_width, LABELS_HEIGHT);
+        cr.clip();
+        cr.set_source_rgb(0.9, 0.9, 0.9);
+        cr.move_to(x + 3.0, LABELS_HEIGHT * 0.75);
+        cr.show_text(&label);
+        cr.restore();
+    }
+
+    let curve_top = LABELS_HEIGHT;
+    let middle = (height + curve_top) / 2.0;
+    let amplitude = (height - curve_top) / 2.0;
+
+    if let Some((min, max)) = analysis.loudness_range() {
+        let range = (max - min).max(1.0);
+        let level = |loudness: f32| ((loudness - min) / range) as f64 * amplitude;
+
+        cr.move_to(0.0, middle);
+        for point in &analysis.loudness {
+            cr.line_to(x_at(point.time_ms), middle - level(point.loudness));
+        }
+        for point in analysis.loudness.iter().rev() {
+            cr.line_to(x_at(point.time_ms), middle + level(point.loudness));
+        }
+        cr.close_path();
+
+        cr.set_source_rgba(0.95, 0.6, 0.2, 0.8);
+        cr.fill();
+    }
+
+    cr.set_source_rgba(1.0, 1.0, 1.0, 0.5);
+    cr.set_line_width(1.0);
+
+    for &beat in &analysis.beats {
+        cr.move_to(x_at(beat), height - 4.0);
+        cr.line_to(x_at(beat), height);
+    }
+    cr.stroke();
+
+    for &bar in &analysis.bars {
+        cr.move_to(x_at(bar), height - 10.0);
+        cr.line_to(x_at(bar), height);
+    }
+    cr.stroke();
+}
+
+impl Extract<EventStream<AnalysisMsg>> for AnalysisView {
+    fn extract(&self) -> EventStream<AnalysisMsg> { self.model.stream.clone() }
+}
+
+impl Extract<SpotifyRef> for AnalysisView {
+    fn extract(&self) -> SpotifyRef { self.model.spotify.clone() }
+}
+
+impl Spawn for AnalysisView {
+    fn pool(&self) -> Handle { self.model.pool.clone() }
+}
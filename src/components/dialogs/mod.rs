@@ -0,0 +1,5 @@
+mod playlist_chooser;
+mod playlist_details;
+
+pub use playlist_chooser::choose_playlist;
+pub use playlist_details::edit_playlist_details;
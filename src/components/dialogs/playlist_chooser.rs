@@ -0,0 +1,87 @@
+use crate::models::playlist::PlaylistLike;
+use glib::StaticType;
+use gtk::prelude::*;
+use rspotify::model::SimplifiedPlaylist;
+
+const COL_CHOICE_URI: u32 = 0;
+const COL_CHOICE_NAME: u32 = 1;
+const COL_CHOICE_TOTAL_TRACKS: u32 = 2;
+
+pub struct PlaylistChoice {
+    pub uri: String,
+    /// Position to insert tracks at, `None` to append them
+    pub position: Option<u32>,
+}
+
+/// Runs modal dialog to pick one of the given playlists to add tracks to
+pub fn choose_playlist(parent: Option<&gtk::Window>, playlists: &[SimplifiedPlaylist]) -> Option<PlaylistChoice> {
+    let dialog = gtk::Dialog::with_buttons(
+        Some("Add to playlist"),
+        parent,
+        gtk::DialogFlags::MODAL | gtk::DialogFlags::DESTROY_WITH_PARENT,
+        &[("_Cancel", gtk::ResponseType::Cancel), ("_Add", gtk::ResponseType::Accept)],
+    );
+    dialog.set_default_size(400, 500);
+    dialog.set_default_response(gtk::ResponseType::Accept);
+
+    let store = gtk::ListStore::new(&[String::static_type(), String::static_type(), u32::static_type()]);
+
+    for playlist in playlists {
+        store.insert_with_values(None, &[COL_CHOICE_URI, COL_CHOICE_NAME, COL_CHOICE_TOTAL_TRACKS], &[
+            &playlist.uri,
+            &playlist.name,
+            &playlist.total_tracks(),
+        ]);
+    }
+
+    let view = gtk::TreeViewBuilder::new()
+        .model(&store)
+        .headers_visible(true)
+        .enable_search(true)
+        .search_column(COL_CHOICE_NAME as i32)
+        .build();
+
+    for &(title, column, expand) in &[
+        ("Playlist", COL_CHOICE_NAME, true),
+        ("Tracks", COL_CHOICE_TOTAL_TRACKS, false),
+    ] {
+        let cell = gtk::CellRendererText::new();
+        let view_column = gtk::TreeViewColumnBuilder::new().title(title).expand(expand).build();
+
+        CellLayoutExt::pack_start(&view_column, &cell, true);
+        CellLayoutExt::add_attribute(&view_column, &cell, "text", column as i32);
+        view.append_column(&view_column);
+    }
+
+    {
+        let dialog = dialog.clone();
+        view.connect_row_activated(move |_, _, _| dialog.response(gtk::ResponseType::Accept));
+    }
+
+    let scroller = gtk::ScrolledWindowBuilder::new().vexpand(true).build();
+    scroller.add(&view);
+
+    let prepend_check = gtk::CheckButton::with_label("Add to the beginning of the playlist");
+
+    let content = dialog.get_content_area();
+    content.set_spacing(10);
+    content.pack_start(&scroller, true, true, 0);
+    content.pack_start(&prepend_check, false, false, 0);
+    content.show_all();
+
+    let choice = match dialog.run() {
+        gtk::ResponseType::Accept => view
+            .get_selection()
+            .get_selected()
+            .and_then(|(model, pos)| model.get_value(&pos, COL_CHOICE_URI as i32).get::<String>().ok().flatten())
+            .map(|uri| PlaylistChoice {
+                uri,
+                position: if prepend_check.get_active() { Some(0) } else { None },
+            }),
+        _ => None,
+    };
+
+    dialog.close();
+
+    choice
+}
@@ -0,0 +1,86 @@
+use crate::models::playlist::PlaylistDetails;
+use gtk::prelude::*;
+
+/// Runs modal dialog to fill in playlist details, returns `None` if cancelled
+pub fn edit_playlist_details(parent: Option<&gtk::Window>, title: &str, details: &PlaylistDetails) -> Option<PlaylistDetails> {
+    let dialog = gtk::Dialog::with_buttons(
+        Some(title),
+        parent,
+        gtk::DialogFlags::MODAL | gtk::DialogFlags::DESTROY_WITH_PARENT,
+        &[("_Cancel", gtk::ResponseType::Cancel), ("_Save", gtk::ResponseType::Accept)],
+    );
+    dialog.set_default_size(400, -1);
+    dialog.set_default_response(gtk::ResponseType::Accept);
+
+    let grid = gtk::GridBuilder::new().row_spacing(10).column_spacing(10).margin(10).build();
+
+    let name_entry = gtk::EntryBuilder::new()
+        .text(&details.name)
+        .activates_default(true)
+        .hexpand(true)
+        .build();
+    let description_entry = gtk::EntryBuilder::new()
+        .text(&details.description)
+        .activates_default(true)
+        .hexpand(true)
+        .build();
+    let public_check = gtk::CheckButton::with_label("Public");
+    let collaborative_check = gtk::CheckButton::with_label("Collaborative");
+
+    public_check.set_active(details.public && !details.collaborative);
+    public_check.set_sensitive(!details.collaborative);
+    collaborative_check.set_active(details.collaborative);
+
+    {
+        // Collaborative playlists are always private
+        let public_check = public_check.clone();
+        collaborative_check.connect_toggled(move |check| {
+            if check.get_active() {
+                public_check.set_active(false);
+            }
+            public_check.set_sensitive(!check.get_active());
+        });
+    }
+
+    if let Some(save_btn) = dialog.get_widget_for_response(gtk::ResponseType::Accept) {
+        save_btn.set_sensitive(!details.name.trim().is_empty());
+        name_entry.connect_changed(move |entry| save_btn.set_sensitive(!entry.get_text().trim().is_empty()));
+    }
+
+    grid.attach(
+        &gtk::LabelBuilder::new().label("Name").halign(gtk::Align::End).build(),
+        0,
+        0,
+        1,
+        1,
+    );
+    grid.attach(&name_entry, 1, 0, 1, 1);
+    grid.attach(
+        &gtk::LabelBuilder::new().label("Description").halign(gtk::Align::End).build(),
+        0,
+        1,
+        1,
+        1,
+    );
+    grid.attach(&description_entry, 1, 1, 1, 1);
+    grid.attach(&public_check, 1, 2, 1, 1);
+    grid.attach(&collaborative_check, 1, 3, 1, 1);
+
+    let content = dialog.get_content_area();
+    content.pack_start(&grid, true, true, 0);
+    content.show_all();
+
+    let details = match dialog.run() {
+        gtk::ResponseType::Accept => Some(PlaylistDetails {
+            name: name_entry.get_text().trim().to_owned(),
+            description: description_entry.get_text().trim().to_owned(),
+            public: public_check.get_active(),
+            collaborative: collaborative_check.get_active(),
+        }),
+        _ => None,
+    };
+
+    dialog.close();
+
+    details
+}
@@ -22,6 +22,7 @@ use tokio::runtime::Handle;
 #[derive(Msg)]
 pub enum ContainerMsg<Loader: ContainerLoader> {
     Clear,
+    Reset,
     Load(Loader::ParentId),
     Reload,
     LoadPage(<Loader::Page as PageLike<Loader::Item>>::Offset, usize),
@@ -55,6 +56,9 @@ pub struct ContainerModel<Loader> {
     pub total_duration: u32,
     pub total_duration_exact: bool,
     pub is_loading: bool,
+    /// Snapshot left by the last change made to the shown playlist,
+    /// `None` until the list is changed from the app
+    pub snapshot_id: Option<String>,
 }
 
 impl<Loader> ContainerModel<Loader> {
@@ -76,6 +80,7 @@ impl<Loader> ContainerModel<Loader> {
             total_duration: 0,
             total_duration_exact: true,
             is_loading: false,
+            snapshot_id: None,
         }
     }
 }
@@ -168,6 +173,7 @@ impl<Loader, ItemsView, Handler, Message> ContainerList<Loader, ItemsView, Handl
         self.model.total_items = 0;
         self.model.total_duration = 0;
         self.model.total_duration_exact = true;
+        self.model.snapshot_id = None;
 
         let status_ctx = self.status_bar.get_context_id("totals");
 
@@ -279,6 +285,10 @@ where
                 Clear => {
                     self.clear_store();
                 }
+                Reset => {
+                    self.model.items_loader = None;
+                    self.clear_store();
+                }
                 Load(parent_id) => {
                     if self
                         .model
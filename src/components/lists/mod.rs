@@ -10,4 +10,4 @@ pub use artist::ArtistList;
 pub use category::CategoryList;
 pub use common::{ContainerList, ContainerMsg, GetSelectedRows, ItemsListView, MessageHandler};
 pub use playlist::PlaylistList;
-pub use track::{TrackList, TrackMsg};
+pub use track::{item_view::TARGET_TRACK_URIS, TrackList, TrackMsg};
@@ -1,18 +1,25 @@
 use crate::{
-    components::lists::{ContainerMsg, GetSelectedRows, MessageHandler, TrackList, TrackMsg},
-    loaders::ContainerLoader,
-    models::{common::*, page::*, track::*},
+    components::{
+        dialogs::{choose_playlist, edit_playlist_details},
+        lists::{ContainerMsg, GetSelectedRows, MessageHandler, TrackList, TrackMsg},
+    },
+    loaders::{ContainerLoader, PlaybackQueueId, RecommendParams, SearchQuery},
+    models::{common::*, key::*, mixing::*, page::*, playlist::PlaylistDetails, track::*},
     services::{
-        api::{PlaybackControlApi, PlaybackQueueApi, TracksStorageApi},
+        api::{PlaybackControlApi, PlaybackQueueApi, PlaylistsStorageApi, ThreadSafe, TracksStorageApi, UsersStorageApi},
         SpotifyRef,
     },
-    utils::Spawn,
+    utils::{AsyncCell, Spawn},
 };
 use async_trait::async_trait;
-use glib::{Continue, ToValue};
-use gtk::{prelude::GtkListStoreExtManual, ProgressBarExt, TreeModelExt, TreeSelectionExt, TreeViewExt};
+use glib::{Cast, Continue, ToValue};
+use gtk::{prelude::GtkListStoreExtManual, ProgressBarExt, TreeModelExt, TreeSelectionExt, TreeViewExt, WidgetExt};
 use relm::EventStream;
-use rspotify::client::ClientError;
+use rspotify::{
+    client::ClientError,
+    model::{CurrentPlaybackContext, PlayingItem, SimplifiedPlaylist},
+};
+use std::collections::{HashMap, HashSet};
 
 pub struct TrackMsgHandler;
 
@@ -41,6 +48,22 @@ where
                 let tracks = page.items();
                 let offset = page.num_offset();
 
+                // Positions of loaded tracks are pinned to the snapshot for edits
+                if offset == 0 {
+                    if let Some(playlist_uri) = this.playlist_uri() {
+                        this.spawn_args(
+                            playlist_uri,
+                            async move |pool, (stream, spotify): (EventStream<_>, SpotifyRef), playlist_uri| {
+                                let playlist = pool
+                                    .spawn(async move { spotify.read().await.get_playlist(&playlist_uri).await })
+                                    .await??;
+                                stream.emit(PlaylistSnapshotLoaded(playlist.snapshot_id, epoch));
+                                Ok(())
+                            },
+                        );
+                    }
+                }
+
                 this.progress_bar
                     .set_fraction((offset as f64 + tracks.len() as f64) / page.total() as f64);
 
@@ -83,6 +106,11 @@ where
                     this.finish_load();
                 }
             }
+            Parent(ContainerMsg::OpenContextMenu(event)) => {
+                this.items_view.show_playlist_items(this.playlist_uri().is_some());
+                this.items_view.show_queue_items(this.is_playback_queue());
+                return Some(Parent(ContainerMsg::OpenContextMenu(event)));
+            }
             event @ Parent(_) => {
                 return Some(event);
             }
@@ -112,8 +140,47 @@ where
             }
             NewTracksInfo(info, iters) => {
                 let store = &this.model.store;
-                for (idx, pos) in iters.iter().enumerate() {
-                    store.set(pos, &[COL_TRACK_BPM], &[&info[idx].tempo]);
+                // Features of unknown or unavailable tracks are missing from the response
+                let info = info.iter().map(|feats| (&*feats.uri, feats)).collect::<HashMap<_, _>>();
+
+                for pos in &iters {
+                    let feats = match store
+                        .get_value(pos, COL_TRACK_URI as i32)
+                        .get::<&str>()
+                        .ok()
+                        .flatten()
+                        .and_then(|uri| info.get(uri))
+                    {
+                        Some(feats) => feats,
+                        None => continue,
+                    };
+                    let key = Key::from_pitch(feats.key, feats.mode > 0.5).map_or(0, Key::to_code);
+
+                    store.set(
+                        pos,
+                        &[
+                            COL_TRACK_BPM,
+                            COL_TRACK_KEY,
+                            COL_TRACK_ENERGY,
+                            COL_TRACK_DANCEABILITY,
+                            COL_TRACK_VALENCE,
+                            COL_TRACK_LOUDNESS,
+                            COL_TRACK_ACOUSTICNESS,
+                            COL_TRACK_INSTRUMENTALNESS,
+                            COL_TRACK_TIME_SIGNATURE,
+                        ],
+                        &[
+                            &feats.tempo,
+                            &key,
+                            &feats.energy,
+                            &feats.danceability,
+                            &feats.valence,
+                            &feats.loudness,
+                            &feats.acousticness,
+                            &feats.instrumentalness,
+                            &(feats.time_signature.max(0) as u32),
+                        ],
+                    );
                 }
             }
             GoToTrack(track_id) => {
@@ -122,17 +189,20 @@ where
                     loop {
                         if let Ok(Some(uri)) = store.get_value(&pos, COL_TRACK_URI as i32).get::<&str>() {
                             if uri == track_id {
-                                let select = this.items_view.get_selection();
-                                select.unselect_all();
-                                select.select_iter(&pos);
-
-                                this.items_view.scroll_to_cell(
-                                    store.get_path(&pos).as_ref(),
-                                    None::<&gtk::TreeViewColumn>,
-                                    false,
-                                    0.0,
-                                    0.0,
-                                );
+                                // The track can be hidden by compatible tracks filter
+                                if let Some(pos) = this.items_view.view_iter(&pos) {
+                                    let select = this.items_view.get_selection();
+                                    select.unselect_all();
+                                    select.select_iter(&pos);
+
+                                    this.items_view.scroll_to_cell(
+                                        this.items_view.get_model().and_then(|model| model.get_path(&pos)).as_ref(),
+                                        None::<&gtk::TreeViewColumn>,
+                                        false,
+                                        0.0,
+                                        0.0,
+                                    );
+                                }
 
                                 break true;
                             }
@@ -173,7 +243,144 @@ where
                     },
                 );
             }
-            AddChosenTracks => {}
+            PlayChosenTracksNext => {
+                this.spawn_args(
+                    this.get_selected_tracks_uris(),
+                    async move |pool, spotify: SpotifyRef, uris| {
+                        pool.spawn(async move { spotify.write().await.insert_queue_tracks(0, &uris).await })
+                            .await??;
+                        Ok(())
+                    },
+                );
+            }
+            MoveChosenTracksToTop => {
+                if this.is_playback_queue() {
+                    let mut positions = this.get_selected_positions();
+                    positions.sort_unstable();
+
+                    this.spawn_args(
+                        positions,
+                        async move |pool, (stream, spotify): (EventStream<_>, SpotifyRef), positions| {
+                            pool.spawn(async move {
+                                let mut spotify = spotify.write().await;
+
+                                // Moving a track up doesn't shift the chosen tracks below it
+                                for (to, from) in positions.into_iter().enumerate() {
+                                    spotify.move_queue_track(from, to).await?;
+                                }
+
+                                Ok::<_, ClientError>(())
+                            })
+                            .await??;
+                            stream.emit(ContainerMsg::Reload.into());
+                            Ok(())
+                        },
+                    );
+                }
+            }
+            AddChosenTracks => {
+                this.spawn_args(
+                    this.get_selected_tracks_uris(),
+                    async move |pool, (stream, spotify): (EventStream<_>, SpotifyRef), uris| {
+                        let playlists = pool.spawn(load_editable_playlists(spotify)).await??;
+                        stream.emit(ChoosePlaylist(uris, playlists));
+                        Ok(())
+                    },
+                );
+            }
+            ChoosePlaylist(uris, playlists) => {
+                let window = this
+                    .root
+                    .get_toplevel()
+                    .and_then(|widget| widget.downcast::<gtk::Window>().ok());
+
+                if let Some(choice) = choose_playlist(window.as_ref(), &playlists) {
+                    this.spawn_args(
+                        (uris, choice.uri, choice.position),
+                        async move |pool, spotify: SpotifyRef, (uris, playlist_uri, position)| {
+                            pool.spawn(
+                                async move { spotify.read().await.add_playlist_tracks(&playlist_uri, &uris, position).await },
+                            )
+                            .await??;
+                            Ok(())
+                        },
+                    );
+                }
+            }
+            NewPlaylistFromChosenTracks => {
+                let uris = this.get_selected_tracks_uris();
+
+                if uris.is_empty() {
+                    return None;
+                }
+
+                let window = this
+                    .root
+                    .get_toplevel()
+                    .and_then(|widget| widget.downcast::<gtk::Window>().ok());
+
+                if let Some(details) = edit_playlist_details(window.as_ref(), "New playlist", &PlaylistDetails::default()) {
+                    this.spawn_args((uris, details), async move |pool, spotify: SpotifyRef, (uris, details)| {
+                        pool.spawn(async move {
+                            let spotify = spotify.read().await;
+                            let playlist = spotify.create_my_playlist(&details).await?;
+                            spotify.add_playlist_tracks(&playlist.uri, &uris, None).await
+                        })
+                        .await??;
+                        Ok(())
+                    });
+                }
+            }
+            RemoveChosenTracks => {
+                if let Some(playlist_uri) = this.playlist_uri() {
+                    this.spawn_args(
+                        (
+                            playlist_uri,
+                            this.get_selected_tracks_positions(),
+                            this.model.snapshot_id.clone(),
+                        ),
+                        async move |pool,
+                                    (stream, spotify): (EventStream<_>, SpotifyRef),
+                                    (playlist_uri, tracks, snapshot_id)| {
+                            pool.spawn(async move {
+                                spotify
+                                    .read()
+                                    .await
+                                    .remove_playlist_tracks(&playlist_uri, &tracks, snapshot_id.as_deref())
+                                    .await
+                            })
+                            .await??;
+                            stream.emit(ContainerMsg::Reload.into());
+                            Ok(())
+                        },
+                    );
+                } else if this.is_playback_queue() {
+                    this.spawn_args(
+                        this.get_selected_positions(),
+                        async move |pool, (stream, spotify): (EventStream<_>, SpotifyRef), positions| {
+                            pool.spawn(async move { spotify.write().await.dequeue_tracks(&positions).await })
+                                .await??;
+                            stream.emit(ContainerMsg::Reload.into());
+                            Ok(())
+                        },
+                    );
+                }
+            }
+            DedupeTracks => {
+                if let Some(playlist_uri) = this.playlist_uri() {
+                    this.spawn_args(
+                        playlist_uri,
+                        async move |pool, (stream, spotify): (EventStream<_>, SpotifyRef), playlist_uri| {
+                            let deduped = pool.spawn(dedupe_playlist(spotify, playlist_uri)).await??;
+
+                            if deduped {
+                                stream.emit(ContainerMsg::Reload.into());
+                            }
+                            Ok(())
+                        },
+                    );
+                }
+            }
             SaveChosenTracks => {
                 this.spawn_args(
                     this.get_selected_tracks_uris(),
@@ -229,8 +436,92 @@ where
                     }
                 }
             }
+            ShowChosenTrackAnalysis => {
+                let (rows, model) = this.items_view.get_selected_rows();
+
+                if let Some(pos) = rows.into_iter().filter_map(|path| model.get_iter(&path)).next() {
+                    let uri = model.get_value(&pos, COL_TRACK_URI as i32).get::<String>().ok().flatten();
+                    let name = model.get_value(&pos, COL_TRACK_NAME as i32).get::<String>().ok().flatten();
+
+                    if let (Some(uri), Some(name)) = (uri, name) {
+                        this.stream.emit(ShowAnalysis(uri, name));
+                    }
+                }
+            }
             GoToAlbum(..) => {}
             GoToArtist(..) => {}
+            ShowAnalysis(..) => {}
+            TracksReordered(..) => {
+                let order = this.old_positions();
+
+                if order.iter().enumerate().all(|(index, &old)| index as u32 == old) {
+                    return None;
+                }
+
+                let (range_start, range_length, insert_before) = match moved_block(&order) {
+                    Some(moved) => moved,
+                    None => {
+                        // Rows no longer match any single move, get the real order back
+                        warn!("tracks order doesn't match a single move, reloading");
+                        return Some(Parent(ContainerMsg::Reload));
+                    }
+                };
+
+                if let Some(playlist_uri) = this.playlist_uri() {
+                    this.renumber_tracks();
+
+                    this.spawn_args(
+                        (playlist_uri, this.model.snapshot_id.clone()),
+                        async move |pool, (stream, spotify): (EventStream<_>, SpotifyRef), (playlist_uri, snapshot_id)| {
+                            let snapshot_id = pool
+                                .spawn(async move {
+                                    spotify
+                                        .read()
+                                        .await
+                                        .reorder_playlist_tracks(
+                                            &playlist_uri,
+                                            range_start,
+                                            range_length,
+                                            insert_before,
+                                            snapshot_id.as_deref(),
+                                        )
+                                        .await
+                                })
+                                .await??;
+                            stream.emit(NewPlaylistSnapshot(snapshot_id));
+                            Ok(())
+                        },
+                    );
+                } else if this.is_playback_queue() {
+                    this.renumber_tracks();
+
+                    this.spawn_args(
+                        queue_moves(range_start, range_length, insert_before),
+                        async move |pool, spotify: SpotifyRef, moves| {
+                            pool.spawn(async move {
+                                let mut spotify = spotify.write().await;
+
+                                for (from, to) in moves {
+                                    spotify.move_queue_track(from, to).await?;
+                                }
+
+                                Ok::<_, ClientError>(())
+                            })
+                            .await??;
+                            Ok(())
+                        },
+                    );
+                }
+            }
+            NewPlaylistSnapshot(snapshot_id) => {
+                this.model.snapshot_id = Some(snapshot_id);
+            }
+            PlaylistSnapshotLoaded(snapshot_id, epoch) => {
+                // Snapshot of an edit made meanwhile is newer than the loaded one
+                if epoch == this.current_epoch() && this.model.snapshot_id.is_none() {
+                    this.model.snapshot_id = Some(snapshot_id);
+                }
+            }
             PlayTracks(uris) => {
                 if let Some(ref loader) = this.model.items_loader {
                     this.spawn_args(
@@ -246,19 +537,223 @@ where
             PlayingNewTrack => {}
             NewBpm(path, bpm) => {
                 let store = &this.model.store;
-                if let Some(iter) = store.get_iter(&path) {
+                if let Some(iter) = this.items_view.store_path(&path).and_then(|path| store.get_iter(&path)) {
                     store.set_value(&iter, COL_TRACK_BPM, &bpm.to_value());
                 }
             }
+            FilterCompatible(true) => {
+                this.spawn(async move |pool, (stream, spotify): (EventStream<_>, SpotifyRef)| {
+                    let feats = pool
+                        .spawn(async move {
+                            let spotify = spotify.read().await;
+                            let uri = match spotify.get_playback_state().await? {
+                                Some(CurrentPlaybackContext {
+                                    item: Some(PlayingItem::Track(track)),
+                                    ..
+                                }) => track.uri,
+                                _ => return Ok(None),
+                            };
+
+                            Ok::<_, ClientError>(spotify.get_tracks_features(&[uri]).await?.into_iter().next())
+                        })
+                        .await??;
+
+                    stream.emit(NewMixTarget(feats.as_ref().map(Harmony::from_features)));
+                    Ok(())
+                });
+            }
+            FilterCompatible(false) => {
+                this.items_view.set_mix_target(None);
+            }
+            NewMixTarget(target) => {
+                this.items_view.set_mix_target(target);
+            }
         }
 
         None
     }
 }
 
+impl<Loader> TrackList<Loader>
+where
+    Loader: ContainerLoader,
+    Loader::ParentId: PlayTracksContext,
+{
+    /// Uri of the playlist shown in the list, if it's a playlist
+    fn playlist_uri(&self) -> Option<String> {
+        self.model
+            .items_loader
+            .as_ref()
+            .and_then(|loader| loader.parent_id().playlist_uri())
+            .map(String::from)
+    }
+
+    /// Whether the list shows the app playback queue
+    fn is_playback_queue(&self) -> bool {
+        self.model
+            .items_loader
+            .as_ref()
+            .map_or(false, |loader| loader.parent_id().is_playback_queue())
+    }
+
+    /// Positions tracks had before they were reordered, in the current rows
+    /// order, as track numbers still hold the old positions
+    fn old_positions(&self) -> Vec<u32> {
+        let store = &self.model.store;
+        let mut order = Vec::with_capacity(store.iter_n_children(None) as usize);
+
+        store.foreach(|store, _, pos| {
+            if let Ok(Some(number)) = store.get_value(pos, COL_TRACK_NUMBER as i32).get::<u32>() {
+                order.push(number.saturating_sub(1));
+            }
+            false
+        });
+
+        order
+    }
+
+    /// Updates track numbers and timeline to match current rows order
+    fn renumber_tracks(&self) {
+        let mut timeline = 0;
+
+        self.model.store.foreach(|store, path, pos| {
+            let index = path.get_indices().first().copied().unwrap_or(0) as u32;
+            let duration = store
+                .get_value(pos, COL_TRACK_DURATION_MS as i32)
+                .get::<u32>()
+                .ok()
+                .flatten()
+                .unwrap_or(0);
+
+            self.model.store.set(pos, &[COL_TRACK_NUMBER, COL_TRACK_TIMELINE], &[
+                &(index + 1),
+                &crate::utils::humanize_time(timeline),
+            ]);
+
+            timeline += duration;
+            false
+        });
+    }
+}
+
+/// Finds a single block of tracks moved to another place given old track
+/// positions in new order, returns `(range_start, range_length, insert_before)`
+/// as expected by reorder request, or `None` if the order is not a single move
+fn moved_block(order: &[u32]) -> Option<(u32, u32, u32)> {
+    let first = order.iter().enumerate().position(|(index, &old)| index as u32 != old)?;
+    let last = order.iter().enumerate().rposition(|(index, &old)| index as u32 != old)?;
+    let changed = &order[first..=last];
+
+    // Changed positions must hold the end of the old range followed by its start
+    let split = changed.iter().position(|&old| old == first as u32)?;
+    let (head, tail) = changed.split_at(split);
+    let is_run = |run: &[u32], start: u32| run.iter().enumerate().all(|(offset, &old)| old == start + offset as u32);
+
+    if !is_run(tail, first as u32) || !is_run(head, first as u32 + tail.len() as u32) {
+        return None;
+    }
+
+    // Either part can be seen as the moved one, the shorter is the one dragged
+    if head.len() <= tail.len() {
+        Some((head[0], head.len() as u32, first as u32))
+    } else {
+        Some((first as u32, tail.len() as u32, last as u32 + 1))
+    }
+}
+
+/// Single track moves as `(from, to)` queue positions doing a block move
+fn queue_moves(range_start: u32, range_length: u32, insert_before: u32) -> Vec<(usize, usize)> {
+    let (start, length, before) = (range_start as usize, range_length as usize, insert_before as usize);
+
+    if before < start {
+        (0..length).map(|offset| (start + offset, before + offset)).collect()
+    } else {
+        (0..length).map(|_| (start, before - 1)).collect()
+    }
+}
+
+/// Loads all playlists the user can add tracks to: own and collaborative ones
+async fn load_editable_playlists(spotify: SpotifyRef) -> Result<Vec<SimplifiedPlaylist>, ClientError> {
+    let spotify = spotify.read().await;
+    let me = spotify.get_my_profile().await?;
+    let mut playlists = Vec::new();
+    let mut offset = 0;
+
+    loop {
+        let page = spotify.get_my_playlists(offset, 50).await?;
+        let has_next = page.next.is_some();
+
+        offset += page.items.len() as u32;
+        playlists.extend(
+            page.items
+                .into_iter()
+                .filter(|playlist| playlist.collaborative || playlist.owner.id == me.id),
+        );
+
+        if !has_next {
+            break Ok(playlists);
+        }
+    }
+}
+
+/// Removes repetitions of tracks from a playlist, keeping their first
+/// occurrences and the other items untouched, returns `false` if there
+/// were no duplicates
+async fn dedupe_playlist<Client>(spotify: AsyncCell<Client>, uri: String) -> Result<bool, ClientError>
+where
+    Client: PlaylistsStorageApi + TracksStorageApi + ThreadSafe,
+{
+    let spotify = spotify.read().await;
+    let snapshot_id = spotify.get_playlist(&uri).await?.snapshot_id;
+    let mut seen = HashSet::new();
+    let mut duplicates = Vec::new();
+    let mut offset = 0;
+
+    loop {
+        let page = spotify.get_playlist_tracks(&uri, offset, 100).await?;
+        let has_next = page.next.is_some();
+        let page_offset = offset;
+
+        offset += page.items.len() as u32;
+
+        for (index, item) in page.items.into_iter().enumerate() {
+            if let Some(track) = item.track {
+                if !seen.insert(track.uri.clone()) {
+                    duplicates.push((track.uri, page_offset + index as u32));
+                }
+            }
+        }
+
+        if !has_next {
+            break;
+        }
+    }
+
+    if duplicates.is_empty() {
+        return Ok(false);
+    }
+
+    spotify.remove_playlist_tracks(&uri, &duplicates, Some(&snapshot_id)).await?;
+    Ok(true)
+}
+
 #[async_trait]
 pub trait PlayTracksContext {
     async fn play_tracks(self, spotify: SpotifyRef, uris: Vec<String>) -> Result<(), ClientError>;
+
+    /// Uri of the playlist this context is, to edit it
+    fn playlist_uri(&self) -> Option<&str> { None }
+
+    fn is_playback_queue(&self) -> bool { false }
+}
+
+#[async_trait]
+impl PlayTracksContext for PlaybackQueueId {
+    async fn play_tracks(self, spotify: SpotifyRef, uris: Vec<String>) -> Result<(), ClientError> {
+        spotify.read().await.play_tracks(&uris).await
+    }
+
+    fn is_playback_queue(&self) -> bool { true }
 }
 
 #[async_trait]
@@ -269,18 +764,19 @@ impl PlayTracksContext for () {
     }
 }
 
-/*
 #[async_trait]
-impl<K, V> PlayTracksContext for Map<K, V> {
-    async fn play_tracks(
-        self,
-        spotify: SpotifyRef,
-        uris: Vec<String>,
-    ) {
-        spotify.play_tracks(uris).await;
+impl PlayTracksContext for RecommendParams {
+    async fn play_tracks(self, spotify: SpotifyRef, uris: Vec<String>) -> Result<(), ClientError> {
+        spotify.read().await.play_tracks(&uris).await
+    }
+}
+
+#[async_trait]
+impl PlayTracksContext for SearchQuery {
+    async fn play_tracks(self, spotify: SpotifyRef, uris: Vec<String>) -> Result<(), ClientError> {
+        spotify.read().await.play_tracks(&uris).await
     }
 }
- */
 
 #[async_trait]
 impl PlayTracksContext for String {
@@ -294,4 +790,109 @@ impl PlayTracksContext for String {
 
         spotify.read().await.play_context(self, start_uri).await
     }
+
+    fn playlist_uri(&self) -> Option<&str> {
+        if self.starts_with("spotify:playlist:") {
+            Some(self)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::FakeSpotify;
+    use std::sync::Arc;
+    use tokio::sync::RwLock;
+
+    const PLAYLIST: &str = "spotify:playlist:0playlist00000000000001";
+
+    #[tokio::test]
+    async fn dedupe_removes_only_repeated_items() {
+        let spotify = Arc::new(RwLock::new(FakeSpotify::library()));
+
+        assert!(dedupe_playlist(spotify.clone(), PLAYLIST.to_owned()).await.unwrap());
+
+        let client = spotify.read().await;
+        let fixture = client.fixture();
+        let items = &fixture.playlists[0].tracks.items;
+
+        assert_eq!(items.iter().map(|item| item.uri()).collect::<Vec<_>>(), vec![
+            "spotify:track:0track00000000000000001",
+            "spotify:track:0track00000000000000002",
+            "spotify:track:0track00000000000000003",
+        ]);
+        // Kept items are not re-added
+        assert!(items.iter().all(|item| item.added_at.is_some()));
+    }
+
+    #[tokio::test]
+    async fn dedupe_without_duplicates_changes_nothing() {
+        let spotify = Arc::new(RwLock::new(FakeSpotify::library()));
+
+        assert!(dedupe_playlist(spotify.clone(), PLAYLIST.to_owned()).await.unwrap());
+        assert!(!dedupe_playlist(spotify.clone(), PLAYLIST.to_owned()).await.unwrap());
+    }
+
+    #[test]
+    fn track_moved_down_is_found() {
+        // The first track is dropped after the third one
+        assert_eq!(moved_block(&[1, 2, 0, 3]), Some((0, 1, 3)));
+    }
+
+    #[test]
+    fn track_moved_up_is_found() {
+        // The last track is dropped before the second one
+        assert_eq!(moved_block(&[0, 3, 1, 2]), Some((3, 1, 1)));
+    }
+
+    #[test]
+    fn adjacent_tracks_swap_is_a_move() {
+        assert_eq!(moved_block(&[1, 0, 2]), Some((1, 1, 0)));
+    }
+
+    #[test]
+    fn block_of_tracks_moved_is_found() {
+        // The last two tracks are moved to the top
+        assert_eq!(moved_block(&[3, 4, 0, 1, 2]), Some((3, 2, 0)));
+        // The first two tracks are moved to the end
+        assert_eq!(moved_block(&[2, 3, 4, 0, 1]), Some((0, 2, 5)));
+    }
+
+    #[test]
+    fn unchanged_order_is_not_a_move() {
+        assert_eq!(moved_block(&[0, 1, 2]), None);
+        assert_eq!(moved_block(&[]), None);
+    }
+
+    #[test]
+    fn other_permutations_are_not_a_move() {
+        // Sorted by some other column
+        assert_eq!(moved_block(&[2, 0, 3, 1]), None);
+        assert_eq!(moved_block(&[2, 1, 0]), None);
+        assert_eq!(moved_block(&[1, 0, 3, 2]), None);
+        // Track numbers out of the list
+        assert_eq!(moved_block(&[0, 5, 1]), None);
+    }
+
+    #[test]
+    fn queue_block_moves_keep_tracks_order() {
+        let apply = |moves: Vec<(usize, usize)>| {
+            let mut queue = vec![0, 1, 2, 3, 4];
+
+            for (from, to) in moves {
+                let track = queue.remove(from);
+                queue.insert(to, track);
+            }
+
+            queue
+        };
+
+        assert_eq!(apply(queue_moves(3, 2, 0)), vec![3, 4, 0, 1, 2]);
+        assert_eq!(apply(queue_moves(0, 2, 5)), vec![2, 3, 4, 0, 1]);
+        assert_eq!(apply(queue_moves(0, 1, 3)), vec![1, 2, 0, 3, 4]);
+        assert_eq!(apply(queue_moves(3, 1, 1)), vec![0, 3, 1, 2, 4]);
+    }
 }
@@ -1,36 +1,136 @@
 use crate::{
     components::lists::{common::SetupViewSearch, ContainerMsg, GetSelectedRows, ItemsListView, TrackMsg},
     loaders::{ContainerLoader, ImageConverter},
-    models::{common::*, track::*},
+    models::{common::*, key::*, mixing::*, track::*},
 };
 use glib::{signal::Inhibit, Cast, IsA, ObjectExt};
 use gtk::{
-    CellLayoutExt, CellRendererExt, CellRendererPixbufExt, CellRendererTextExt, GtkMenuItemExt, MenuShellExt, TreeModelExt,
-    TreeSelectionExt, TreeViewColumn, TreeViewExt, WidgetExt,
+    CellLayoutExt, CellRendererExt, CellRendererPixbufExt, CellRendererTextExt, CheckMenuItemExt, DragContextExtManual,
+    GtkMenuExt, GtkMenuItemExt, MenuShellExt, TreeModelExt, TreeModelFilterExt, TreeModelSortExt, TreeSelectionExt,
+    TreeSortableExtManual, TreeViewColumn, TreeViewExt, WidgetExt,
 };
 use relm::EventStream;
-use std::ops::Deref;
+use std::{cell::Cell, cmp::Ordering, ops::Deref, rc::Rc};
+
+/// Drag target id for tracks dragged out of the list as uris
+pub const TARGET_TRACK_URIS: u32 = 1;
 
 const THUMB_SIZE: i32 = 32;
 
-pub struct TrackView(gtk::TreeView);
+pub struct TrackView {
+    view: gtk::TreeView,
+    store: gtk::TreeModel,
+    mix_filter: gtk::TreeModelFilter,
+    mix_sort: gtk::TreeModelSort,
+    mix_filter_item: gtk::CheckMenuItem,
+    mix_target: Rc<Cell<Option<Harmony>>>,
+    remove_item: gtk::MenuItem,
+    dedupe_item: gtk::MenuItem,
+    play_next_item: gtk::MenuItem,
+    dequeue_item: gtk::MenuItem,
+    queue_top_item: gtk::MenuItem,
+}
 
 impl Deref for TrackView {
     type Target = gtk::TreeView;
 
-    fn deref(&self) -> &Self::Target { &self.0 }
-}
-
-impl From<gtk::TreeView> for TrackView {
-    fn from(view: gtk::TreeView) -> Self { TrackView(view) }
+    fn deref(&self) -> &Self::Target { &self.view }
 }
 
 impl AsRef<gtk::Widget> for TrackView {
-    fn as_ref(&self) -> &gtk::Widget { self.0.upcast_ref() }
+    fn as_ref(&self) -> &gtk::Widget { self.view.upcast_ref() }
 }
 
 impl GetSelectedRows for TrackView {
-    fn get_selected_rows(&self) -> (Vec<gtk::TreePath>, gtk::TreeModel) { self.0.get_selected_rows() }
+    fn get_selected_rows(&self) -> (Vec<gtk::TreePath>, gtk::TreeModel) { self.view.get_selected_rows() }
+}
+
+impl TrackView {
+    /// Shows only tracks compatible with the given one, or all tracks if `None`
+    pub fn set_mix_target(&self, target: Option<Harmony>) {
+        self.mix_target.set(target);
+
+        if target.is_some() {
+            self.mix_filter.refilter();
+            // Setting the sort func again resorts rows scored for the previous target
+            sort_by_score(&self.mix_sort, self.mix_target.clone());
+            self.view.set_model(Some(&self.mix_sort));
+        } else {
+            self.view.set_model(Some(&self.store));
+            self.mix_filter_item.set_active(false);
+        }
+    }
+
+    pub fn is_mix_filtered(&self) -> bool { self.mix_target.get().is_some() }
+
+    /// Playlist editing menu items make sense only for playlists
+    pub fn show_playlist_items(&self, visible: bool) {
+        self.remove_item.set_visible(visible);
+        self.dedupe_item.set_visible(visible);
+    }
+
+    /// Queue editing menu items replace "Play next" in the queue itself
+    pub fn show_queue_items(&self, visible: bool) {
+        self.play_next_item.set_visible(!visible);
+        self.dequeue_item.set_visible(visible);
+        self.queue_top_item.set_visible(visible);
+    }
+
+    /// Converts store iterator into the one of the currently shown model
+    pub fn view_iter(&self, pos: &gtk::TreeIter) -> Option<gtk::TreeIter> {
+        if self.is_mix_filtered() {
+            self.mix_filter
+                .convert_child_iter_to_iter(pos)
+                .and_then(|pos| self.mix_sort.convert_child_iter_to_iter(&pos))
+        } else {
+            Some(pos.clone())
+        }
+    }
+
+    /// Converts path in the currently shown model into the store path
+    pub fn store_path(&self, path: &gtk::TreePath) -> Option<gtk::TreePath> {
+        if self.is_mix_filtered() {
+            self.mix_sort
+                .convert_path_to_child_path(path)
+                .and_then(|path| self.mix_filter.convert_path_to_child_path(&path))
+        } else {
+            Some(path.clone())
+        }
+    }
+}
+
+/// Compatibility of a listed track with the mix target
+fn row_compatibility(target: &Harmony, model: &gtk::TreeModel, pos: &gtk::TreeIter) -> Option<Compatibility> {
+    let candidate = Harmony::new(
+        model
+            .get_value(pos, COL_TRACK_KEY as i32)
+            .get::<u32>()
+            .ok()
+            .flatten()
+            .and_then(Key::from_code),
+        model
+            .get_value(pos, COL_TRACK_BPM as i32)
+            .get::<f32>()
+            .ok()
+            .flatten()
+            .unwrap_or(0.0),
+    );
+
+    Compatibility::between(target, &candidate, tempo_tolerance())
+}
+
+/// Ranks the best matches for the mix target first
+fn sort_by_score(sort: &gtk::TreeModelSort, mix_target: Rc<Cell<Option<Harmony>>>) {
+    sort.set_default_sort_func(move |model, a, b| {
+        let score = |pos| {
+            mix_target
+                .get()
+                .and_then(|target| row_compatibility(&target, model, pos))
+                .map_or(0.0, |compat| compat.score())
+        };
+
+        score(b).partial_cmp(&score(a)).unwrap_or(Ordering::Equal)
+    });
 }
 
 impl<Loader> ItemsListView<Loader, TrackMsg<Loader>> for TrackView
@@ -210,6 +310,44 @@ where
             });
         }
 
+        if !missing_columns.contains(&COL_TRACK_KEY) {
+            items_view.append_column(&{
+                let text_cell = gtk::CellRendererText::new();
+
+                text_cell.set_alignment(1.0, 0.5);
+
+                let column = base_column
+                    .clone()
+                    .expand(false)
+                    .title("Key")
+                    .sort_column_id(COL_TRACK_KEY as i32)
+                    .build();
+
+                column.pack_start(&text_cell, true);
+
+                gtk::TreeViewColumnExt::set_cell_data_func(
+                    &column,
+                    &text_cell,
+                    Some(Box::new(|_layout, cell, model, iter| {
+                        let key = model
+                            .get_value(iter, COL_TRACK_KEY as i32)
+                            .get::<u32>()
+                            .ok()
+                            .flatten()
+                            .and_then(Key::from_code);
+
+                        let _ = cell.set_property(
+                            "text",
+                            &key.map(|key| key.display(KeyNotation::current()).to_string())
+                                .unwrap_or_default(),
+                        );
+                    })),
+                );
+
+                column
+            });
+        }
+
         if !missing_columns.contains(&COL_TRACK_RATE) {
             let column_index = items_view.append_column(&{
                 let text_cell = gtk::CellRendererText::new();
@@ -336,12 +474,62 @@ where
             });
         }
 
+        macro_rules! feature_columns {
+            ($($column:ident => $title:literal, $render:expr;)+) => {
+                $(if !missing_columns.contains(&$column) {
+                    items_view.append_column(&feature_column(&base_column, $title, $column, $render));
+                })+
+            };
+        }
+
+        feature_columns! {
+            COL_TRACK_ENERGY => "Energy", |value| (value, format!("{:.0}%", value * 100.0));
+            COL_TRACK_DANCEABILITY => "Dance", |value| (value, format!("{:.0}%", value * 100.0));
+            COL_TRACK_VALENCE => "Valence", |value| (value, format!("{:.0}%", value * 100.0));
+            COL_TRACK_ACOUSTICNESS => "Acoustic", |value| (value, format!("{:.0}%", value * 100.0));
+            COL_TRACK_INSTRUMENTALNESS => "Instrumental", |value| (value, format!("{:.0}%", value * 100.0));
+            COL_TRACK_LOUDNESS => "Loudness", |value| if value == 0.0 {
+                (0.0, String::new())
+            } else {
+                ((value + 60.0) / 60.0, format!("{:.1} dB", value))
+            };
+            COL_TRACK_TIME_SIGNATURE => "Time", |value| if value == 0.0 {
+                (0.0, String::new())
+            } else {
+                (value / 7.0, format!("{}/4", value))
+            };
+        }
+
+        let columns_menu = gtk::Menu::new();
+
+        for column in items_view.get_columns() {
+            let title = match gtk::TreeViewColumnExt::get_title(&column) {
+                Some(title) if !title.is_empty() => title,
+                _ => continue,
+            };
+
+            let item = gtk::CheckMenuItem::with_label(&title);
+
+            item.set_active(gtk::TreeViewColumnExt::get_visible(&column));
+            item.connect_toggled(move |item| gtk::TreeViewColumnExt::set_visible(&column, item.get_active()));
+
+            columns_menu.append(&item);
+        }
+
+        columns_menu.show_all();
+
         {
             let stream = stream.clone();
 
-            items_view.connect_button_press_event(move |_, event| {
+            items_view.connect_button_press_event(move |view, event| {
                 if event.get_button() == 3 {
-                    stream.emit(TrackMsg::Parent(ContainerMsg::OpenContextMenu(event.clone())));
+                    // Right click on the column headers
+                    if event.get_window() != view.get_bin_window() {
+                        columns_menu.popup_at_pointer(Some(event));
+                    } else {
+                        stream.emit(TrackMsg::Parent(ContainerMsg::OpenContextMenu(event.clone())));
+                    }
+
                     Inhibit(true)
                 } else {
                     Inhibit(false)
@@ -363,11 +551,165 @@ where
             });
         }
 
-        TrackView(items_view)
+        // Besides reordering rows, selected tracks can be dragged out to other views as
+        // uris
+        items_view.enable_model_drag_source(
+            gdk::ModifierType::BUTTON1_MASK,
+            &[
+                gtk::TargetEntry::new("GTK_TREE_MODEL_ROW", gtk::TargetFlags::SAME_WIDGET, 0),
+                gtk::TargetEntry::new("text/uri-list", gtk::TargetFlags::SAME_APP, TARGET_TRACK_URIS),
+            ],
+            gdk::DragAction::MOVE | gdk::DragAction::COPY,
+        );
+
+        items_view.connect_drag_data_get(|tree, _, data, info, _| {
+            if info != TARGET_TRACK_URIS {
+                return;
+            }
+
+            let (rows, store) = tree.get_selection().get_selected_rows();
+            let uris = rows
+                .into_iter()
+                .filter_map(|path| store.get_iter(&path))
+                .filter_map(|pos| store.get_value(&pos, COL_TRACK_URI as i32).get::<String>().ok().flatten())
+                .collect::<Vec<_>>();
+
+            data.set_uris(&uris.iter().map(String::as_str).collect::<Vec<_>>());
+        });
+
+        // Set when a row is dropped inside the view, other drags (cancelled
+        // ones or the ones to other views) don't change rows order
+        let row_dropped = Rc::new(Cell::new(false));
+
+        {
+            let row_dropped = row_dropped.clone();
+
+            items_view.connect_drag_data_received(move |tree, context, _, _, _, info, time| {
+                if info == TARGET_TRACK_URIS {
+                    return;
+                }
+
+                // Sorted or filtered rows don't follow track numbers, so a drop there
+                // can't be mapped to a move in the list
+                let is_sorted = tree.get_model().map_or(true, |model| {
+                    model.downcast_ref::<gtk::ListStore>().map_or(true, |store| {
+                        matches!(store.get_sort_column_id(), Some((gtk::SortColumn::Index(_), _)))
+                    })
+                });
+
+                if is_sorted {
+                    context.drag_finish(false, false, time);
+                    tree.stop_signal_emission("drag-data-received");
+                } else {
+                    row_dropped.set(true);
+                }
+            });
+        }
+
+        {
+            let stream = stream.clone();
+
+            // Rows are moved by the view itself, so just report the new order
+            items_view.connect_drag_end(move |tree, _| {
+                if !row_dropped.replace(false) {
+                    return;
+                }
+
+                if let Some(store) = tree.get_model() {
+                    let mut uris = Vec::new();
+
+                    store.foreach(|store, _, pos| {
+                        if let Ok(Some(uri)) = store.get_value(pos, COL_TRACK_URI as i32).get::<String>() {
+                            uris.push(uri);
+                        }
+                        false
+                    });
+
+                    stream.emit(TrackMsg::TracksReordered(uris));
+                }
+            });
+        }
+
+        let mix_target = Rc::new(Cell::new(None::<Harmony>));
+        let mix_filter = gtk::TreeModelFilter::new(store, None);
+
+        {
+            let mix_target = mix_target.clone();
+
+            mix_filter.set_visible_func(move |model, pos| {
+                mix_target
+                    .get()
+                    .map_or(true, |target| row_compatibility(&target, model, pos).is_some())
+            });
+        }
+
+        let mix_sort = gtk::TreeModelSort::new(&mix_filter);
+        sort_by_score(&mix_sort, mix_target.clone());
+        mix_sort.set_sort_column_id(gtk::SortColumn::Default, gtk::SortType::Ascending);
+
+        let mix_filter_item = gtk::CheckMenuItem::with_label("Only compatible with playing");
+
+        if missing_columns.contains(&COL_TRACK_KEY) || missing_columns.contains(&COL_TRACK_BPM) {
+            mix_filter_item.set_sensitive(false);
+        }
+
+        {
+            let stream = stream.clone();
+
+            mix_filter_item.connect_toggled(move |item| {
+                stream.emit(TrackMsg::FilterCompatible(item.get_active()));
+            });
+        }
+
+        let remove_item = gtk::MenuItem::with_label("Remove from playlist");
+        let dedupe_item = gtk::MenuItem::with_label("Remove duplicates from playlist");
+
+        {
+            let stream = stream.clone();
+            remove_item.connect_activate(move |_| stream.emit(TrackMsg::RemoveChosenTracks));
+        }
+
+        {
+            let stream = stream.clone();
+            dedupe_item.connect_activate(move |_| stream.emit(TrackMsg::DedupeTracks));
+        }
+
+        let play_next_item = gtk::MenuItem::with_label("Play next");
+        let dequeue_item = gtk::MenuItem::with_label("Remove from queue");
+        let queue_top_item = gtk::MenuItem::with_label("Move to top of queue");
+
+        {
+            let stream = stream.clone();
+            play_next_item.connect_activate(move |_| stream.emit(TrackMsg::PlayChosenTracksNext));
+        }
+
+        {
+            let stream = stream.clone();
+            dequeue_item.connect_activate(move |_| stream.emit(TrackMsg::RemoveChosenTracks));
+        }
+
+        {
+            let stream = stream.clone();
+            queue_top_item.connect_activate(move |_| stream.emit(TrackMsg::MoveChosenTracksToTop));
+        }
+
+        TrackView {
+            view: items_view,
+            store: store.upcast_ref::<gtk::TreeModel>().clone(),
+            mix_filter,
+            mix_sort,
+            mix_filter_item,
+            mix_target,
+            remove_item,
+            dedupe_item,
+            play_next_item,
+            dequeue_item,
+            queue_top_item,
+        }
     }
 
     fn setup_search(&self, entry: &gtk::Entry) -> bool {
-        self.0.setup_search(COL_TRACK_NAME, Some(entry));
+        self.view.setup_search(COL_TRACK_NAME, Some(entry));
         true
     }
 
@@ -389,20 +731,31 @@ where
             (@ $stream:ident, (===)) => {
                 gtk::SeparatorMenuItem::new()
             };
+            (@ $stream:ident, (@$item:ident)) => {
+                self.$item.clone()
+            };
         }
 
         menu! {context_menu, stream,
             ("Play now" => PlayChosenTracks),
             ("Add to queue" => EnqueueChosenTracks),
+            (@play_next_item),
             ("Add to library" => SaveChosenTracks),
             ("Add to playlist…" => AddChosenTracks),
+            ("New playlist from selection…" => NewPlaylistFromChosenTracks),
             (===),
             ("Go to album" => GoToChosenTrackAlbum),
             ("Go to artist" => GoToChosenTrackArtist),
             ("Recommend similar" => RecommendTracks),
+            ("Audio analysis" => ShowChosenTrackAnalysis),
+            (===),
+            (@mix_filter_item),
             (===),
-            ("Remove from library" => UnsaveChosenTracks)
-            //("Remove from playlist" => RemoveChosenTracks)
+            ("Remove from library" => UnsaveChosenTracks),
+            (@remove_item),
+            (@dedupe_item),
+            (@dequeue_item),
+            (@queue_top_item)
         };
 
         context_menu
@@ -410,3 +763,45 @@ where
 
     fn thumb_converter(&self) -> ImageConverter { ImageConverter::new(THUMB_SIZE, false) }
 }
+
+/// Optional audio feature column rendered as a bar, hidden by default,
+/// `render` maps stored value to bar fraction (0.0..=1.0) and label
+fn feature_column(
+    base_column: &gtk::TreeViewColumnBuilder,
+    title: &str,
+    column: u32,
+    render: fn(f32) -> (f32, String),
+) -> gtk::TreeViewColumn {
+    let bar_cell = gtk::CellRendererProgressBuilder::new().width(60).build();
+
+    let view_column = base_column
+        .clone()
+        .expand(false)
+        .visible(false)
+        .title(title)
+        .sort_column_id(column as i32)
+        .build();
+
+    view_column.pack_start(&bar_cell, true);
+
+    gtk::TreeViewColumnExt::set_cell_data_func(
+        &view_column,
+        &bar_cell,
+        Some(Box::new(move |_layout, cell, model, iter| {
+            let value = model.get_value(iter, column as i32);
+            let value = value
+                .get::<f32>()
+                .ok()
+                .flatten()
+                .or_else(|| value.get::<u32>().ok().flatten().map(|value| value as f32))
+                .unwrap_or(0.0);
+
+            let (fraction, text) = render(value);
+
+            let _ = cell.set_property("value", &((fraction.max(0.0).min(1.0) * 100.0) as i32));
+            let _ = cell.set_property("text", &text);
+        })),
+    );
+
+    view_column
+}
@@ -1,6 +1,6 @@
-use crate::{components::lists::common::ContainerMsg, loaders::ContainerLoader};
+use crate::{components::lists::common::ContainerMsg, loaders::ContainerLoader, models::mixing::Harmony};
 use relm_derive::Msg;
-use rspotify::model::AudioFeatures;
+use rspotify::model::{AudioFeatures, SimplifiedPlaylist};
 use std::convert::TryFrom;
 
 #[derive(Msg)]
@@ -14,6 +14,11 @@ pub enum TrackMsg<Loader: ContainerLoader> {
     NewTracksInfo(Vec<AudioFeatures>, Vec<gtk::TreeIter>),
     NewTracksSaved(Vec<bool>, Vec<gtk::TreeIter>),
     NewBpm(gtk::TreePath, f32),
+    FilterCompatible(bool),
+    NewMixTarget(Option<Harmony>),
+    TracksReordered(Vec<String>),
+    NewPlaylistSnapshot(String),
+    PlaylistSnapshotLoaded(String, usize),
 
     PlayChosenTracks,
     GoToTrack(String),
@@ -22,9 +27,17 @@ pub enum TrackMsg<Loader: ContainerLoader> {
     GoToChosenTrackArtist,
     GoToArtist(String, String),
     EnqueueChosenTracks,
+    PlayChosenTracksNext,
+    MoveChosenTracksToTop,
     AddChosenTracks,
+    ChoosePlaylist(Vec<String>, Vec<SimplifiedPlaylist>),
+    NewPlaylistFromChosenTracks,
+    RemoveChosenTracks,
+    DedupeTracks,
     SaveChosenTracks,
     RecommendTracks,
+    ShowChosenTrackAnalysis,
+    ShowAnalysis(String, String),
     UnsaveChosenTracks,
 }
 
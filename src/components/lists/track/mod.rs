@@ -24,4 +24,30 @@ impl<Loader: ContainerLoader> TrackList<Loader> {
             .filter_map(|pos| model.get_value(&pos, COL_TRACK_URI as i32).get::<String>().ok().flatten())
             .collect::<Vec<_>>()
     }
+
+    /// Positions of selected tracks in the whole list, even if it's filtered
+    fn get_selected_positions(&self) -> Vec<usize> {
+        let (rows, _) = self.items_view.get_selected_rows();
+
+        rows.into_iter()
+            .filter_map(|path| self.items_view.store_path(&path))
+            .filter_map(|path| path.get_indices().first().map(|&index| index as usize))
+            .collect::<Vec<_>>()
+    }
+
+    /// Selected tracks as `(uri, position)` pairs, positions are in the whole
+    /// list
+    fn get_selected_tracks_positions(&self) -> Vec<(String, u32)> {
+        let (rows, model) = self.items_view.get_selected_rows();
+
+        rows.into_iter()
+            .filter_map(|path| {
+                let uri = model
+                    .get_iter(&path)
+                    .and_then(|pos| model.get_value(&pos, COL_TRACK_URI as i32).get::<String>().ok().flatten())?;
+                let position = *self.items_view.store_path(&path)?.get_indices().first()?;
+                Some((uri, position as u32))
+            })
+            .collect::<Vec<_>>()
+    }
 }
@@ -28,6 +28,7 @@ use crate::{
     config::SettingsRef,
     loaders::{ImageData, ImageLoader},
     models::{common::*, TrackLike},
+    observe,
     services::{
         api::{
             AlbumsStorageApi, ArtistsStorageApi, LibraryStorageApi, PlaybackControlApi, PlaylistsStorageApi, ShowsStorageApi,
@@ -36,12 +37,13 @@ use crate::{
         SpotifyRef,
     },
     utils::{Extract, Spawn},
+    AppEvent,
 };
 use gdk_pixbuf::Pixbuf;
 use gtk::{prelude::*, ButtonBoxExt, GridExt, ImageExt, RangeExt, RevealerExt, ScaleExt, WidgetExt};
 use itertools::Itertools;
 use notify_rust::Notification;
-use relm::{EventStream, Relm, Widget};
+use relm::{Channel, EventStream, Relm, Widget};
 use relm_derive::{widget, Msg};
 use rspotify::{
     client::ClientError,
@@ -77,6 +79,8 @@ pub enum MediaControlsMsg {
     ClickTrackUri(Option<String>),
     GoToTrack(Type, String, Option<(String, String)>),
     ShowInfo(bool),
+    ShowCurrentAnalysis,
+    ShowAnalysis(String, String),
     SaveCurrentTrack(bool),
     SaveCurrentContext(bool),
     IsTrackSaved(bool),
@@ -313,6 +317,12 @@ impl Widget for MediaControls {
                         image: Some(&gtk::Image::from_icon_name(Some("go-down-symbolic"), gtk::IconSize::LargeToolbar)),
                         toggled(btn) => MediaControlsMsg::ShowInfo(btn.get_active()),
                     },
+                    #[name="analysis_btn"]
+                    gtk::Button {
+                        tooltip_text: Some("Audio analysis"),
+                        image: Some(&gtk::Image::from_icon_name(Some("utilities-system-monitor"), gtk::IconSize::LargeToolbar)),
+                        clicked(_) => MediaControlsMsg::ShowCurrentAnalysis,
+                    },
                     #[name="prev_track_btn"]
                     gtk::Button {
                         tooltip_text: Some("Previous track"),
@@ -398,17 +408,13 @@ impl Widget for MediaControls {
     fn model(relm: &Relm<Self>, (pool, spotify, settings): (Handle, SpotifyRef, SettingsRef)) -> MediaControlsModel {
         let stream = relm.stream().clone();
 
+        // Playback state comes from playback state service, progress is moved
+        // between its updates here
         let _update_timer = {
             let stream = stream.clone();
-            let mut counter = 0;
 
             glib::timeout_add_seconds_local(1, move || {
-                counter = (counter + 1) % 10;
-                if counter == 0 {
-                    stream.emit(MediaControlsMsg::LoadState);
-                } else {
-                    stream.emit(MediaControlsMsg::Tick(1));
-                }
+                stream.emit(MediaControlsMsg::Tick(1));
                 Continue(true)
             })
         };
@@ -480,11 +486,9 @@ impl Widget for MediaControls {
                 }
             }
             LoadState => {
-                self.spawn(async move |pool, (stream, spotify): (EventStream<_>, SpotifyRef)| {
-                    stream.emit(NewState(Box::new(
-                        pool.spawn(async move { spotify.read().await.get_playback_state().await })
-                            .await??,
-                    )));
+                self.spawn(async move |pool, spotify: SpotifyRef| {
+                    pool.spawn(async move { spotify.read().await.playback_changed().notify() })
+                        .await?;
 
                     Ok(())
                 });
@@ -736,38 +740,38 @@ impl Widget for MediaControls {
             ShowInfo(state) => {
                 self.state_info.set_reveal_child(state);
             }
+            ShowCurrentAnalysis => {
+                if let Some(PlayingItem::Track(ref track)) = self.model.state.as_ref().and_then(|s| s.item.as_ref()) {
+                    self.model.stream.emit(ShowAnalysis(track.uri.clone(), track.name.clone()));
+                }
+            }
+            ShowAnalysis(..) => {}
             Play => {
-                self.spawn(async move |pool, (stream, spotify): (EventStream<_>, SpotifyRef)| {
-                    pool.spawn(async move { spotify.read().await.start_playback().await })
-                        .await??;
-                    stream.emit(LoadState);
-                    Ok(())
+                self.spawn(async move |pool, spotify: SpotifyRef| {
+                    Ok(pool
+                        .spawn(async move { spotify.read().await.start_playback().await })
+                        .await??)
                 });
             }
             Pause => {
-                self.spawn(async move |pool, (stream, spotify): (EventStream<_>, SpotifyRef)| {
-                    pool.spawn(async move { spotify.read().await.pause_playback().await })
-                        .await??;
-                    stream.emit(LoadState);
-                    Ok(())
+                self.spawn(async move |pool, spotify: SpotifyRef| {
+                    Ok(pool
+                        .spawn(async move { spotify.read().await.pause_playback().await })
+                        .await??)
                 });
             }
             NextTrack => {
-                self.spawn(async move |pool, (stream, spotify): (EventStream<_>, SpotifyRef)| {
-                    pool.spawn(async move { spotify.read().await.play_next_track().await })
-                        .await??;
-                    stream.emit(LoadState);
-                    Ok(())
+                self.spawn(async move |pool, spotify: SpotifyRef| {
+                    Ok(pool
+                        .spawn(async move { spotify.read().await.play_next_track().await })
+                        .await??)
                 });
             }
             PrevTrack => {
-                self.spawn(async move |pool, (stream, spotify): (EventStream<_>, SpotifyRef)| {
-                    pool.spawn(async move { spotify.read().await.play_prev_track().await })
-                        .await??;
-
-                    stream.emit(LoadState);
-
-                    Ok(())
+                self.spawn(async move |pool, spotify: SpotifyRef| {
+                    Ok(pool
+                        .spawn(async move { spotify.read().await.play_prev_track().await })
+                        .await??)
                 });
             }
             LoadContext(kind, uri) => {
@@ -833,6 +837,24 @@ impl Widget for MediaControls {
         }
     }
 
+    fn subscriptions(&mut self, relm: &Relm<Self>) {
+        let stream = relm.stream().clone();
+
+        let (_, stream_tx) = Channel::new(move |msg| {
+            stream.emit(msg);
+        });
+
+        observe(&self.model.pool, move |event| match event {
+            AppEvent::PlaybackStateUpdated(state) => {
+                let _ = stream_tx.send(MediaControlsMsg::NewState(Box::new((*state).clone())));
+            }
+            AppEvent::PlaybackDeviceChanged(_) => {
+                let _ = stream_tx.send(MediaControlsMsg::LoadDevices);
+            }
+            _ => {}
+        });
+    }
+
     fn init_view(&mut self) {
         let stream = self.model.stream.clone();
         let cell = gtk::CellRendererText::new();
@@ -1,5 +1,7 @@
 #![allow(clippy::redundant_field_names)]
 
+mod analysis;
+mod dialogs;
 mod lists;
 mod media_controls;
 mod notifier;
@@ -70,6 +70,21 @@ impl Widget for ArtistsTab {
 
                 self.top_artists_view.emit(ContainerMsg::Load(()));
             }
+            Reset => {
+                self.followed_artists_view.emit(ContainerMsg::Reset);
+
+                self.top_artists_view.emit(ContainerMsg::Reset);
+
+                self.top_tracks_view.emit(ContainerMsg::Reset.into());
+
+                self.albums_view.emit(ContainerMsg::Reset);
+
+                self.related_artists_view.emit(ContainerMsg::Reset);
+
+                self.tracks_view.emit(ContainerMsg::Reset.into());
+
+                self.stack.set_visible_child(self.followed_artists_view.widget());
+            }
             OpenContainer(ARTIST_TAB_ALBUMS, uri, name) => {
                 self.albums_view.emit(ContainerMsg::Load(uri.clone()));
 
@@ -45,6 +45,15 @@ impl Widget for CategoriesTab {
             ShowTab => {
                 self.categories_view.emit(ContainerMsg::Load(()));
             }
+            Reset => {
+                self.categories_view.emit(ContainerMsg::Reset);
+
+                self.playlists_view.emit(ContainerMsg::Reset);
+
+                self.tracks_view.emit(ContainerMsg::Reset.into());
+
+                self.stack.set_visible_child(self.categories_view.widget());
+            }
             OpenContainer(0, id, name) => {
                 self.playlists_view.emit(ContainerMsg::Load(id));
 
@@ -39,6 +39,13 @@ impl Widget for FeaturedTab {
             ShowTab => {
                 self.playlists_view.emit(ContainerMsg::Load(()));
             }
+            Reset => {
+                self.playlists_view.emit(ContainerMsg::Reset);
+
+                self.tracks_view.emit(ContainerMsg::Reset.into());
+
+                self.stack.set_visible_child(self.playlists_view.widget());
+            }
             OpenContainer(0, uri, name) => {
                 self.tracks_view.emit(ContainerMsg::Load(uri).into());
 
@@ -8,22 +8,30 @@ pub mod playlists;
 pub mod queue;
 pub mod recent;
 pub mod search;
+pub mod sets;
 pub mod settings;
 pub mod shows;
 pub mod tracks;
 
 use crate::{components::lists::TrackMsg, loaders::ContainerLoader, services::SpotifyRef};
 use relm_derive::Msg;
-use rspotify::model::Type;
+use rspotify::model::{FullPlaylist, Type};
 use tokio::runtime::Handle;
 
 #[derive(Msg)]
 pub enum MusicTabMsg {
     ShowTab,
+    Reset,
     OpenContainer(u8, String, String),
     GoToTrack(String),
     GoTo(Type, String, String),
     PlaybackUpdate,
+    ShowAnalysis(String, String),
+    NewPlaylist,
+    EditOpenPlaylist,
+    EditPlaylist(Box<FullPlaylist>, bool),
+    RemoveOpenPlaylist,
+    PlaylistsChanged,
 }
 
 pub struct TracksObserver {
@@ -67,6 +75,7 @@ impl<Loader: ContainerLoader> Fn<(&TrackMsg<Loader>,)> for TracksObserver {
             TrackMsg::PlayingNewTrack => self.upstream.emit(MusicTabMsg::PlaybackUpdate),
             TrackMsg::GoToArtist(uri, name) => self.upstream.emit(MusicTabMsg::GoTo(Type::Artist, uri.clone(), name.clone())),
             TrackMsg::GoToAlbum(uri, name) => self.upstream.emit(MusicTabMsg::GoTo(Type::Album, uri.clone(), name.clone())),
+            TrackMsg::ShowAnalysis(uri, name) => self.upstream.emit(MusicTabMsg::ShowAnalysis(uri.clone(), name.clone())),
             _ => {}
         }
     }
@@ -40,6 +40,13 @@ impl Widget for NewReleasesTab {
             ShowTab => {
                 self.albums_view.emit(ContainerMsg::Load(()));
             }
+            Reset => {
+                self.albums_view.emit(ContainerMsg::Reset);
+
+                self.tracks_view.emit(ContainerMsg::Reset.into());
+
+                self.stack.set_visible_child(self.albums_view.widget());
+            }
             OpenContainer(0, uri, name) => {
                 self.tracks_view.emit(ContainerMsg::Load(uri).into());
 
@@ -1,20 +1,60 @@
 use crate::{
     components::{
+        dialogs::edit_playlist_details,
         lists::{ContainerMsg, PlaylistList, TrackList, TrackMsg},
-        tabs::{MusicTabModel, MusicTabMsg, MusicTabParams, TracksObserver},
+        tabs::{MusicTabMsg, MusicTabParams, TracksObserver},
     },
     loaders::{PlaylistLoader, SavedPlaylistsLoader as SavedLoader},
+    models::playlist::PlaylistDetails,
+    services::{
+        api::{PlaylistsStorageApi, UsersStorageApi},
+        SpotifyRef,
+    },
+    utils::{Extract, Spawn},
 };
+use glib::Cast;
 use gtk::prelude::*;
-use relm::{Relm, Widget};
+use relm::{EventStream, Relm, Widget};
 use relm_derive::widget;
+use rspotify::client::ClientError;
+use tokio::runtime::Handle;
+
+pub struct PlaylistsTabModel {
+    pool: Handle,
+    spotify: SpotifyRef,
+    stream: EventStream<MusicTabMsg>,
+    /// Uri and name of the playlist shown in tracks view
+    open_playlist: Option<(String, String)>,
+}
 
 #[widget]
 impl Widget for PlaylistsTab {
     view! {
         gtk::Box(gtk::Orientation::Vertical, 1) {
-            #[name="breadcrumb"]
-            gtk::StackSwitcher {},
+            gtk::Box(gtk::Orientation::Horizontal, 5) {
+                #[name="breadcrumb"]
+                gtk::StackSwitcher {
+                    hexpand: true,
+                    halign: gtk::Align::Start,
+                },
+                gtk::Button {
+                    image: Some(&gtk::Image::from_icon_name(Some("list-add"), gtk::IconSize::SmallToolbar)),
+                    tooltip_text: Some("New playlist"),
+                    clicked(_) => MusicTabMsg::NewPlaylist,
+                },
+                gtk::Button {
+                    image: Some(&gtk::Image::from_icon_name(Some("document-properties"), gtk::IconSize::SmallToolbar)),
+                    tooltip_text: Some("Edit playlist details"),
+                    sensitive: self.model.open_playlist.is_some(),
+                    clicked(_) => MusicTabMsg::EditOpenPlaylist,
+                },
+                gtk::Button {
+                    image: Some(&gtk::Image::from_icon_name(Some("edit-delete"), gtk::IconSize::SmallToolbar)),
+                    tooltip_text: Some("Unfollow or delete playlist"),
+                    sensitive: self.model.open_playlist.is_some(),
+                    clicked(_) => MusicTabMsg::RemoveOpenPlaylist,
+                },
+            },
             #[name="stack"]
             gtk::Stack {
                 vexpand: true,
@@ -30,7 +70,14 @@ impl Widget for PlaylistsTab {
         }
     }
 
-    fn model(params: MusicTabParams) -> MusicTabModel { MusicTabModel::from_params(params) }
+    fn model(relm: &Relm<Self>, (pool, spotify): MusicTabParams) -> PlaylistsTabModel {
+        PlaylistsTabModel {
+            pool,
+            spotify,
+            stream: relm.stream().clone(),
+            open_playlist: None,
+        }
+    }
 
     fn update(&mut self, event: MusicTabMsg) {
         use MusicTabMsg::*;
@@ -39,18 +86,117 @@ impl Widget for PlaylistsTab {
             ShowTab => {
                 self.playlists_view.emit(ContainerMsg::Load(()));
             }
+            Reset => {
+                self.playlists_view.emit(ContainerMsg::Reset);
+
+                self.tracks_view.emit(ContainerMsg::Reset.into());
+
+                self.model.open_playlist = None;
+
+                self.stack.set_visible_child(self.playlists_view.widget());
+            }
             OpenContainer(0, uri, name) => {
-                self.tracks_view.emit(ContainerMsg::Load(uri).into());
+                self.tracks_view.emit(ContainerMsg::Load(uri.clone()).into());
 
                 let tracks_tab = self.tracks_view.widget();
 
                 self.stack.set_child_title(tracks_tab, Some(&name));
 
                 self.stack.set_visible_child(tracks_tab);
+
+                self.model.open_playlist = Some((uri, name));
             }
             GoToTrack(uri) => {
                 self.tracks_view.emit(TrackMsg::GoToTrack(uri));
             }
+            NewPlaylist => {
+                if let Some(details) = edit_playlist_details(self.window().as_ref(), "New playlist", &PlaylistDetails::default())
+                {
+                    self.spawn_args(
+                        details,
+                        async move |pool, (stream, spotify): (EventStream<_>, SpotifyRef), details| {
+                            let playlist = pool
+                                .spawn(async move { spotify.read().await.create_my_playlist(&details).await })
+                                .await??;
+
+                            stream.emit(PlaylistsChanged);
+                            stream.emit(OpenContainer(0, playlist.uri, playlist.name));
+                            Ok(())
+                        },
+                    );
+                }
+            }
+            EditOpenPlaylist => {
+                if let Some((uri, _)) = self.model.open_playlist.clone() {
+                    self.spawn_args(
+                        uri,
+                        async move |pool, (stream, spotify): (EventStream<_>, SpotifyRef), uri| {
+                            let (playlist, owned) = pool
+                                .spawn(async move {
+                                    let spotify = spotify.read().await;
+                                    let playlist = spotify.get_playlist(&uri).await?;
+                                    let me = spotify.get_my_profile().await?;
+                                    let owned = playlist.owner.id == me.id;
+                                    Ok::<_, ClientError>((playlist, owned))
+                                })
+                                .await??;
+
+                            stream.emit(EditPlaylist(Box::new(playlist), owned));
+                            Ok(())
+                        },
+                    );
+                }
+            }
+            EditPlaylist(playlist, owned) => {
+                if !owned {
+                    self.show_message(&format!("Only the owner can edit details of \"{}\"", playlist.name));
+                    return;
+                }
+
+                if let Some(details) =
+                    edit_playlist_details(self.window().as_ref(), "Playlist details", &PlaylistDetails::from(&*playlist))
+                {
+                    self.spawn_args(
+                        (playlist.uri, details),
+                        async move |pool, (stream, spotify): (EventStream<_>, SpotifyRef), (uri, details)| {
+                            let name = details.name.clone();
+
+                            pool.spawn(async move { spotify.read().await.update_playlist_details(&uri, &details).await })
+                                .await??;
+
+                            stream.emit(PlaylistsChanged);
+                            stream.emit(OpenContainer(0, uri, name));
+                            Ok(())
+                        },
+                    );
+                }
+            }
+            RemoveOpenPlaylist => {
+                if let Some((uri, name)) = self.model.open_playlist.clone() {
+                    let question = format!("Unfollow playlist \"{}\"?\nPlaylists you own are deleted this way.", name);
+
+                    if !self.confirm(&question) {
+                        return;
+                    }
+
+                    self.spawn_args(
+                        uri,
+                        async move |pool, (stream, spotify): (EventStream<_>, SpotifyRef), uri| {
+                            pool.spawn(async move { spotify.read().await.remove_my_playlists(&[uri]).await })
+                                .await??;
+
+                            stream.emit(PlaylistsChanged);
+                            Ok(())
+                        },
+                    );
+
+                    self.model.open_playlist = None;
+                    self.stack.set_visible_child(self.playlists_view.widget());
+                }
+            }
+            PlaylistsChanged => {
+                self.playlists_view.emit(ContainerMsg::Reload);
+            }
             _ => {}
         }
     }
@@ -68,4 +214,49 @@ impl Widget for PlaylistsTab {
 
         self.tracks_view.stream().observe(TracksObserver::new(relm.stream()));
     }
+
+    fn window(&self) -> Option<gtk::Window> {
+        self.stack
+            .get_toplevel()
+            .and_then(|widget| widget.downcast::<gtk::Window>().ok())
+    }
+
+    fn confirm(&self, question: &str) -> bool {
+        let dialog = gtk::MessageDialog::new(
+            self.window().as_ref(),
+            gtk::DialogFlags::MODAL | gtk::DialogFlags::DESTROY_WITH_PARENT,
+            gtk::MessageType::Question,
+            gtk::ButtonsType::OkCancel,
+            question,
+        );
+
+        let confirmed = dialog.run() == gtk::ResponseType::Ok;
+        dialog.close();
+        confirmed
+    }
+
+    fn show_message(&self, message: &str) {
+        let dialog = gtk::MessageDialog::new(
+            self.window().as_ref(),
+            gtk::DialogFlags::MODAL | gtk::DialogFlags::DESTROY_WITH_PARENT,
+            gtk::MessageType::Info,
+            gtk::ButtonsType::Close,
+            message,
+        );
+
+        dialog.run();
+        dialog.close();
+    }
+}
+
+impl Extract<EventStream<MusicTabMsg>> for PlaylistsTab {
+    fn extract(&self) -> EventStream<MusicTabMsg> { self.model.stream.clone() }
+}
+
+impl Extract<SpotifyRef> for PlaylistsTab {
+    fn extract(&self) -> SpotifyRef { self.model.spotify.clone() }
+}
+
+impl Spawn for PlaylistsTab {
+    fn pool(&self) -> Handle { self.model.pool.clone() }
 }
@@ -3,7 +3,7 @@ use crate::{
         lists::{ContainerMsg, TrackList, TrackMsg},
         tabs::{MusicTabModel, MusicTabMsg, MusicTabParams, TracksObserver},
     },
-    loaders::QueueLoader,
+    loaders::{PlaybackQueueId, QueueLoader},
 };
 use relm::{Relm, Widget};
 use relm_derive::widget;
@@ -22,7 +22,10 @@ impl Widget for QueueTab {
 
         match event {
             ShowTab => {
-                self.tracks_view.emit(ContainerMsg::Load(()).into());
+                self.tracks_view.emit(ContainerMsg::Load(PlaybackQueueId).into());
+            }
+            Reset => {
+                self.tracks_view.emit(ContainerMsg::Reset.into());
             }
             GoToTrack(uri) => {
                 self.tracks_view.emit(TrackMsg::GoToTrack(uri));
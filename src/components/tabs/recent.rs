@@ -24,6 +24,9 @@ impl Widget for RecentTab {
             ShowTab => {
                 self.tracks_view.emit(ContainerMsg::Load(()).into());
             }
+            Reset => {
+                self.tracks_view.emit(ContainerMsg::Reset.into());
+            }
             GoToTrack(uri) => {
                 self.tracks_view.emit(TrackMsg::GoToTrack(uri));
             }
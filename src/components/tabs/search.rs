@@ -1,28 +1,36 @@
-// TODO
-#![allow(unused_imports, dead_code)]
-
 use crate::{
-    components::{lists::TrackList, tabs::MusicTabParams},
-    loaders::RecommendLoader,
+    components::{
+        lists::{AlbumList, ArtistList, ContainerMsg, PlaylistList, TrackList, TrackMsg},
+        tabs::MusicTabParams,
+    },
+    loaders::{
+        ContainerLoader, Mode, RecommendLoader, RecommendParams, SearchAlbumsLoader, SearchArtistsLoader, SearchPlaylistsLoader,
+        SearchQuery, SearchShowsLoader, SearchTracksLoader, Seed,
+    },
     services::SpotifyRef,
     utils::{SearchTerm, SearchTerms},
 };
-use gtk::{
-    BoxExt, ButtonExt, CheckMenuItemExt, ContainerExt, EntryExt, FlowBoxChildExt, FlowBoxExt, GtkMenuExt, GtkMenuItemExt,
-    LabelExt, MenuBarExt, MenuButtonExt, OrientableExt, WidgetExt,
-};
+use glib::{Cast, IsA};
+use gtk::prelude::*;
 use relm::{EventStream, Relm, Widget};
 use relm_derive::{widget, Msg};
-use std::{cell::RefCell, rc::Rc, sync::Arc};
+use rspotify::model::Type;
+use serde_json::Value;
+use std::{cell::RefCell, fmt::Debug, rc::Rc, str::FromStr};
 use tokio::runtime::Handle;
 
-#[derive(Msg, Copy, Clone)]
+#[derive(Msg)]
 pub enum SearchMsg {
     ShowTab,
     AddSearchTerm(SearchTerm, bool),
+    Search,
+    Reset,
+    SearchCatalog,
+    GoTo(Type, String, String),
+    PlaybackUpdate,
+    ShowAnalysis(String, String),
 }
 
-// TODO
 pub struct SearchModel {
     pool: Handle,
     spotify: SpotifyRef,
@@ -95,266 +103,340 @@ impl Widget for SearchTab {
                 },
             },
 
-            gtk::ButtonBox {
-                homogeneous: true,
-                gtk::Button {
-                    image: Some(&gtk::Image::from_icon_name(Some("system-search"), gtk::IconSize::Button)),
-                    label: "Search",
-                    always_show_image: true,
+            #[name="query_entry"]
+            gtk::SearchEntry {
+                margin_top: 10, margin_start: 10, margin_end: 10,
+                placeholder_text: Some("Search catalog, e.g. daft punk year:1995-2001 genre:house"),
+                activate(_) => SearchMsg::SearchCatalog,
+            },
+
+            #[name="breadcrumb"]
+            gtk::StackSwitcher {
+                halign: gtk::Align::Center,
+            },
+
+            #[name="stack"]
+            gtk::Stack {
+                vexpand: true,
+
+                #[name="tracks_view"]
+                TrackList::<SearchTracksLoader>((self.model.pool.clone(), self.model.spotify.clone())) {
+                    child: { title: Some("Tracks") },
                 },
-                gtk::Button {
-                    image: Some(&gtk::Image::from_icon_name(Some("process-stop"), gtk::IconSize::Button)),
-                    label: "Reset",
-                    always_show_image: true,
+                #[name="albums_view"]
+                AlbumList::<SearchAlbumsLoader>((self.model.pool.clone(), self.model.spotify.clone())) {
+                    child: { title: Some("Albums") },
                 },
-                gtk::MenuButton {
-                    image: Some(&gtk::Image::from_icon_name(Some("list-add"), gtk::IconSize::Button)),
-                    label: "Add term",
-                    always_show_image: true,
-                    popup: Some(&search_terms_menu),
+                #[name="artists_view"]
+                ArtistList::<SearchArtistsLoader>((self.model.pool.clone(), self.model.spotify.clone())) {
+                    child: { title: Some("Artists") },
+                },
+                #[name="playlists_view"]
+                PlaylistList::<SearchPlaylistsLoader>((self.model.pool.clone(), self.model.spotify.clone())) {
+                    child: { title: Some("Playlists") },
+                },
+                #[name="shows_view"]
+                PlaylistList::<SearchShowsLoader>((self.model.pool.clone(), self.model.spotify.clone())) {
+                    child: { title: Some("Shows") },
                 },
-            },
 
-            #[name="search_terms_box"]
-            gtk::FlowBox {
-                //homogeneous: true,
+                #[name="recommend_view"]
+                gtk::Box(gtk::Orientation::Vertical, 10) {
+                    child: { title: Some("Recommendations") },
 
-                /*
-                    gtk::Label {
-                        text: "Genres"
-                    },
-                    gtk::Label {
-                        text: "Artists"
-                    },
-                    gtk::Label {
-                        text: "Tracks"
-                    },
-                 */
+                    gtk::Grid {
+                        margin_top: 10, margin_start: 10, margin_end: 10,
+                        row_spacing: 5,
+                        column_spacing: 10,
 
-                #[name="tempo_term_box"]
-                gtk::Fixed {
-                    property_width_request: 200,
-                    gtk::Box(gtk::Orientation::Horizontal, 10) {
-                        homogeneous: true,
                         gtk::Label {
-                            halign: gtk::Align::End,
-                            text: "BPM"
+                            cell: { left_attach: 0, top_attach: 0, },
+                            halign: gtk::Align::Start,
+                            text: "Genres",
                         },
-                        gtk::SpinButton {
-
+                        #[name="seed_genres_entry"]
+                        gtk::Entry {
+                            cell: { left_attach: 1, top_attach: 0, },
+                            hexpand: true,
+                            placeholder_text: Some("Comma separated genres, e.g. house, techno"),
+                            activate(_) => SearchMsg::Search,
                         },
-                        gtk::SpinButton {},
-                        gtk::SpinButton {},
-                    },
-                },
-
-                #[name="duration_term_box"]
-                gtk::Fixed {
-                    gtk::Box(gtk::Orientation::Horizontal, 10) {
-                        property_width_request: 200,
-                        homogeneous: true,
                         gtk::Label {
-                            halign: gtk::Align::End,
-                            text: "Duration"
+                            cell: { left_attach: 0, top_attach: 1, },
+                            halign: gtk::Align::Start,
+                            text: "Artists",
                         },
-
+                        #[name="seed_artists_entry"]
                         gtk::Entry {
+                            cell: { left_attach: 1, top_attach: 1, },
+                            placeholder_text: Some("Comma separated artist URIs or IDs"),
+                            activate(_) => SearchMsg::Search,
                         },
-                        gtk::Entry {
+                        gtk::Label {
+                            cell: { left_attach: 0, top_attach: 2, },
+                            halign: gtk::Align::Start,
+                            text: "Tracks",
                         },
+                        #[name="seed_tracks_entry"]
                         gtk::Entry {
+                            cell: { left_attach: 1, top_attach: 2, },
+                            placeholder_text: Some("Comma separated track URIs or IDs"),
+                            activate(_) => SearchMsg::Search,
                         },
                     },
-                },
 
-                #[name="key_term_box"]
-                gtk::Fixed {
-                    property_width_request: 200,
-                    gtk::Box(gtk::Orientation::Horizontal, 10) {
+                    gtk::ButtonBox {
                         homogeneous: true,
-                        gtk::Label {
-                            halign: gtk::Align::End,
-                            text: "Key"
+                        gtk::Button {
+                            image: Some(&gtk::Image::from_icon_name(Some("system-search"), gtk::IconSize::Button)),
+                            label: "Search",
+                            always_show_image: true,
+                            clicked(_) => SearchMsg::Search,
+                        },
+                        gtk::Button {
+                            image: Some(&gtk::Image::from_icon_name(Some("process-stop"), gtk::IconSize::Button)),
+                            label: "Reset",
+                            always_show_image: true,
+                            clicked(_) => SearchMsg::Reset,
+                        },
+                        gtk::MenuButton {
+                            image: Some(&gtk::Image::from_icon_name(Some("list-add"), gtk::IconSize::Button)),
+                            label: "Add term",
+                            always_show_image: true,
+                            popup: Some(&search_terms_menu),
                         },
-                        gtk::SpinButton {},
-                        gtk::SpinButton {},
-                        gtk::SpinButton {},
                     },
-                },
 
-                #[name="mode_term_box"]
-                gtk::Fixed {
-                    property_width_request: 200,
-                    gtk::Box(gtk::Orientation::Horizontal, 10) {
-                        property_width_request: 200,
-                        homogeneous: true,
-                        gtk::Label {
-                            halign: gtk::Align::End,
-                            text: "Mode"
+                    #[name="error_label"]
+                    gtk::Label {
+                        halign: gtk::Align::Start,
+                        margin_start: 10,
+                        text: "",
+                    },
+
+                    #[name="search_terms_box"]
+                    gtk::FlowBox {
+                        #[name="tempo_term_box"]
+                        gtk::Fixed {
+                            property_width_request: 200,
+                            gtk::Box(gtk::Orientation::Horizontal, 10) {
+                                homogeneous: true,
+                                gtk::Label {
+                                    halign: gtk::Align::End,
+                                    text: "BPM"
+                                },
+                                gtk::Entry { placeholder_text: Some("min"), width_chars: 6 },
+                                gtk::Entry { placeholder_text: Some("max"), width_chars: 6 },
+                                gtk::Entry { placeholder_text: Some("target"), width_chars: 6 },
+                            },
                         },
-                        gtk::CheckButton {
-                           label: "Minor"
+
+                        #[name="duration_term_box"]
+                        gtk::Fixed {
+                            property_width_request: 200,
+                            gtk::Box(gtk::Orientation::Horizontal, 10) {
+                                homogeneous: true,
+                                gtk::Label {
+                                    halign: gtk::Align::End,
+                                    text: "Duration"
+                                },
+                                gtk::Entry { placeholder_text: Some("min"), width_chars: 6 },
+                                gtk::Entry { placeholder_text: Some("max"), width_chars: 6 },
+                                gtk::Entry { placeholder_text: Some("target"), width_chars: 6 },
+                            },
                         },
-                        gtk::CheckButton {
-                           label: "Major"
+
+                        #[name="key_term_box"]
+                        gtk::Fixed {
+                            property_width_request: 200,
+                            gtk::Box(gtk::Orientation::Horizontal, 10) {
+                                homogeneous: true,
+                                gtk::Label {
+                                    halign: gtk::Align::End,
+                                    text: "Key"
+                                },
+                                gtk::Entry { placeholder_text: Some("min"), width_chars: 6 },
+                                gtk::Entry { placeholder_text: Some("max"), width_chars: 6 },
+                                gtk::Entry { placeholder_text: Some("target"), width_chars: 6 },
+                            },
                         },
-                    },
-                },
 
-                #[name="instrument_term_box"]
-                gtk::Fixed {
-                    property_width_request: 200,
-                    gtk::Box(gtk::Orientation::Horizontal, 10) {
-                        homogeneous: true,
-                        gtk::Label {
-                            halign: gtk::Align::End,
-                            text: "Instrumental"
+                        #[name="mode_term_box"]
+                        gtk::Fixed {
+                            property_width_request: 200,
+                            gtk::Box(gtk::Orientation::Horizontal, 10) {
+                                homogeneous: true,
+                                gtk::Label {
+                                    halign: gtk::Align::End,
+                                    text: "Mode"
+                                },
+                                #[name="mode_minor_check"]
+                                gtk::CheckButton {
+                                   label: "Minor"
+                                },
+                                #[name="mode_major_check"]
+                                gtk::CheckButton {
+                                   label: "Major"
+                                },
+                            },
                         },
-                        gtk::SpinButton {},
-                        gtk::SpinButton {},
-                        gtk::SpinButton {},
-                    },
-                },
 
-                #[name="speech_term_box"]
-                gtk::Fixed {
-                    property_width_request: 200,
-                    gtk::Box(gtk::Orientation::Horizontal, 10) {
-                        homogeneous: true,
-                        gtk::Label {
-                            halign: gtk::Align::End,
-                            text: "Speech"
+                        #[name="instrument_term_box"]
+                        gtk::Fixed {
+                            property_width_request: 200,
+                            gtk::Box(gtk::Orientation::Horizontal, 10) {
+                                homogeneous: true,
+                                gtk::Label {
+                                    halign: gtk::Align::End,
+                                    text: "Instrumental"
+                                },
+                                gtk::Entry { placeholder_text: Some("min"), width_chars: 6 },
+                                gtk::Entry { placeholder_text: Some("max"), width_chars: 6 },
+                                gtk::Entry { placeholder_text: Some("target"), width_chars: 6 },
+                            },
                         },
-                        gtk::SpinButton {},
-                        gtk::SpinButton {},
-                        gtk::SpinButton {},
-                    },
-                },
 
-                #[name="acoustic_term_box"]
-                gtk::Fixed {
-                    property_width_request: 200,
-                    gtk::Box(gtk::Orientation::Horizontal, 10) {
-                        homogeneous: true,
-                        gtk::Label {
-                            halign: gtk::Align::End,
-                            text: "Acoustic"
+                        #[name="speech_term_box"]
+                        gtk::Fixed {
+                            property_width_request: 200,
+                            gtk::Box(gtk::Orientation::Horizontal, 10) {
+                                homogeneous: true,
+                                gtk::Label {
+                                    halign: gtk::Align::End,
+                                    text: "Speech"
+                                },
+                                gtk::Entry { placeholder_text: Some("min"), width_chars: 6 },
+                                gtk::Entry { placeholder_text: Some("max"), width_chars: 6 },
+                                gtk::Entry { placeholder_text: Some("target"), width_chars: 6 },
+                            },
                         },
-                        gtk::SpinButton {},
-                        gtk::SpinButton {},
-                        gtk::SpinButton {},
-                    },
-                },
 
-                #[name="dance_term_box"]
-                gtk::Fixed {
-                    property_width_request: 200,
-                    gtk::Box(gtk::Orientation::Horizontal, 10) {
-                        homogeneous: true,
-                        gtk::Label {
-                            halign: gtk::Align::End,
-                            text: "Dance"
+                        #[name="acoustic_term_box"]
+                        gtk::Fixed {
+                            property_width_request: 200,
+                            gtk::Box(gtk::Orientation::Horizontal, 10) {
+                                homogeneous: true,
+                                gtk::Label {
+                                    halign: gtk::Align::End,
+                                    text: "Acoustic"
+                                },
+                                gtk::Entry { placeholder_text: Some("min"), width_chars: 6 },
+                                gtk::Entry { placeholder_text: Some("max"), width_chars: 6 },
+                                gtk::Entry { placeholder_text: Some("target"), width_chars: 6 },
+                            },
                         },
-                        gtk::SpinButton {},
-                        gtk::SpinButton {},
-                        gtk::SpinButton {},
-                    },
-                },
 
-                #[name="energy_term_box"]
-                gtk::Fixed {
-                    property_width_request: 200,
-                    gtk::Box(gtk::Orientation::Horizontal, 10) {
-                        homogeneous: true,
-                        gtk::Label {
-                            halign: gtk::Align::End,
-                            text: "Energy"
+                        #[name="dance_term_box"]
+                        gtk::Fixed {
+                            property_width_request: 200,
+                            gtk::Box(gtk::Orientation::Horizontal, 10) {
+                                homogeneous: true,
+                                gtk::Label {
+                                    halign: gtk::Align::End,
+                                    text: "Dance"
+                                },
+                                gtk::Entry { placeholder_text: Some("min"), width_chars: 6 },
+                                gtk::Entry { placeholder_text: Some("max"), width_chars: 6 },
+                                gtk::Entry { placeholder_text: Some("target"), width_chars: 6 },
+                            },
                         },
-                        gtk::SpinButton {},
-                        gtk::SpinButton {},
-                        gtk::SpinButton {},
-                    },
-                },
 
-                #[name="liveness_term_box"]
-                gtk::Fixed {
-                    property_width_request: 200,
-                    gtk::Box(gtk::Orientation::Horizontal, 10) {
-                        homogeneous: true,
-                        gtk::Label {
-                            halign: gtk::Align::End,
-                            text: "Liveness"
+                        #[name="energy_term_box"]
+                        gtk::Fixed {
+                            property_width_request: 200,
+                            gtk::Box(gtk::Orientation::Horizontal, 10) {
+                                homogeneous: true,
+                                gtk::Label {
+                                    halign: gtk::Align::End,
+                                    text: "Energy"
+                                },
+                                gtk::Entry { placeholder_text: Some("min"), width_chars: 6 },
+                                gtk::Entry { placeholder_text: Some("max"), width_chars: 6 },
+                                gtk::Entry { placeholder_text: Some("target"), width_chars: 6 },
+                            },
                         },
-                        gtk::SpinButton {},
-                        gtk::SpinButton {},
-                        gtk::SpinButton {},
-                    },
-                },
 
-                #[name="valence_term_box"]
-                gtk::Fixed {
-                    property_width_request: 200,
-                    gtk::Box(gtk::Orientation::Horizontal, 10) {
-                        homogeneous: true,
-                        gtk::Label {
-                            halign: gtk::Align::End,
-                            text: "Valence"
+                        #[name="liveness_term_box"]
+                        gtk::Fixed {
+                            property_width_request: 200,
+                            gtk::Box(gtk::Orientation::Horizontal, 10) {
+                                homogeneous: true,
+                                gtk::Label {
+                                    halign: gtk::Align::End,
+                                    text: "Liveness"
+                                },
+                                gtk::Entry { placeholder_text: Some("min"), width_chars: 6 },
+                                gtk::Entry { placeholder_text: Some("max"), width_chars: 6 },
+                                gtk::Entry { placeholder_text: Some("target"), width_chars: 6 },
+                            },
                         },
-                        gtk::SpinButton {},
-                        gtk::SpinButton {},
-                        gtk::SpinButton {},
-                    },
-                },
 
-                #[name="loudness_term_box"]
-                gtk::Fixed {
-                    property_width_request: 200,
-                    gtk::Box(gtk::Orientation::Horizontal, 10) {
-                        homogeneous: true,
-                        gtk::Label {
-                            halign: gtk::Align::End,
-                            text: "Loudness"
+                        #[name="valence_term_box"]
+                        gtk::Fixed {
+                            property_width_request: 200,
+                            gtk::Box(gtk::Orientation::Horizontal, 10) {
+                                homogeneous: true,
+                                gtk::Label {
+                                    halign: gtk::Align::End,
+                                    text: "Valence"
+                                },
+                                gtk::Entry { placeholder_text: Some("min"), width_chars: 6 },
+                                gtk::Entry { placeholder_text: Some("max"), width_chars: 6 },
+                                gtk::Entry { placeholder_text: Some("target"), width_chars: 6 },
+                            },
                         },
-                        gtk::SpinButton {},
-                        gtk::SpinButton {},
-                        gtk::SpinButton {},
-                    },
-                },
 
-                #[name="popularity_term_box"]
-                gtk::Fixed {
-                    property_width_request: 200,
-                    gtk::Box(gtk::Orientation::Horizontal, 10) {
-                        homogeneous: true,
-                        gtk::Label {
-                            halign: gtk::Align::End,
-                            text: "Popularity"
+                        #[name="loudness_term_box"]
+                        gtk::Fixed {
+                            property_width_request: 200,
+                            gtk::Box(gtk::Orientation::Horizontal, 10) {
+                                homogeneous: true,
+                                gtk::Label {
+                                    halign: gtk::Align::End,
+                                    text: "Loudness"
+                                },
+                                gtk::Entry { placeholder_text: Some("min"), width_chars: 6 },
+                                gtk::Entry { placeholder_text: Some("max"), width_chars: 6 },
+                                gtk::Entry { placeholder_text: Some("target"), width_chars: 6 },
+                            },
                         },
-                        gtk::SpinButton {},
-                        gtk::SpinButton {},
-                        gtk::SpinButton {},
-                    },
-                },
 
-                #[name="time_sign_term_box"]
-                gtk::Fixed {
-                    property_width_request: 200,
-                    gtk::Box(gtk::Orientation::Horizontal, 10) {
-                        homogeneous: true,
-                        gtk::Label {
-                            halign: gtk::Align::End,
-                            text: "Time sig."
+                        #[name="popularity_term_box"]
+                        gtk::Fixed {
+                            property_width_request: 200,
+                            gtk::Box(gtk::Orientation::Horizontal, 10) {
+                                homogeneous: true,
+                                gtk::Label {
+                                    halign: gtk::Align::End,
+                                    text: "Popularity"
+                                },
+                                gtk::Entry { placeholder_text: Some("min"), width_chars: 6 },
+                                gtk::Entry { placeholder_text: Some("max"), width_chars: 6 },
+                                gtk::Entry { placeholder_text: Some("target"), width_chars: 6 },
+                            },
                         },
-                        gtk::SpinButton {
 
+                        #[name="time_sign_term_box"]
+                        gtk::Fixed {
+                            property_width_request: 200,
+                            gtk::Box(gtk::Orientation::Horizontal, 10) {
+                                homogeneous: true,
+                                gtk::Label {
+                                    halign: gtk::Align::End,
+                                    text: "Time sig."
+                                },
+                                gtk::Entry { placeholder_text: Some("min"), width_chars: 6 },
+                                gtk::Entry { placeholder_text: Some("max"), width_chars: 6 },
+                                gtk::Entry { placeholder_text: Some("target"), width_chars: 6 },
+                            },
                         },
-                        gtk::SpinButton {},
-                        gtk::SpinButton {},
+
                     },
-                },
 
+                    #[name="results_view"]
+                    TrackList::<RecommendLoader>((self.model.pool.clone(), self.model.spotify.clone())),
+                },
             },
-            //TrackList::<RecommendLoader>((self.model.pool.clone(), self.model.spotify.clone()))
         }
     }
 
@@ -379,10 +461,114 @@ impl Widget for SearchTab {
 
                 self.search_terms_box.invalidate_filter();
             }
+            Search => match self.search_params() {
+                Ok(params) => {
+                    self.error_label.set_text("");
+
+                    self.results_view.emit(ContainerMsg::Load(params).into());
+                }
+                Err(error) => {
+                    self.error_label.set_text(&error);
+                }
+            },
+            SearchCatalog => {
+                let query = SearchQuery::parse(&self.query_entry.get_text());
+
+                if query.is_empty() {
+                    return;
+                }
+
+                self.tracks_view.emit(ContainerMsg::Load(query.clone()).into());
+                self.albums_view.emit(ContainerMsg::Load(query.clone()));
+                self.artists_view.emit(ContainerMsg::Load(query.clone()));
+                self.playlists_view.emit(ContainerMsg::Load(query.clone()));
+                self.shows_view.emit(ContainerMsg::Load(query));
+
+                if self
+                    .stack
+                    .get_visible_child()
+                    .map_or(true, |child| &child == self.recommend_view.upcast_ref::<gtk::Widget>())
+                {
+                    self.stack.set_visible_child(self.tracks_view.widget());
+                }
+            }
+            GoTo(..) | PlaybackUpdate | ShowAnalysis(..) => {}
+            Reset => {
+                for entry in &[&self.seed_genres_entry, &self.seed_artists_entry, &self.seed_tracks_entry] {
+                    entry.set_text("");
+                }
+
+                for term_box in self.search_terms_box.get_children() {
+                    for entry in term_entries(&term_box) {
+                        entry.set_text("");
+                    }
+                }
+
+                self.mode_minor_check.set_active(false);
+                self.mode_major_check.set_active(false);
+
+                for item in self.search_terms_menu.get_children() {
+                    if let Ok(item) = item.downcast::<gtk::CheckMenuItem>() {
+                        item.set_active(false);
+                    }
+                }
+
+                self.error_label.set_text("");
+
+                self.results_view.emit(ContainerMsg::Clear.into());
+            }
         }
     }
 
+    fn search_params(&self) -> Result<RecommendParams, String> {
+        let mut params = RecommendParams {
+            seed_genres: split_seeds(&self.seed_genres_entry),
+            seed_artists: split_seeds(&self.seed_artists_entry),
+            seed_tracks: split_seeds(&self.seed_tracks_entry),
+            ..RecommendParams::default()
+        };
+
+        let search_terms = *self.model.search_terms.borrow();
+
+        for term in search_terms {
+            match term {
+                SearchTerm::Tempo => params.tempo = read_seed(&self.tempo_term_box, "BPM", parse_value)?,
+                SearchTerm::Duration => {
+                    params.duration_ms = read_seed(&self.duration_term_box, "duration", crate::utils::parse_time)?
+                }
+                SearchTerm::Key => params.key = read_seed(&self.key_term_box, "key", parse_value)?,
+                SearchTerm::Mode => {
+                    params.mode = match (self.mode_minor_check.get_active(), self.mode_major_check.get_active()) {
+                        (true, false) => Some(Mode::Minor),
+                        (false, true) => Some(Mode::Major),
+                        _ => None,
+                    }
+                }
+                SearchTerm::Instrumental => {
+                    params.instrumentalness = read_seed(&self.instrument_term_box, "instrumental", parse_value)?
+                }
+                SearchTerm::Speech => params.speechiness = read_seed(&self.speech_term_box, "speech", parse_value)?,
+                SearchTerm::Acoustic => params.acousticness = read_seed(&self.acoustic_term_box, "acoustic", parse_value)?,
+                SearchTerm::Dance => params.danceability = read_seed(&self.dance_term_box, "dance", parse_value)?,
+                SearchTerm::Energy => params.energy = read_seed(&self.energy_term_box, "energy", parse_value)?,
+                SearchTerm::Liveness => params.liveness = read_seed(&self.liveness_term_box, "liveness", parse_value)?,
+                SearchTerm::Valence => params.valence = read_seed(&self.valence_term_box, "valence", parse_value)?,
+                SearchTerm::Loudness => params.loudness = read_seed(&self.loudness_term_box, "loudness", parse_value)?,
+                SearchTerm::Popularity => params.popularity = read_seed(&self.popularity_term_box, "popularity", parse_value)?,
+                SearchTerm::TimeSign => {
+                    params.time_signature = read_seed(&self.time_sign_term_box, "time signature", parse_value)?
+                }
+            }
+        }
+
+        params.validate().map_err(|error| error.to_string())?;
+
+        Ok(params)
+    }
+
     fn init_view(&mut self) {
+        self.breadcrumb.set_stack(Some(&self.stack));
+
         let search_terms = self.model.search_terms.clone();
 
         self.search_terms_box.set_filter_func(Some(Box::new(move |term_box| {
@@ -391,4 +577,95 @@ impl Widget for SearchTab {
             search_terms.borrow().is_set(index as u8)
         })));
     }
+
+    fn subscriptions(&mut self, relm: &Relm<Self>) {
+        self.tracks_view.stream().observe(tracks_observer(relm.stream().clone()));
+
+        self.results_view.stream().observe(tracks_observer(relm.stream().clone()));
+
+        macro_rules! observe_activate {
+            ($($view:ident => $kind:expr),+) => {
+                $({
+                    let stream = relm.stream().clone();
+
+                    self.$view.stream().observe(move |msg| {
+                        if let ContainerMsg::ActivateItem(uri, name) = msg {
+                            stream.emit(SearchMsg::GoTo($kind, uri.clone(), name.clone()));
+                        }
+                    });
+                })+
+            };
+        }
+
+        observe_activate!(
+            albums_view => Type::Album,
+            artists_view => Type::Artist,
+            playlists_view => Type::Playlist,
+            shows_view => Type::Show
+        );
+    }
+}
+
+fn tracks_observer<Loader: ContainerLoader + 'static>(stream: EventStream<SearchMsg>) -> impl Fn(&TrackMsg<Loader>) + 'static {
+    move |msg| match msg {
+        TrackMsg::PlayingNewTrack => stream.emit(SearchMsg::PlaybackUpdate),
+        TrackMsg::GoToArtist(uri, name) => stream.emit(SearchMsg::GoTo(Type::Artist, uri.clone(), name.clone())),
+        TrackMsg::GoToAlbum(uri, name) => stream.emit(SearchMsg::GoTo(Type::Album, uri.clone(), name.clone())),
+        TrackMsg::ShowAnalysis(uri, name) => stream.emit(SearchMsg::ShowAnalysis(uri.clone(), name.clone())),
+        _ => {}
+    }
+}
+
+fn parse_value<T: FromStr>(value: &str) -> Option<T> { value.parse().ok() }
+
+fn split_seeds(entry: &gtk::Entry) -> Vec<String> {
+    entry
+        .get_text()
+        .split(',')
+        .map(str::trim)
+        .filter(|seed| !seed.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+fn term_entries<W: IsA<gtk::Container>>(term_box: &W) -> Vec<gtk::Entry> {
+    term_box
+        .get_children()
+        .into_iter()
+        .flat_map(|child| match child.downcast::<gtk::Container>() {
+            Ok(container) => container.get_children(),
+            Err(child) => vec![child],
+        })
+        .flat_map(|child| match child.downcast::<gtk::Box>() {
+            Ok(row) => row.get_children(),
+            Err(child) => vec![child],
+        })
+        .filter_map(|child| child.downcast::<gtk::Entry>().ok())
+        .collect()
+}
+
+fn read_seed<Val>(term_box: &gtk::Fixed, name: &str, parse: fn(&str) -> Option<Val>) -> Result<Option<Seed<Val>>, String>
+where
+    Val: Copy + PartialOrd + Debug + Into<Value>,
+{
+    let values = term_entries(term_box)
+        .iter()
+        .map(|entry| {
+            let text = entry.get_text();
+            let text = text.trim();
+
+            if text.is_empty() {
+                Ok(None)
+            } else {
+                parse(text)
+                    .map(Some)
+                    .ok_or_else(|| format!("Invalid {} value: {}", name, text))
+            }
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    match values[..] {
+        [min, max, target] => Ok(Seed::new(min, max, target)),
+        _ => Ok(None),
+    }
 }
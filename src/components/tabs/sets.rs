@@ -0,0 +1,565 @@
+use crate::{
+    components::lists::TARGET_TRACK_URIS,
+    models::{
+        playlist::PlaylistDetails,
+        set::{DjSet, SetTrack},
+    },
+    services::{
+        api::{PlaylistsStorageApi, SetsStorageApi, TracksStorageApi},
+        SpotifyRef,
+    },
+    utils::{humanize_time, Extract, Spawn},
+};
+use glib::{ObjectExt, StaticType};
+use gtk::prelude::*;
+use relm::{EventStream, Relm, Widget};
+use relm_derive::{widget, Msg};
+use rspotify::client::ClientError;
+use std::{collections::HashMap, path::PathBuf};
+use tokio::runtime::Handle;
+
+#[derive(Msg)]
+pub enum SetsMsg {
+    ShowTab,
+    LoadNames,
+    NewNames(Vec<String>),
+    OpenSet(Option<String>),
+    NewSet(Box<DjSet>),
+    CreateSet,
+    SaveSet,
+    DeleteSet,
+    AddTracks(Vec<String>, Option<usize>),
+    NewTracks(Vec<SetTrack>, Option<usize>),
+    RemoveChosenTracks,
+    TracksReordered,
+    ExportPlaylist,
+    ExportFile(ExportFormat),
+    Status(String),
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ExportFormat {
+    M3u,
+    Csv,
+    CueSheet,
+}
+
+impl ExportFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::M3u => "m3u",
+            ExportFormat::Csv => "csv",
+            ExportFormat::CueSheet => "txt",
+        }
+    }
+
+    fn render(self, set: &DjSet) -> String {
+        match self {
+            ExportFormat::M3u => set.to_m3u(),
+            ExportFormat::Csv => set.to_csv(),
+            ExportFormat::CueSheet => set.to_cue_sheet(),
+        }
+    }
+}
+
+pub struct SetsModel {
+    pool: Handle,
+    stream: EventStream<SetsMsg>,
+    spotify: SpotifyRef,
+    store: gtk::ListStore,
+    set: DjSet,
+}
+
+const COL_SET_INDEX: u32 = 0;
+const COL_SET_POSITION: u32 = 1;
+const COL_SET_START: u32 = 2;
+const COL_SET_NAME: u32 = 3;
+const COL_SET_ARTISTS: u32 = 4;
+const COL_SET_DURATION: u32 = 5;
+const COL_SET_BPM: u32 = 6;
+const COL_SET_KEY: u32 = 7;
+const COL_SET_BPM_DELTA: u32 = 8;
+const COL_SET_KEY_MATCH: u32 = 9;
+const COL_SET_HARD_MIX: u32 = 10;
+
+#[widget]
+impl Widget for SetsTab {
+    view! {
+        gtk::Box(gtk::Orientation::Vertical, 10) {
+            #[name="export_menu"]
+            gtk::Menu {
+                gtk::MenuItem {
+                    label: "To Spotify playlist",
+                    activate(_) => SetsMsg::ExportPlaylist,
+                },
+                gtk::MenuItem {
+                    label: "As M3U playlist…",
+                    activate(_) => SetsMsg::ExportFile(ExportFormat::M3u),
+                },
+                gtk::MenuItem {
+                    label: "As CSV…",
+                    activate(_) => SetsMsg::ExportFile(ExportFormat::Csv),
+                },
+                gtk::MenuItem {
+                    label: "As cue sheet…",
+                    activate(_) => SetsMsg::ExportFile(ExportFormat::CueSheet),
+                },
+            },
+
+            gtk::Box(gtk::Orientation::Horizontal, 10) {
+                margin_top: 10,
+                margin_start: 10,
+                margin_end: 10,
+
+                #[name="sets_combo"]
+                gtk::ComboBoxText {
+                    tooltip_text: Some("Saved sets"),
+                    changed(combo) => SetsMsg::OpenSet(combo.get_active_id().map(String::from)),
+                },
+                #[name="name_entry"]
+                gtk::Entry {
+                    placeholder_text: Some("Set name"),
+                    hexpand: true,
+                },
+                gtk::Button {
+                    image: Some(&gtk::Image::from_icon_name(Some("document-new"), gtk::IconSize::Button)),
+                    tooltip_text: Some("New set"),
+                    clicked(_) => SetsMsg::CreateSet,
+                },
+                gtk::Button {
+                    image: Some(&gtk::Image::from_icon_name(Some("document-save"), gtk::IconSize::Button)),
+                    tooltip_text: Some("Save set"),
+                    clicked(_) => SetsMsg::SaveSet,
+                },
+                gtk::Button {
+                    image: Some(&gtk::Image::from_icon_name(Some("edit-delete"), gtk::IconSize::Button)),
+                    tooltip_text: Some("Delete saved set"),
+                    clicked(_) => SetsMsg::DeleteSet,
+                },
+                gtk::Button {
+                    image: Some(&gtk::Image::from_icon_name(Some("list-remove"), gtk::IconSize::Button)),
+                    tooltip_text: Some("Remove selected tracks from set"),
+                    clicked(_) => SetsMsg::RemoveChosenTracks,
+                },
+                gtk::MenuButton {
+                    image: Some(&gtk::Image::from_icon_name(Some("document-send"), gtk::IconSize::Button)),
+                    label: "Export",
+                    always_show_image: true,
+                    popup: Some(&export_menu),
+                },
+            },
+
+            #[name="status_label"]
+            gtk::Label {
+                halign: gtk::Align::Start,
+                margin_start: 10,
+                text: "Drag tracks here from any list",
+            },
+
+            gtk::ScrolledWindow {
+                vexpand: true,
+
+                #[name="set_view"]
+                gtk::TreeView {
+                    model: Some(&self.model.store),
+                    reorderable: true,
+                    expand: true,
+                },
+            },
+        }
+    }
+
+    fn model(relm: &Relm<Self>, (pool, spotify): (Handle, SpotifyRef)) -> SetsModel {
+        let store = gtk::ListStore::new(&[
+            u32::static_type(),    // index
+            u32::static_type(),    // position
+            String::static_type(), // start
+            String::static_type(), // name
+            String::static_type(), // artists
+            String::static_type(), // duration
+            String::static_type(), // bpm
+            String::static_type(), // key
+            String::static_type(), // bpm delta
+            String::static_type(), // key match
+            bool::static_type(),   // hard mix
+        ]);
+
+        SetsModel {
+            pool,
+            stream: relm.stream().clone(),
+            spotify,
+            store,
+            set: DjSet::default(),
+        }
+    }
+
+    fn update(&mut self, event: SetsMsg) {
+        use SetsMsg::*;
+
+        match event {
+            ShowTab => {
+                self.model.stream.emit(LoadNames);
+            }
+            LoadNames => {
+                self.spawn(async move |pool, (stream, spotify): (EventStream<_>, SpotifyRef)| {
+                    let names = pool.spawn(async move { spotify.read().await.get_set_names() }).await??;
+                    stream.emit(NewNames(names));
+                    Ok(())
+                });
+            }
+            NewNames(names) => {
+                self.sets_combo.remove_all();
+
+                for name in &names {
+                    self.sets_combo.append(Some(name), name);
+                }
+
+                if !self.model.set.name.is_empty() {
+                    self.sets_combo.set_active_id(Some(&self.model.set.name));
+                }
+            }
+            OpenSet(Some(name)) => {
+                if name == self.model.set.name {
+                    return;
+                }
+
+                self.spawn_args(
+                    name,
+                    async move |pool, (stream, spotify): (EventStream<_>, SpotifyRef), name| {
+                        if let Some(set) = pool.spawn(async move { spotify.read().await.get_set(&name) }).await?? {
+                            stream.emit(NewSet(Box::new(set)));
+                        }
+                        Ok(())
+                    },
+                );
+            }
+            OpenSet(None) => {}
+            NewSet(set) => {
+                self.name_entry.set_text(&set.name);
+                self.model.set = *set;
+                self.refresh_store();
+            }
+            CreateSet => {
+                self.sets_combo.set_active(None);
+                self.model.stream.emit(NewSet(Box::new(DjSet::default())));
+            }
+            SaveSet => {
+                let name = self.name_entry.get_text().trim().to_owned();
+
+                if name.is_empty() {
+                    self.model.stream.emit(Status("Enter set name to save it".to_owned()));
+                    return;
+                }
+
+                self.model.set.name = name;
+
+                let set = self.model.set.clone();
+
+                self.spawn_args(
+                    set,
+                    async move |pool, (stream, spotify): (EventStream<_>, SpotifyRef), set| {
+                        let name = set.name.clone();
+                        pool.spawn(async move { spotify.read().await.save_set(set) }).await??;
+                        stream.emit(Status(format!("Set \"{}\" saved", name)));
+                        stream.emit(LoadNames);
+                        Ok(())
+                    },
+                );
+            }
+            DeleteSet => {
+                let name = self.model.set.name.clone();
+
+                if name.is_empty() {
+                    return;
+                }
+
+                self.spawn_args(
+                    name,
+                    async move |pool, (stream, spotify): (EventStream<_>, SpotifyRef), name| {
+                        pool.spawn(async move { spotify.read().await.delete_set(&name) }).await??;
+                        stream.emit(NewSet(Box::new(DjSet::default())));
+                        stream.emit(LoadNames);
+                        Ok(())
+                    },
+                );
+            }
+            AddTracks(uris, position) => {
+                self.spawn_args(
+                    uris,
+                    async move |pool, (stream, spotify): (EventStream<_>, SpotifyRef), uris| {
+                        let tracks = pool
+                            .spawn(async move {
+                                let spotify = spotify.read().await;
+                                let tracks = spotify.get_tracks(&uris).await?;
+                                let feats = spotify.get_tracks_features(&uris).await?;
+                                let feats = feats.iter().map(|feat| (&*feat.uri, feat)).collect::<HashMap<_, _>>();
+
+                                Ok::<_, ClientError>(
+                                    tracks
+                                        .iter()
+                                        .map(|track| SetTrack::new(track, feats.get(&*track.uri).copied()))
+                                        .collect::<Vec<_>>(),
+                                )
+                            })
+                            .await??;
+
+                        stream.emit(NewTracks(tracks, position));
+                        Ok(())
+                    },
+                );
+            }
+            NewTracks(tracks, position) => {
+                let position = position.map_or(self.model.set.tracks.len(), |pos| pos.min(self.model.set.tracks.len()));
+
+                self.model.set.tracks.splice(position..position, tracks);
+                self.refresh_store();
+            }
+            RemoveChosenTracks => {
+                let (rows, model) = self.set_view.get_selection().get_selected_rows();
+                let removed = rows
+                    .into_iter()
+                    .filter_map(|path| model.get_iter(&path))
+                    .filter_map(|pos| model.get_value(&pos, COL_SET_INDEX as i32).get::<u32>().ok().flatten())
+                    .collect::<Vec<_>>();
+
+                let mut index = 0;
+                self.model.set.tracks.retain(|_| {
+                    index += 1;
+                    !removed.contains(&(index - 1))
+                });
+
+                self.refresh_store();
+            }
+            TracksReordered => {
+                let store = &self.model.store;
+                let mut order = Vec::with_capacity(self.model.set.tracks.len());
+
+                store.foreach(|store, _, pos| {
+                    if let Ok(Some(index)) = store.get_value(pos, COL_SET_INDEX as i32).get::<u32>() {
+                        order.push(index as usize);
+                    }
+                    false
+                });
+
+                let tracks = order
+                    .into_iter()
+                    .filter_map(|index| self.model.set.tracks.get(index).cloned())
+                    .collect::<Vec<_>>();
+
+                if tracks.len() == self.model.set.tracks.len() {
+                    self.model.set.tracks = tracks;
+                }
+
+                self.refresh_store();
+            }
+            ExportPlaylist => {
+                if self.model.set.tracks.is_empty() {
+                    return;
+                }
+
+                let set = self.model.set.clone();
+
+                self.spawn_args(
+                    set,
+                    async move |pool, (stream, spotify): (EventStream<_>, SpotifyRef), set| {
+                        let details = PlaylistDetails {
+                            name: if set.name.is_empty() {
+                                "DJ set".to_owned()
+                            } else {
+                                set.name.clone()
+                            },
+                            description: format!("{} tracks, {}", set.tracks.len(), humanize_time(set.duration_ms())),
+                            public: false,
+                            collaborative: false,
+                        };
+
+                        let playlist_name = details.name.clone();
+                        pool.spawn(async move {
+                            let spotify = spotify.read().await;
+                            let playlist = spotify.create_my_playlist(&details).await?;
+                            spotify.add_playlist_tracks(&playlist.uri, &set.uris(), None).await
+                        })
+                        .await??;
+
+                        stream.emit(Status(format!("Set exported to playlist \"{}\"", playlist_name)));
+                        Ok(())
+                    },
+                );
+            }
+            ExportFile(format) => {
+                if let Some(path) = self.choose_export_file(format) {
+                    let status = match std::fs::write(&path, format.render(&self.model.set)) {
+                        Ok(_) => format!("Set exported to {}", path.display()),
+                        Err(error) => format!("Failed to export set: {}", error),
+                    };
+
+                    self.model.stream.emit(Status(status));
+                }
+            }
+            Status(message) => {
+                self.status_label.set_text(&message);
+            }
+        }
+    }
+
+    fn init_view(&mut self) {
+        macro_rules! text_column {
+            ($title:literal, $column:expr, $align:expr) => {{
+                let cell = gtk::CellRendererText::new();
+                cell.set_alignment($align, 0.5);
+
+                let column = gtk::TreeViewColumnBuilder::new()
+                    .title($title)
+                    .resizable(true)
+                    .alignment($align)
+                    .build();
+
+                CellLayoutExt::pack_start(&column, &cell, true);
+                CellLayoutExt::add_attribute(&column, &cell, "text", $column as i32);
+                CellLayoutExt::add_attribute(&column, &cell, "strikethrough", COL_SET_HARD_MIX as i32);
+                self.set_view.append_column(&column);
+            }};
+        }
+
+        text_column!("#", COL_SET_POSITION, 1.0);
+        text_column!("Start", COL_SET_START, 1.0);
+        text_column!("Title", COL_SET_NAME, 0.0);
+        text_column!("Artists", COL_SET_ARTISTS, 0.0);
+        text_column!("Duration", COL_SET_DURATION, 1.0);
+        text_column!("BPM", COL_SET_BPM, 1.0);
+        text_column!("Key", COL_SET_KEY, 0.5);
+        text_column!("Δ BPM", COL_SET_BPM_DELTA, 1.0);
+        text_column!("Transition", COL_SET_KEY_MATCH, 0.0);
+
+        self.set_view.get_selection().set_mode(gtk::SelectionMode::Multiple);
+
+        self.set_view.enable_model_drag_dest(
+            &[
+                gtk::TargetEntry::new("GTK_TREE_MODEL_ROW", gtk::TargetFlags::SAME_WIDGET, 0),
+                gtk::TargetEntry::new("text/uri-list", gtk::TargetFlags::SAME_APP, TARGET_TRACK_URIS),
+            ],
+            gdk::DragAction::MOVE | gdk::DragAction::COPY,
+        );
+
+        {
+            let stream = self.model.stream.clone();
+
+            self.set_view
+                .connect_drag_data_received(move |view, context, x, y, data, info, time| {
+                    if info != TARGET_TRACK_URIS {
+                        return;
+                    }
+
+                    let position = view.get_dest_row_at_pos(x, y).and_then(|(path, drop)| {
+                        let index = *path?.get_indices().first()? as usize;
+
+                        Some(match drop {
+                            gtk::TreeViewDropPosition::Before | gtk::TreeViewDropPosition::IntoOrBefore => index,
+                            _ => index + 1,
+                        })
+                    });
+
+                    let uris = data.get_uris().into_iter().map(String::from).collect::<Vec<_>>();
+
+                    if !uris.is_empty() {
+                        stream.emit(SetsMsg::AddTracks(uris, position));
+                    }
+
+                    // Dropped tracks are added after loading, not by the store itself
+                    context.drag_finish(true, false, time);
+                    view.stop_signal_emission("drag-data-received");
+                });
+        }
+
+        {
+            let stream = self.model.stream.clone();
+
+            self.set_view
+                .connect_drag_end(move |_, _| stream.emit(SetsMsg::TracksReordered));
+        }
+    }
+
+    fn refresh_store(&self) {
+        let store = &self.model.store;
+        let set = &self.model.set;
+
+        store.clear();
+
+        for (index, (track, transition)) in set.tracks.iter().zip(set.transitions()).enumerate() {
+            store.insert_with_values(
+                None,
+                &[
+                    COL_SET_INDEX,
+                    COL_SET_POSITION,
+                    COL_SET_START,
+                    COL_SET_NAME,
+                    COL_SET_ARTISTS,
+                    COL_SET_DURATION,
+                    COL_SET_BPM,
+                    COL_SET_KEY,
+                    COL_SET_BPM_DELTA,
+                    COL_SET_KEY_MATCH,
+                    COL_SET_HARD_MIX,
+                ],
+                &[
+                    &(index as u32),
+                    &(index as u32 + 1),
+                    &humanize_time(transition.start_ms),
+                    &track.name,
+                    &track.artists,
+                    &humanize_time(track.duration_ms),
+                    &format!("{:.1}", track.tempo),
+                    &track.key_name(),
+                    &transition.tempo_delta_label(),
+                    &transition.key_match_label(index == 0),
+                    &!transition.compatible,
+                ],
+            );
+        }
+
+        self.status_label
+            .set_text(&format!("{} tracks, {}", set.tracks.len(), humanize_time(set.duration_ms())));
+    }
+
+    fn choose_export_file(&self, format: ExportFormat) -> Option<PathBuf> {
+        let window = self
+            .set_view
+            .get_toplevel()
+            .and_then(|widget| widget.downcast::<gtk::Window>().ok());
+
+        let dialog = gtk::FileChooserDialog::with_buttons(Some("Export set"), window.as_ref(), gtk::FileChooserAction::Save, &[
+            ("_Cancel", gtk::ResponseType::Cancel),
+            ("_Export", gtk::ResponseType::Accept),
+        ]);
+
+        let name = if self.model.set.name.is_empty() {
+            "set"
+        } else {
+            &self.model.set.name
+        };
+
+        dialog.set_do_overwrite_confirmation(true);
+        dialog.set_current_name(&format!("{}.{}", name, format.extension()));
+
+        let path = match dialog.run() {
+            gtk::ResponseType::Accept => dialog.get_filename(),
+            _ => None,
+        };
+
+        dialog.close();
+
+        path
+    }
+}
+
+impl Extract<EventStream<SetsMsg>> for SetsTab {
+    fn extract(&self) -> EventStream<SetsMsg> { self.model.stream.clone() }
+}
+
+impl Extract<SpotifyRef> for SetsTab {
+    fn extract(&self) -> SpotifyRef { self.model.spotify.clone() }
+}
+
+impl Spawn for SetsTab {
+    fn pool(&self) -> Handle { self.model.pool.clone() }
+}
@@ -1,10 +1,20 @@
 use crate::{
-    config::{Config, Settings, SettingsRef},
+    broadcast,
+    config::{Account, Config, SettingsRef},
+    models::{
+        crossfade::{self, FadeCurve},
+        mixing, KeyNotation,
+    },
+    observe,
     services::SpotifyRef,
     utils::{Extract, Spawn},
+    AppEvent,
 };
-use gtk::{self, BoxExt, ButtonExt, EntryExt, FrameExt, GridExt, LabelExt, LinkButtonExt, SwitchExt, WidgetExt};
-use relm::{EventStream, Relm, Widget};
+use gtk::{
+    self, BoxExt, ButtonExt, ComboBoxExt, ComboBoxTextExt, EntryExt, FrameExt, GridExt, LabelExt, LinkButtonExt, SpinButtonExt,
+    SwitchExt, WidgetExt,
+};
+use relm::{Channel, EventStream, Relm, Widget};
 use relm_derive::{widget, Msg};
 use rspotify::client::ClientError;
 use tokio::runtime::Handle;
@@ -14,8 +24,12 @@ pub enum SettingsMsg {
     ShowTab,
     GetAuthorizeUrl,
     SetAuthorizeUrl(String),
+    SwitchAccount(Option<String>),
+    AddAccount,
+    RemoveAccount,
     Reset,
     Save,
+    ClearCache,
 }
 
 pub struct SettingsModel {
@@ -39,34 +53,65 @@ impl Widget for SettingsTab {
                     row_spacing: 5,
                     column_spacing: 10,
 
-                    #[name="client_id_label"]
+                    #[name="account_label"]
                     gtk::Label {
                         cell: { left_attach: 0, top_attach: 0, },
                         halign: gtk::Align::Start,
+                        text_with_mnemonic: "_Account",
+                    },
+                    #[name="account_combo"]
+                    gtk::ComboBoxText {
+                        cell: { left_attach: 1, top_attach: 0, },
+                        changed(combo) => SettingsMsg::SwitchAccount(combo.get_active_id().map(String::from)),
+                    },
+                    gtk::Button {
+                        cell: { left_attach: 2, top_attach: 0, },
+                        label: "Remove",
+                        sensitive: self.model.settings.read().unwrap().account().is_some(),
+                        clicked(_) => SettingsMsg::RemoveAccount,
+                    },
+
+                    #[name="new_account_entry"]
+                    gtk::Entry {
+                        cell: { left_attach: 1, top_attach: 1, },
+                        placeholder_text: Some("New account name"),
+                        activate(_) => SettingsMsg::AddAccount,
+                    },
+                    gtk::Button {
+                        cell: { left_attach: 2, top_attach: 1, },
+                        label: "Add",
+                        clicked(_) => SettingsMsg::AddAccount,
+                    },
+
+                    #[name="client_id_label"]
+                    gtk::Label {
+                        cell: { left_attach: 0, top_attach: 2, },
+                        halign: gtk::Align::Start,
                         text_with_mnemonic: "Client _ID",
                     },
                     #[name="client_id_entry"]
                     gtk::Entry {
-                        cell: { left_attach: 1, top_attach: 0, },
-                        text: &self.model.settings.read().unwrap().client_id,
+                        cell: { left_attach: 1, top_attach: 2, width: 2, },
+                        text: self.model.settings.read().unwrap().client_id(),
                         hexpand: true,
                     },
 
                     #[name="client_secret_label"]
                     gtk::Label {
-                        cell: { left_attach: 0, top_attach: 1, },
+                        cell: { left_attach: 0, top_attach: 3, },
                         halign: gtk::Align::Start,
                         text_with_mnemonic: "Client _Secret",
                     },
                     #[name="client_secret_entry"]
                     gtk::Entry {
-                        cell: { left_attach: 1, top_attach: 1, },
-                        text: &self.model.settings.read().unwrap().client_secret,
+                        cell: { left_attach: 1, top_attach: 3, width: 2, },
+                        text: self.model.settings.read().unwrap().client_secret(),
+                        placeholder_text: Some("Not needed to log in with PKCE"),
                     },
 
                     #[name="client_auth_url_btn"]
                     gtk::LinkButton {
-                        cell: { left_attach: 1, top_attach: 2, width: 2, },
+                        cell: { left_attach: 1, top_attach: 4, width: 2, },
                         label: "Open authorization URL",
                         halign: gtk::Align::Start,
                     },
@@ -94,6 +139,93 @@ impl Widget for SettingsTab {
                         active: self.model.settings.read().unwrap().show_notifications,
                         halign: gtk::Align::End,
                     },
+
+                    gtk::Label {
+                        halign: gtk::Align::Start,
+                        cell: { left_attach: 0, top_attach: 1, },
+                        text: "Key notation",
+                    },
+                    #[name="key_notation_combo"]
+                    gtk::ComboBoxText {
+                        cell: { left_attach: 1, top_attach: 1, },
+                        halign: gtk::Align::End,
+                    },
+
+                    gtk::Label {
+                        halign: gtk::Align::Start,
+                        cell: { left_attach: 0, top_attach: 2, },
+                        text: "Compatible tracks BPM tolerance, %",
+                    },
+                    #[name="tempo_tolerance_spin"]
+                    gtk::SpinButton {
+                        cell: { left_attach: 1, top_attach: 2, },
+                        adjustment: &gtk::Adjustment::new(0.0, 0.0, 50.0, 0.5, 1.0, 0.0),
+                        digits: 1,
+                        value: self.model.settings.read().unwrap().tempo_tolerance as f64,
+                        halign: gtk::Align::End,
+                    },
+
+                    gtk::Label {
+                        halign: gtk::Align::Start,
+                        cell: { left_attach: 0, top_attach: 3, },
+                        text: "Crossfade by volume at track end",
+                    },
+                    #[name="crossfade_switch"]
+                    gtk::Switch {
+                        cell: { left_attach: 1, top_attach: 3, },
+                        active: self.model.settings.read().unwrap().crossfade,
+                        halign: gtk::Align::End,
+                    },
+
+                    gtk::Label {
+                        halign: gtk::Align::Start,
+                        cell: { left_attach: 0, top_attach: 4, },
+                        text: "Crossfade length, s",
+                    },
+                    #[name="crossfade_length_spin"]
+                    gtk::SpinButton {
+                        cell: { left_attach: 1, top_attach: 4, },
+                        adjustment: &gtk::Adjustment::new(0.0, 1.0, 15.0, 0.5, 1.0, 0.0),
+                        digits: 1,
+                        value: self.model.settings.read().unwrap().crossfade_ms as f64 / 1000.0,
+                        halign: gtk::Align::End,
+                    },
+
+                    gtk::Label {
+                        halign: gtk::Align::Start,
+                        cell: { left_attach: 0, top_attach: 5, },
+                        text: "Crossfade curve",
+                    },
+                    #[name="crossfade_curve_combo"]
+                    gtk::ComboBoxText {
+                        cell: { left_attach: 1, top_attach: 5, },
+                        halign: gtk::Align::End,
+                    },
+                },
+            },
+
+            gtk::Frame {
+                label: Some("Cache"),
+                margin_top: 10, margin_bottom: 10, margin_start: 10, margin_end: 10,
+
+                gtk::Grid {
+                    column_homogeneous: true,
+                    margin_top: 10, margin_bottom: 10, margin_start: 10, margin_end: 10,
+                    row_spacing: 5,
+                    column_spacing: 10,
+                    hexpand: true,
+
+                    gtk::Label {
+                        halign: gtk::Align::Start,
+                        cell: { left_attach: 0, top_attach: 0, },
+                        text: "Cached tracks and audio features",
+                    },
+                    gtk::Button {
+                        cell: { left_attach: 1, top_attach: 0, },
+                        label: "Clear cache",
+                        halign: gtk::Align::End,
+                        clicked(_) => SettingsMsg::ClearCache,
+                    },
                 },
             },
 
@@ -129,6 +261,21 @@ impl Widget for SettingsTab {
         }
     }
 
+    fn subscriptions(&mut self, relm: &Relm<Self>) {
+        let stream = relm.stream().clone();
+
+        let (_, stream_tx) = Channel::new(move |msg| {
+            stream.emit(msg);
+        });
+
+        // Finished login attempt leaves the shown authorization URL expired
+        observe(&self.model.pool, move |event| {
+            if matches!(event, AppEvent::SpotifyLoginSucceeded | AppEvent::SpotifyLoginFailed(_)) {
+                let _ = stream_tx.send(SettingsMsg::GetAuthorizeUrl);
+            }
+        });
+    }
+
     fn update(&mut self, event: SettingsMsg) {
         use SettingsMsg::*;
 
@@ -137,11 +284,14 @@ impl Widget for SettingsTab {
                 // Hacky method to make code generator create set_text() method calls
                 self.model.settings = self.model.settings.clone();
 
+                self.load_key_notation();
+                self.load_crossfade_curve();
+
                 self.model.stream.emit(GetAuthorizeUrl);
             }
             GetAuthorizeUrl => {
                 self.spawn(async move |pool, (stream, spotify): (EventStream<_>, SpotifyRef)| {
-                    let auth_url = pool.spawn(async move { spotify.read().await.get_authorize_url() }).await??;
+                    let auth_url = pool.spawn(async move { spotify.write().await.get_authorize_url() }).await??;
 
                     stream.emit(SetAuthorizeUrl(auth_url));
 
@@ -153,6 +303,55 @@ impl Widget for SettingsTab {
 
                 self.client_auth_url_btn.set_visible(true);
             }
+            SwitchAccount(Some(name)) => {
+                if self.model.settings.read().unwrap().active_account != name {
+                    self.model.settings.write().unwrap().active_account = name;
+                    self.switch_account();
+                }
+            }
+            SwitchAccount(None) => {}
+            AddAccount => {
+                let name = self.new_account_entry.get_text().trim().to_owned();
+                let exists = self
+                    .model
+                    .settings
+                    .read()
+                    .unwrap()
+                    .accounts
+                    .iter()
+                    .any(|account| account.name == name);
+
+                if !name.is_empty() && !exists {
+                    self.new_account_entry.set_text("");
+                    self.model.settings.write().unwrap().accounts.push(Account {
+                        name: name.clone(),
+                        ..Account::default()
+                    });
+
+                    self.load_accounts();
+                    self.account_combo.set_active_id(Some(&name));
+                }
+            }
+            RemoveAccount => {
+                let removed = {
+                    let mut settings = self.model.settings.write().unwrap();
+                    let name = std::mem::take(&mut settings.active_account);
+                    settings.accounts.retain(|account| account.name != name);
+                    settings.active_account = settings
+                        .accounts
+                        .first()
+                        .map(|account| account.name.clone())
+                        .unwrap_or_default();
+                    name
+                };
+
+                if let Err(error) = std::fs::remove_file(self.model.config.spotify_token_file(&removed)) {
+                    debug!("no token file removed: {}", error);
+                }
+
+                self.load_accounts();
+                self.switch_account();
+            }
             Reset => {
                 // Hacky method to make code generator create set_text() method calls
                 let settings = self.model.settings.clone();
@@ -160,22 +359,58 @@ impl Widget for SettingsTab {
                 *settings.write().unwrap() = self.model.config.load_settings();
 
                 self.model.settings = settings;
+
+                self.load_key_notation();
+                self.load_crossfade_curve();
+                self.load_accounts();
             }
             Save => {
                 self.save_settings();
             }
+            ClearCache => {
+                self.spawn(async move |pool, spotify: SpotifyRef| {
+                    pool.spawn(async move { spotify.read().await.clear_tracks_cache() }).await??;
+                    Ok(())
+                });
+            }
         }
     }
 
     fn save_settings(&mut self) {
-        let new_settings = Settings {
-            client_id: self.client_id_entry.get_text().into(),
-            client_secret: self.client_secret_entry.get_text().into(),
-            show_notifications: self.show_notifications_switch.get_active(),
-        };
+        let mut new_settings = self.model.settings.read().unwrap().clone();
+
+        new_settings.show_notifications = self.show_notifications_switch.get_active();
+        new_settings.key_notation = self
+            .key_notation_combo
+            .get_active_id()
+            .and_then(|id| KeyNotation::from_name(&id))
+            .unwrap_or_default();
+        new_settings.tempo_tolerance = self.tempo_tolerance_spin.get_value() as f32;
+        new_settings.crossfade = self.crossfade_switch.get_active();
+        new_settings.crossfade_ms = (self.crossfade_length_spin.get_value() * 1000.0).round() as u32;
+        new_settings.crossfade_curve = self
+            .crossfade_curve_combo
+            .get_active_id()
+            .and_then(|id| FadeCurve::from_name(&id))
+            .unwrap_or_default();
+
+        if new_settings.account().is_none() {
+            let account = Account::default();
+            new_settings.active_account = account.name.clone();
+            new_settings.accounts.push(account);
+        }
+
+        if let Some(account) = new_settings.account_mut() {
+            account.client_id = self.client_id_entry.get_text().into();
+            account.client_secret = self.client_secret_entry.get_text().into();
+        }
 
         self.model.config.save_settings(&new_settings).expect("error saving settings");
 
+        KeyNotation::set_current(new_settings.key_notation);
+        mixing::set_tempo_tolerance(new_settings.tempo_tolerance);
+        crossfade::set_config(new_settings.crossfade());
+
         let settings = self.model.settings.clone();
 
         self.spawn_args(
@@ -186,7 +421,7 @@ impl Widget for SettingsTab {
                         let auth_url = spotify
                             .write()
                             .await
-                            .setup_client(new_settings.client_id.clone(), new_settings.client_secret.clone())?;
+                            .setup_client(new_settings.client_id().to_owned(), new_settings.client_secret().to_owned())?;
 
                         *settings.write().unwrap() = new_settings;
 
@@ -201,10 +436,84 @@ impl Widget for SettingsTab {
         );
     }
 
+    /// Rebuilds Spotify client for the active account and makes all tabs reload
+    fn switch_account(&mut self) {
+        if let Err(error) = self.model.config.save_settings(&self.model.settings.read().unwrap()) {
+            error!("error saving settings: {}", error);
+        }
+
+        // Hacky method to make code generator create set_text() method calls
+        self.model.settings = self.model.settings.clone();
+
+        let account = self.model.settings.read().unwrap().account().cloned().unwrap_or_default();
+        let token_file = self.model.config.spotify_token_file(&account.name);
+        let config = self.model.config.clone();
+
+        self.spawn_args(
+            (account, token_file, config),
+            async move |pool, (stream, spotify): (EventStream<_>, SpotifyRef), (account, token_file, config)| {
+                let auth_url = pool
+                    .spawn(async move {
+                        let mut spotify = spotify.write().await;
+                        spotify.open_account_storage(&config, &account.name);
+                        spotify
+                            .switch_account(account.client_id, account.client_secret, token_file)
+                            .await
+                    })
+                    .await??;
+
+                stream.emit(SettingsMsg::SetAuthorizeUrl(auth_url));
+                let _ = broadcast(AppEvent::SpotifyAccountSwitched);
+
+                Ok(())
+            },
+        );
+    }
+
+    fn load_accounts(&self) {
+        let settings = self.model.settings.read().unwrap();
+
+        self.account_combo.remove_all();
+
+        for account in &settings.accounts {
+            self.account_combo.append(Some(&account.name), &account.name);
+        }
+
+        self.account_combo.set_active_id(Some(&settings.active_account));
+    }
+
     fn init_view(&mut self) {
+        self.account_label.set_mnemonic_widget(Some(&self.account_combo));
+
         self.client_id_label.set_mnemonic_widget(Some(&self.client_id_entry));
 
         self.client_secret_label.set_mnemonic_widget(Some(&self.client_secret_entry));
+
+        self.key_notation_combo
+            .append(Some(KeyNotation::Camelot.as_str()), "Camelot (8A)");
+        self.key_notation_combo
+            .append(Some(KeyNotation::OpenKey.as_str()), "Open Key (1m)");
+
+        self.crossfade_curve_combo.append(Some(FadeCurve::Linear.as_str()), "Linear");
+        self.crossfade_curve_combo
+            .append(Some(FadeCurve::EqualPower.as_str()), "Equal power");
+        self.crossfade_curve_combo.append(Some(FadeCurve::SCurve.as_str()), "S-curve");
+
+        self.load_key_notation();
+        self.load_crossfade_curve();
+        self.load_accounts();
+    }
+
+    fn load_key_notation(&self) {
+        let notation = self.model.settings.read().unwrap().key_notation;
+
+        self.key_notation_combo.set_active_id(Some(notation.as_str()));
+    }
+
+    fn load_crossfade_curve(&self) {
+        let curve = self.model.settings.read().unwrap().crossfade_curve;
+
+        self.crossfade_curve_combo.set_active_id(Some(curve.as_str()));
     }
 }
 
@@ -40,6 +40,13 @@ impl Widget for ShowsTab {
             ShowTab => {
                 self.shows_view.emit(ContainerMsg::Load(()));
             }
+            Reset => {
+                self.shows_view.emit(ContainerMsg::Reset);
+
+                self.tracks_view.emit(ContainerMsg::Reset.into());
+
+                self.stack.set_visible_child(self.shows_view.widget());
+            }
             OpenContainer(0, uri, name) => {
                 self.tracks_view.emit(ContainerMsg::Load(uri).into());
 
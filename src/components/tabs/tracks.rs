@@ -47,6 +47,13 @@ impl Widget for TracksTab {
 
                 self.top_tracks_view.emit(ContainerMsg::Load(()).into());
             }
+            Reset => {
+                self.saved_tracks_view.emit(ContainerMsg::Reset.into());
+
+                self.top_tracks_view.emit(ContainerMsg::Reset.into());
+
+                self.stack.set_visible_child(self.saved_tracks_view.widget());
+            }
             GoToTrack(uri) => {
                 self.saved_tracks_view.emit(ContainerMsg::Load(()).into());
 
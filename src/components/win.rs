@@ -5,6 +5,7 @@ use std::sync::{Arc, RwLock};
 
 use crate::{
     components::{
+        analysis::{AnalysisMsg, AnalysisView},
         media_controls::{MediaControls, MediaControlsMsg},
         notifier::{Notifier, NotifierMsg},
         tabs::{
@@ -18,6 +19,7 @@ use crate::{
             queue::QueueTab,
             recent::RecentTab,
             search::{SearchMsg, SearchTab},
+            sets::{SetsMsg, SetsTab},
             settings::{SettingsMsg, SettingsTab},
             shows::ShowsTab,
             tracks::TracksTab,
@@ -25,6 +27,7 @@ use crate::{
         },
     },
     config::{Settings, SettingsRef},
+    models::{crossfade, mixing, KeyNotation},
     observe,
     services::spotify::SpotifyRef,
     AppEvent,
@@ -47,6 +50,7 @@ pub struct State {
 pub enum Msg {
     ChangeTab(Option<glib::GString>),
     GoToTab(Tab),
+    ResetTabs,
     Quit,
 }
 
@@ -64,6 +68,7 @@ pub enum Tab {
     Featured,
     NewReleases,
     Devices,
+    Sets,
     Settings,
 }
 
@@ -96,6 +101,9 @@ impl Widget for Win {
                                 widget_name: "media_controls",
                             },
 
+                            #[name="analysis_view"]
+                            AnalysisView((self.model.pool.clone(), self.model.spotify.clone())),
+
                             #[name="stack"]
                             gtk::Stack {
                                 vexpand: true,
@@ -210,6 +218,15 @@ impl Widget for Win {
                                     },
                                 },
 
+                                #[name="sets_tab"]
+                                SetsTab((self.model.pool.clone(), self.model.spotify.clone())) {
+                                    widget_name: "sets_tab",
+                                    child: {
+                                        name: Some("sets_tab"),
+                                        title: Some("\u{1F3A7} Set"),
+                                    },
+                                },
+
                                 #[name="settings_tab"]
                                 SettingsTab((self.model.pool.clone(), self.model.spotify.clone(), self.model.settings.clone())) {
                                     widget_name: "settings_tab",
@@ -284,6 +301,10 @@ impl Widget for Win {
 
         let stream = relm.stream().clone();
 
+        KeyNotation::set_current(params.settings.key_notation);
+        mixing::set_tempo_tolerance(params.settings.tempo_tolerance);
+        crossfade::set_config(params.settings.crossfade());
+
         State {
             settings: Arc::new(RwLock::new(params.settings)),
             spotify: params.spotify,
@@ -309,10 +330,28 @@ impl Widget for Win {
                     Tab::Artists => self.artists_tab.widget(),
                     Tab::Shows => self.shows_tab.widget(),
                     Tab::Categories => self.categories_tab.widget(),
+                    Tab::Sets => self.sets_tab.widget(),
                     Tab::Settings => self.settings_tab.widget(),
                     _ => self.search_tab.widget(),
                 });
             }
+            ResetTabs => {
+                self.recent_tab.emit(MusicTabMsg::Reset);
+                self.queue_tab.emit(MusicTabMsg::Reset);
+                self.tracks_tab.emit(MusicTabMsg::Reset);
+                self.playlists_tab.emit(MusicTabMsg::Reset);
+                self.artists_tab.emit(MusicTabMsg::Reset);
+                self.albums_tab.emit(MusicTabMsg::Reset);
+                self.shows_tab.emit(MusicTabMsg::Reset);
+                self.categories_tab.emit(MusicTabMsg::Reset);
+                self.featured_tab.emit(MusicTabMsg::Reset);
+                self.new_releases_tab.emit(MusicTabMsg::Reset);
+
+                self.media_controls.emit(MediaControlsMsg::Reload);
+
+                // Visible tab is loaded again right away, the others once they are shown
+                self.model.stream.emit(ChangeTab(self.stack.get_visible_child_name()));
+            }
             ChangeTab(widget_name) => match widget_name.as_deref() {
                 Some("recent_tab") => {
                     self.recent_tab.emit(MusicTabMsg::ShowTab);
@@ -320,6 +359,9 @@ impl Widget for Win {
                 Some("queue_tab") => {
                     self.queue_tab.emit(MusicTabMsg::ShowTab);
                 }
+                Some("sets_tab") => {
+                    self.sets_tab.emit(SetsMsg::ShowTab);
+                }
                 Some("settings_tab") => {
                     self.settings_tab.emit(SettingsMsg::ShowTab);
                 }
@@ -398,6 +440,37 @@ impl Widget for Win {
                     timeout_ms: 5000,
                 });
             }
+            AppEvent::SpotifyLoginSucceeded => {
+                let _ = notifier_tx.send(NotifierMsg::Notify {
+                    message: "Logged in to Spotify".to_owned(),
+                    kind: gtk::MessageType::Info,
+                    timeout_ms: 3000,
+                });
+            }
+            AppEvent::SpotifyLoginFailed(msg) => {
+                let _ = notifier_tx.send(NotifierMsg::Notify {
+                    message: format!("Login failed: {}", msg),
+                    kind: gtk::MessageType::Error,
+                    timeout_ms: 5000,
+                });
+            }
+            AppEvent::SpotifyAccountSwitched => {
+                let _ = notifier_tx.send(NotifierMsg::Notify {
+                    message: "Switched Spotify account".to_owned(),
+                    kind: gtk::MessageType::Info,
+                    timeout_ms: 3000,
+                });
+
+                let _ = stream_tx.send(Msg::ResetTabs);
+            }
+            AppEvent::SpotifyRateLimited(timeout) => {
+                let _ = notifier_tx.send(NotifierMsg::Notify {
+                    message: format!("Rate limited by Spotify, retrying in {}s", timeout.as_secs()),
+                    kind: gtk::MessageType::Info,
+                    timeout_ms: timeout.as_millis().max(1000).min(10000) as u32,
+                });
+            }
+            _ => {}
         });
 
         /*
@@ -437,7 +510,23 @@ impl Widget for Win {
         let shows_stream = self.shows_tab.stream().clone();
         let stream = self.model.stream.clone();
 
+        {
+            let media_controls_stream = self.media_controls.stream().clone();
+
+            self.analysis_view.stream().observe(move |msg| {
+                if let AnalysisMsg::Seeked = msg {
+                    media_controls_stream.emit(MediaControlsMsg::LoadState);
+                }
+            });
+        }
+
+        let analysis_stream = self.analysis_view.stream().clone();
+
         self.media_controls.stream().observe(move |msg| {
+            if let MediaControlsMsg::ShowAnalysis(uri, name) = msg {
+                analysis_stream.emit(AnalysisMsg::Load(uri.clone(), name.clone()));
+            }
+
             if let MediaControlsMsg::GoToTrack(kind, uri, context_info) = msg {
                 let (tab, tab_stream) = match kind {
                     Type::Album => (Tab::Albums, &albums_stream),
@@ -467,6 +556,7 @@ impl Widget for Win {
                 let media_controls_stream = self.$media_controls.stream().clone();
                 let artists_stream = self.artists_tab.stream().clone();
                 let albums_stream = self.albums_tab.stream().clone();
+                let analysis_stream = self.analysis_view.stream().clone();
                 let stream = self.model.stream.clone();
                 self.$tab.stream().observe(move |msg| {
                     match msg {
@@ -481,6 +571,9 @@ impl Widget for Win {
                             albums_stream.emit(MusicTabMsg::OpenContainer(0, uri.clone(), name.clone()));
                             stream.emit(Msg::GoToTab(Tab::Albums));
                         }
+                        MusicTabMsg::ShowAnalysis(uri, name) => {
+                            analysis_stream.emit(AnalysisMsg::Load(uri.clone(), name.clone()));
+                        }
                         _ => {}
                     }
                 });
@@ -488,6 +581,38 @@ impl Widget for Win {
             }}
         }
 
+        {
+            let media_controls_stream = self.media_controls.stream().clone();
+            let albums_stream = self.albums_tab.stream().clone();
+            let artists_stream = self.artists_tab.stream().clone();
+            let playlists_stream = self.playlists_tab.stream().clone();
+            let shows_stream = self.shows_tab.stream().clone();
+            let analysis_stream = self.analysis_view.stream().clone();
+            let stream = self.model.stream.clone();
+
+            self.search_tab.stream().observe(move |msg| match msg {
+                SearchMsg::PlaybackUpdate => {
+                    media_controls_stream.emit(MediaControlsMsg::LoadState);
+                }
+                SearchMsg::GoTo(kind, uri, name) => {
+                    let (tab, tab_stream) = match kind {
+                        Type::Album => (Tab::Albums, &albums_stream),
+                        Type::Artist => (Tab::Artists, &artists_stream),
+                        Type::Playlist => (Tab::Playlists, &playlists_stream),
+                        Type::Show => (Tab::Shows, &shows_stream),
+                        _ => return,
+                    };
+
+                    tab_stream.emit(MusicTabMsg::OpenContainer(0, uri.clone(), name.clone()));
+                    stream.emit(Msg::GoToTab(tab));
+                }
+                SearchMsg::ShowAnalysis(uri, name) => {
+                    analysis_stream.emit(AnalysisMsg::Load(uri.clone(), name.clone()));
+                }
+                _ => {}
+            });
+        }
+
         connect_playback_update!(media_controls => (
             albums_tab, artists_tab, categories_tab, tracks_tab,
             featured_tab, new_releases_tab, queue_tab, recent_tab, shows_tab,
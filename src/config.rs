@@ -1,3 +1,8 @@
+use crate::models::{
+    crossfade::{CrossfadeConfig, FadeCurve, DEFAULT_FADE_MS},
+    mixing::DEFAULT_TEMPO_TOLERANCE,
+    KeyNotation,
+};
 use directories::ProjectDirs;
 use serde::{Deserialize, Serialize};
 use std::{
@@ -9,30 +14,119 @@ use std::{
 
 const SETTINGS_FILE: &str = "settings.toml";
 
-const SPOTIFY_TOKEN_FILE: &str = "token.json";
+/// Token file of the only account before multiple accounts were supported
+const LEGACY_SPOTIFY_TOKEN_FILE: &str = "token.json";
+
+const DEFAULT_ACCOUNT: &str = "default";
 
 const THUMB_CACHE_DIR: &str = "thumbs";
 
+const TRACKS_CACHE_DIR: &str = "tracks";
+
+const LIBRARY_MIRROR_DIR: &str = "library";
+
+const PLAYBACK_QUEUE_DIR: &str = "queue";
+
+const SETS_DIR: &str = "sets";
+
+/// Data of every account is kept in its own subdirectory of this one
+const ACCOUNTS_DIR: &str = "accounts";
+
+const CONTROL_SOCKET_FILE: &str = "control.sock";
+
+/// Spotify account with its own token cache
 #[derive(Clone, Deserialize, Serialize)]
-pub struct Settings {
+pub struct Account {
+    pub name: String,
     pub client_id: String,
+    /// Authorization with PKCE is used if empty
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub client_secret: String,
+}
+
+impl Default for Account {
+    fn default() -> Self {
+        Self {
+            name: DEFAULT_ACCOUNT.to_owned(),
+            client_id: String::new(),
+            client_secret: String::new(),
+        }
+    }
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct Settings {
     #[serde(default)]
     pub show_notifications: bool,
+    #[serde(default)]
+    pub key_notation: KeyNotation,
+    #[serde(default = "default_tempo_tolerance")]
+    pub tempo_tolerance: f32,
+    #[serde(default)]
+    pub crossfade: bool,
+    /// Length of each of crossfade volume ramps, in milliseconds
+    #[serde(default = "default_crossfade_ms")]
+    pub crossfade_ms: u32,
+    #[serde(default)]
+    pub crossfade_curve: FadeCurve,
+    #[serde(default)]
+    pub active_account: String,
+    /// Credentials of the only account saved by older versions, moved to
+    /// `accounts` on load
+    #[serde(default, skip_serializing)]
+    client_id: String,
+    #[serde(default, skip_serializing)]
+    client_secret: String,
+    // Tables must go after plain values in TOML
+    #[serde(default)]
+    pub accounts: Vec<Account>,
 }
 
+fn default_tempo_tolerance() -> f32 { DEFAULT_TEMPO_TOLERANCE }
+
+fn default_crossfade_ms() -> u32 { DEFAULT_FADE_MS }
+
 pub type SettingsRef = Arc<RwLock<Settings>>;
 
 impl Default for Settings {
     fn default() -> Self {
         Self {
+            show_notifications: true,
+            key_notation: KeyNotation::default(),
+            tempo_tolerance: DEFAULT_TEMPO_TOLERANCE,
+            crossfade: false,
+            crossfade_ms: DEFAULT_FADE_MS,
+            crossfade_curve: FadeCurve::default(),
+            active_account: String::new(),
             client_id: String::new(),
             client_secret: String::new(),
-            show_notifications: true,
+            accounts: Vec::new(),
+        }
+    }
+}
+
+impl Settings {
+    pub fn account(&self) -> Option<&Account> { self.accounts.iter().find(|account| account.name == self.active_account) }
+
+    pub fn account_mut(&mut self) -> Option<&mut Account> {
+        let name = &self.active_account;
+        self.accounts.iter_mut().find(|account| &account.name == name)
+    }
+
+    pub fn client_id(&self) -> &str { self.account().map_or("", |account| &account.client_id) }
+
+    pub fn client_secret(&self) -> &str { self.account().map_or("", |account| &account.client_secret) }
+
+    pub fn crossfade(&self) -> CrossfadeConfig {
+        CrossfadeConfig {
+            enabled: self.crossfade,
+            fade_ms: self.crossfade_ms,
+            curve: self.crossfade_curve,
         }
     }
 }
 
+#[derive(Clone)]
 pub struct Config {
     dirs: ProjectDirs,
 }
@@ -58,14 +152,98 @@ impl Config {
 
     pub fn config_file(&self) -> PathBuf { self.dirs.config_dir().join(SETTINGS_FILE) }
 
-    pub fn spotify_token_file(&self) -> PathBuf { self.dirs.cache_dir().join(SPOTIFY_TOKEN_FILE) }
+    pub fn spotify_token_file(&self, account: &str) -> PathBuf {
+        self.dirs
+            .cache_dir()
+            .join(format!("token-{}.json", account_file_name(account)))
+    }
+
+    /// Token file name used before it had a hash of account name, so names
+    /// different only in special characters shared it
+    fn unhashed_spotify_token_file(&self, account: &str) -> PathBuf {
+        self.dirs
+            .cache_dir()
+            .join(format!("token-{}.json", sanitize_file_name(account)))
+    }
+
+    fn account_data_dir(&self, account: &str) -> PathBuf {
+        self.dirs.data_dir().join(ACCOUNTS_DIR).join(account_file_name(account))
+    }
 
     pub fn load_settings(&self) -> Settings {
-        self.try_load_settings()
+        let mut settings = self
+            .try_load_settings()
             .map_err(|error| {
                 error!("failed to read settings file: {:?}", error);
             })
-            .unwrap_or_default()
+            .unwrap_or_default();
+
+        self.migrate_legacy_account(&mut settings);
+        self.migrate_unhashed_token_files(&settings);
+        self.migrate_shared_data(settings.account().map_or(DEFAULT_ACCOUNT, |account| &account.name));
+
+        settings
+    }
+
+    /// Turns credentials saved before multiple accounts were supported into the
+    /// default account
+    fn migrate_legacy_account(&self, settings: &mut Settings) {
+        if settings.client_id.is_empty() || !settings.accounts.is_empty() {
+            return;
+        }
+
+        settings.accounts.push(Account {
+            name: DEFAULT_ACCOUNT.to_owned(),
+            client_id: std::mem::take(&mut settings.client_id),
+            client_secret: std::mem::take(&mut settings.client_secret),
+        });
+        settings.active_account = DEFAULT_ACCOUNT.to_owned();
+
+        let legacy_token_file = self.dirs.cache_dir().join(LEGACY_SPOTIFY_TOKEN_FILE);
+
+        if legacy_token_file.exists() {
+            if let Err(error) = std::fs::rename(&legacy_token_file, self.spotify_token_file(DEFAULT_ACCOUNT)) {
+                warn!("failed to move token file to default account: {}", error);
+            }
+        }
+    }
+
+    /// Moves token files to their names with account name hashes, unless
+    /// several accounts shared the same file
+    fn migrate_unhashed_token_files(&self, settings: &Settings) {
+        for account in &settings.accounts {
+            let old_file = self.unhashed_spotify_token_file(&account.name);
+            let new_file = self.spotify_token_file(&account.name);
+            let shared = settings
+                .accounts
+                .iter()
+                .filter(|other| self.unhashed_spotify_token_file(&other.name) == old_file)
+                .count()
+                > 1;
+
+            if old_file.exists() && !new_file.exists() && !shared {
+                if let Err(error) = std::fs::rename(&old_file, &new_file) {
+                    warn!("failed to move token file of account {}: {}", account.name, error);
+                }
+            }
+        }
+    }
+
+    /// Moves library mirror, playback queue and sets saved before they were
+    /// kept per account to the active account
+    fn migrate_shared_data(&self, account: &str) {
+        let account_dir = self.account_data_dir(account);
+
+        for name in &[LIBRARY_MIRROR_DIR, PLAYBACK_QUEUE_DIR, SETS_DIR] {
+            let old_dir = self.dirs.data_dir().join(name);
+            let new_dir = account_dir.join(name);
+
+            if old_dir.exists() && !new_dir.exists() {
+                if let Err(error) = std::fs::create_dir_all(&account_dir).and_then(|_| std::fs::rename(&old_dir, &new_dir)) {
+                    warn!("failed to move {} to account {}: {}", name, account, error);
+                }
+            }
+        }
     }
 
     fn try_load_settings(&self) -> Result<Settings, Error> {
@@ -97,4 +275,50 @@ impl Config {
 
         dir
     }
+
+    pub fn tracks_cache_dir(&self) -> PathBuf { self.dirs.cache_dir().join(TRACKS_CACHE_DIR) }
+
+    pub fn library_mirror_dir(&self, account: &str) -> PathBuf { self.account_data_dir(account).join(LIBRARY_MIRROR_DIR) }
+
+    pub fn playback_queue_dir(&self, account: &str) -> PathBuf { self.account_data_dir(account).join(PLAYBACK_QUEUE_DIR) }
+
+    pub fn sets_dir(&self, account: &str) -> PathBuf { self.account_data_dir(account).join(SETS_DIR) }
+
+    /// Socket of local control API, in runtime dir if there is one
+    pub fn control_socket_file(&self) -> PathBuf {
+        self.dirs
+            .runtime_dir()
+            .unwrap_or_else(|| self.dirs.cache_dir())
+            .join(CONTROL_SOCKET_FILE)
+    }
+}
+
+fn sanitize_file_name(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_alphanumeric() || c == '-' { c } else { '_' })
+        .collect()
+}
+
+/// Account name turned into a file name, with a hash of the name itself, so
+/// that names different only in special characters get different files
+fn account_file_name(account: &str) -> String {
+    let hash = format!("{:x}", md5::compute(account));
+    format!("{}-{}", sanitize_file_name(account), &hash[..8])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn similar_account_names_get_different_files() {
+        assert_ne!(account_file_name("work/dj"), account_file_name("work dj"));
+        assert_ne!(account_file_name("work/dj"), account_file_name("work_dj"));
+    }
+
+    #[test]
+    fn account_file_name_is_stable_and_readable() {
+        assert_eq!(account_file_name("work dj"), account_file_name("work dj"));
+        assert!(account_file_name("work dj").starts_with("work_dj-"));
+    }
 }
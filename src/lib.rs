@@ -11,6 +11,7 @@
 #[macro_use]
 extern crate log;
 
+mod cli;
 mod components;
 mod config;
 mod loaders;
@@ -19,11 +20,19 @@ mod scopes;
 mod services;
 mod utils;
 
+pub use cli::Cli;
 pub use components::win::{Params, Win};
 pub use config::Config;
-pub use services::{LoginService, RefreshTokenService, Spotify};
+pub use services::{
+    api, ControlService, CrossfadeService, LibraryMirror, LibrarySyncService, LoginService, MprisService, PlaybackQueue,
+    PlaybackStateService, QueueFeederService, RefreshTokenService, SetsStorage, Spotify, TracksCache,
+};
+#[cfg(any(test, feature = "fake"))]
+pub use services::{FakeSpotify, Fixture, FixtureError};
 
 use lazy_static::lazy_static;
+use rspotify::model::CurrentPlaybackContext;
+use std::{sync::Arc, time::Duration};
 use tokio::{
     runtime::Handle,
     sync::broadcast::{channel, Receiver, RecvError, SendError, Sender},
@@ -33,7 +42,23 @@ use tokio::{
 #[derive(Clone)]
 pub enum AppEvent {
     SpotifyAuthError(String),
+    /// Access token was refreshed, requests rejected with the old one can be
+    /// sent again
+    SpotifyTokenRefreshed,
     SpotifyError(String),
+    SpotifyRateLimited(Duration),
+    SpotifyLoginSucceeded,
+    SpotifyLoginFailed(String),
+    SpotifyAccountSwitched,
+    /// Fresh playback state, sent on every check
+    PlaybackStateUpdated(Arc<Option<CurrentPlaybackContext>>),
+    /// URI of the track or episode which started playing
+    PlaybackTrackChanged(Option<String>),
+    PlaybackPaused,
+    PlaybackResumed,
+    /// ID of the device which is played on now
+    PlaybackDeviceChanged(Option<String>),
+    PlaybackVolumeChanged(u32),
 }
 
 const EVENT_BUS_SIZE: usize = 1024;
@@ -1,6 +1,6 @@
 use crate::{
-    loaders::ContainerLoader,
-    services::api::{AlbumsStorageApi, ThreadSafe},
+    loaders::{or_mirrored, ContainerLoader},
+    services::api::{AlbumsStorageApi, LibraryMirrorApi, ThreadSafe},
     utils::AsyncCell,
 };
 use async_trait::async_trait;
@@ -17,7 +17,7 @@ pub struct SavedLoader(usize);
 #[async_trait]
 impl<Client> ContainerLoader<Client> for SavedLoader
 where
-    Client: AlbumsStorageApi + ThreadSafe,
+    Client: AlbumsStorageApi + LibraryMirrorApi + ThreadSafe,
 {
     type Item = SavedAlbum;
     type Page = Page<Self::Item>;
@@ -30,7 +30,8 @@ where
     fn parent_id(&self) -> &Self::ParentId { &() }
 
     async fn load_page(self, spotify: AsyncCell<Client>, offset: u32) -> ClientResult<Self::Page> {
-        spotify.read().await.get_my_albums(offset, 20).await
+        let client = spotify.read().await;
+        or_mirrored(client.get_my_albums(offset, 20).await, || client.mirrored_my_albums(offset))
     }
 
     fn epoch(&self) -> usize { self.0 }
@@ -1,6 +1,6 @@
 use crate::{
-    loaders::ContainerLoader,
-    services::api::{ArtistsStorageApi, ThreadSafe},
+    loaders::{or_mirrored, ContainerLoader},
+    services::api::{ArtistsStorageApi, LibraryMirrorApi, ThreadSafe},
     utils::AsyncCell,
 };
 use async_trait::async_trait;
@@ -17,7 +17,7 @@ pub struct SavedLoader(usize);
 #[async_trait]
 impl<Client> ContainerLoader<Client> for SavedLoader
 where
-    Client: ArtistsStorageApi + ThreadSafe,
+    Client: ArtistsStorageApi + LibraryMirrorApi + ThreadSafe,
 {
     type Item = FullArtist;
     type Page = CursorBasedPage<Self::Item>;
@@ -30,8 +30,14 @@ where
     fn parent_id(&self) -> &Self::ParentId { &() }
 
     async fn load_page(self, spotify: AsyncCell<Client>, cursor: String) -> ClientResult<Self::Page> {
-        let cursor = if cursor.is_empty() { None } else { Some(cursor) };
-        spotify.read().await.get_my_artists(cursor, 20).await
+        let client = spotify.read().await;
+
+        if cursor.is_empty() {
+            // Mirror returns all artists at once, so it's used for the first page only
+            or_mirrored(client.get_my_artists(None, 20).await, || client.mirrored_my_artists())
+        } else {
+            client.get_my_artists(Some(cursor), 20).await
+        }
     }
 
     fn epoch(&self) -> usize { self.0 }
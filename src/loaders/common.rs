@@ -1,6 +1,6 @@
 use crate::{models::PageLike, utils::AsyncCell, Spotify};
 use async_trait::async_trait;
-use rspotify::client::ClientResult;
+use rspotify::client::{ClientError, ClientResult};
 
 #[async_trait]
 
@@ -21,3 +21,15 @@ pub trait ContainerLoader<Client = Spotify> {
 
     fn epoch(&self) -> usize { self as *const _ as *const () as usize }
 }
+
+/// Replaces failed API call result with library mirror data,
+/// unless the failure is not about the network (auth or rate limit errors)
+pub fn or_mirrored<T>(result: ClientResult<T>, mirrored: impl FnOnce() -> Option<T>) -> ClientResult<T> {
+    match result {
+        Err(error @ ClientError::InvalidAuth(_))
+        | Err(error @ ClientError::Unauthorized)
+        | Err(error @ ClientError::RateLimited(_)) => Err(error),
+        Err(error) => mirrored().ok_or(error),
+        result => result,
+    }
+}
@@ -5,6 +5,7 @@ pub mod album;
 pub mod artist;
 pub mod category;
 pub mod playlist;
+pub mod search;
 pub mod track;
 
 pub use album::{SavedLoader as SavedAlbumsLoader, *};
@@ -13,4 +14,8 @@ pub use category::*;
 pub use common::*;
 pub use image::{CairoSurfaceToPixbuf, ImageConverter, ImageData, ImageLoader, PixbufConvert};
 pub use playlist::{SavedLoader as SavedPlaylistsLoader, *};
+pub use search::*;
 pub use track::{SavedLoader as SavedTracksLoader, *};
+
+#[cfg(test)]
+mod tests;
@@ -1,6 +1,6 @@
 use crate::{
-    loaders::common::ContainerLoader,
-    services::api::{PlaylistsStorageApi, ShowsStorageApi, ThreadSafe},
+    loaders::common::{or_mirrored, ContainerLoader},
+    services::api::{LibraryMirrorApi, PlaylistsStorageApi, ShowsStorageApi, ThreadSafe},
     utils::AsyncCell,
 };
 use async_trait::async_trait;
@@ -17,7 +17,7 @@ pub struct FeaturedLoader(usize);
 #[async_trait]
 impl<Client> ContainerLoader<Client> for FeaturedLoader
 where
-    Client: PlaylistsStorageApi + ThreadSafe,
+    Client: PlaylistsStorageApi + LibraryMirrorApi + ThreadSafe,
 {
     type Item = SimplifiedPlaylist;
     type Page = Page<Self::Item>;
@@ -55,7 +55,10 @@ where
     fn parent_id(&self) -> &Self::ParentId { &() }
 
     async fn load_page(self, spotify: AsyncCell<Client>, offset: u32) -> ClientResult<Self::Page> {
-        spotify.read().await.get_my_playlists(offset, 20).await
+        let client = spotify.read().await;
+        or_mirrored(client.get_my_playlists(offset, 20).await, || {
+            client.mirrored_my_playlists(offset)
+        })
     }
 
     fn epoch(&self) -> usize { self.0 }
@@ -0,0 +1,278 @@
+use crate::{
+    loaders::ContainerLoader,
+    models::Empty,
+    services::api::{SearchApi, ThreadSafe},
+    utils::AsyncCell,
+};
+use async_trait::async_trait;
+use rspotify::{
+    client::ClientResult,
+    model::{FullArtist, FullTrack, Page, SearchResult, SearchType, SimplifiedAlbum, SimplifiedPlaylist, SimplifiedShow},
+};
+use std::fmt::{self, Display};
+
+const PAGE_SIZE: u32 = 50;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SearchFilter {
+    Album,
+    Artist,
+    Track,
+    Year,
+    Genre,
+    Isrc,
+    Upc,
+    Tag,
+}
+
+impl SearchFilter {
+    pub fn from_name(name: &str) -> Option<SearchFilter> {
+        use SearchFilter::*;
+
+        Some(match &*name.to_ascii_lowercase() {
+            "album" => Album,
+            "artist" => Artist,
+            "track" => Track,
+            "year" => Year,
+            "genre" => Genre,
+            "isrc" => Isrc,
+            "upc" => Upc,
+            "tag" => Tag,
+            _ => return None,
+        })
+    }
+
+    pub fn as_str(self) -> &'static str {
+        use SearchFilter::*;
+
+        match self {
+            Album => "album",
+            Artist => "artist",
+            Track => "track",
+            Year => "year",
+            Genre => "genre",
+            Isrc => "isrc",
+            Upc => "upc",
+            Tag => "tag",
+        }
+    }
+
+    /// Field filters Web API accepts for the given result type
+    pub fn applies_to(self, kind: SearchType) -> bool {
+        use SearchFilter::*;
+
+        match kind {
+            SearchType::Album => matches!(self, Album | Artist | Year | Upc | Tag),
+            SearchType::Artist => matches!(self, Artist | Year | Genre),
+            SearchType::Track => matches!(self, Album | Artist | Track | Year | Genre | Isrc),
+            _ => false,
+        }
+    }
+}
+
+/// Free text catalog query with optional `field:value` filters,
+/// e.g. `daft punk year:1995-2001 genre:"french house"`
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SearchQuery {
+    pub text: Vec<String>,
+    pub filters: Vec<(SearchFilter, String)>,
+}
+
+impl SearchQuery {
+    pub fn parse(query: &str) -> SearchQuery {
+        let mut result = SearchQuery::default();
+
+        for token in Self::tokenize(query) {
+            let filter = token
+                .find(':')
+                .and_then(|pos| SearchFilter::from_name(&token[..pos]).map(|filter| (filter, token[pos + 1..].to_owned())));
+
+            match filter {
+                Some((filter, value)) if !value.is_empty() => result.filters.push((filter, value)),
+                _ => result.text.push(token),
+            }
+        }
+
+        result
+    }
+
+    fn tokenize(query: &str) -> Vec<String> {
+        let mut tokens = Vec::new();
+        let mut token = String::new();
+        let mut quoted = false;
+
+        for ch in query.chars() {
+            match ch {
+                '"' => quoted = !quoted,
+                ch if ch.is_whitespace() && !quoted => {
+                    if !token.is_empty() {
+                        tokens.push(std::mem::take(&mut token));
+                    }
+                }
+                ch => token.push(ch),
+            }
+        }
+
+        if !token.is_empty() {
+            tokens.push(token);
+        }
+
+        tokens
+    }
+
+    pub fn is_empty(&self) -> bool { self.text.is_empty() && self.filters.is_empty() }
+
+    /// Renders query for the given result type, turning filters
+    /// the type doesn't support into plain search terms
+    pub fn to_query(&self, kind: SearchType) -> String {
+        let terms = self.text.iter().map(|text| Self::quote(text));
+        let filters = self.filters.iter().map(|(filter, value)| {
+            if filter.applies_to(kind) {
+                format!("{}:{}", filter.as_str(), Self::quote(value))
+            } else {
+                Self::quote(value)
+            }
+        });
+
+        terms.chain(filters).collect::<Vec<_>>().join(" ")
+    }
+
+    fn quote(value: &str) -> String {
+        if value.contains(char::is_whitespace) {
+            format!("\"{}\"", value)
+        } else {
+            value.to_owned()
+        }
+    }
+}
+
+impl Display for SearchQuery {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let terms = self.text.iter().map(|text| Self::quote(text));
+        let filters = self
+            .filters
+            .iter()
+            .map(|(filter, value)| format!("{}:{}", filter.as_str(), Self::quote(value)));
+
+        f.write_str(&terms.chain(filters).collect::<Vec<_>>().join(" "))
+    }
+}
+
+macro_rules! impl_search_loader {
+    ($($loader:ident($kind:ident => $result:ident, $item:ty, $name:literal)),+) => {
+        $(
+        #[derive(Clone)]
+        pub struct $loader {
+            query: SearchQuery,
+        }
+
+        #[async_trait]
+        impl<Client> ContainerLoader<Client> for $loader
+        where
+            Client: SearchApi + ThreadSafe,
+        {
+            type Item = $item;
+            type Page = Page<Self::Item>;
+            type ParentId = SearchQuery;
+
+            const NAME: &'static str = $name;
+
+            fn new(query: Self::ParentId) -> Self { $loader { query } }
+
+            fn parent_id(&self) -> &Self::ParentId { &self.query }
+
+            async fn load_page(self, spotify: AsyncCell<Client>, offset: u32) -> ClientResult<Self::Page> {
+                let query = self.query.to_query(SearchType::$kind);
+
+                match spotify.read().await.search(&query, SearchType::$kind, offset, PAGE_SIZE).await? {
+                    SearchResult::$result(page) => Ok(page),
+                    _ => Ok(Page::empty()),
+                }
+            }
+        }
+        )+
+    };
+}
+
+impl_search_loader! {
+    SearchTracksLoader(Track => Tracks, FullTrack, "tracks"),
+    SearchAlbumsLoader(Album => Albums, SimplifiedAlbum, "albums"),
+    SearchArtistsLoader(Artist => Artists, FullArtist, "artists"),
+    SearchPlaylistsLoader(Playlist => Playlists, SimplifiedPlaylist, "playlists"),
+    SearchShowsLoader(Show => Shows, SimplifiedShow, "shows")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn words_are_split_on_whitespace() {
+        assert_eq!(SearchQuery::tokenize("  daft\tpunk \n"), vec!["daft", "punk"]);
+        assert!(SearchQuery::tokenize("   ").is_empty());
+    }
+
+    #[test]
+    fn quoted_phrases_are_kept_together() {
+        assert_eq!(SearchQuery::tokenize(r#""around the world" genre:"french house""#), vec![
+            "around the world",
+            "genre:french house"
+        ]);
+        assert_eq!(SearchQuery::tokenize(r#"one "unclosed quote"#), vec!["one", "unclosed quote"]);
+    }
+
+    #[test]
+    fn filters_are_parsed() {
+        let query = SearchQuery::parse(r#"daft punk Year:1995-2001 genre:"french house""#);
+
+        assert_eq!(query.text, vec!["daft", "punk"]);
+        assert_eq!(query.filters, vec![
+            (SearchFilter::Year, "1995-2001".to_owned()),
+            (SearchFilter::Genre, "french house".to_owned())
+        ]);
+    }
+
+    #[test]
+    fn unknown_and_empty_filters_are_text() {
+        let query = SearchQuery::parse("mood:happy artist: 12:34");
+
+        assert_eq!(query.text, vec!["mood:happy", "artist:", "12:34"]);
+        assert!(query.filters.is_empty());
+        assert!(SearchQuery::parse("").is_empty());
+    }
+
+    #[test]
+    fn supported_filters_are_kept_in_query() {
+        let query = SearchQuery::parse(r#""get lucky" artist:"daft punk" year:2013"#);
+
+        assert_eq!(
+            query.to_query(SearchType::Track),
+            r#""get lucky" artist:"daft punk" year:2013"#
+        );
+    }
+
+    #[test]
+    fn unsupported_filters_become_terms() {
+        let query = SearchQuery::parse(r#"discovery genre:"french house" upc:724384960650 isrc:GBDUW0000059"#);
+
+        assert_eq!(
+            query.to_query(SearchType::Album),
+            r#"discovery "french house" upc:724384960650 GBDUW0000059"#
+        );
+        assert_eq!(
+            query.to_query(SearchType::Artist),
+            r#"discovery genre:"french house" 724384960650 GBDUW0000059"#
+        );
+        assert_eq!(
+            query.to_query(SearchType::Playlist),
+            r#"discovery "french house" 724384960650 GBDUW0000059"#
+        );
+    }
+
+    #[test]
+    fn query_is_displayed_with_all_filters() {
+        let query = SearchQuery::parse(r#"discovery tag:new genre:"french house""#);
+
+        assert_eq!(query.to_string(), r#"discovery tag:new genre:"french house""#);
+    }
+}
@@ -0,0 +1,92 @@
+use super::*;
+use crate::{
+    models::HasUri,
+    services::{api::PlaybackQueueApi, FakeSpotify},
+    utils::AsyncCell,
+};
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+const FIRST: &str = "spotify:track:0track00000000000000001";
+const SECOND: &str = "spotify:track:0track00000000000000002";
+const THIRD: &str = "spotify:track:0track00000000000000003";
+const ALBUM: &str = "spotify:album:0album00000000000000001";
+const PLAYLIST: &str = "spotify:playlist:0playlist00000000000001";
+
+fn library() -> AsyncCell<FakeSpotify> { Arc::new(RwLock::new(FakeSpotify::library())) }
+
+fn uris<T: HasUri>(items: &[T]) -> Vec<&str> { items.iter().map(HasUri::uri).collect() }
+
+#[tokio::test]
+async fn saved_tracks_are_loaded() {
+    let page = <SavedTracksLoader as ContainerLoader<FakeSpotify>>::new(())
+        .load_page(library(), 0)
+        .await
+        .unwrap();
+
+    assert_eq!(uris(&page.items), vec![SECOND, FIRST]);
+    assert_eq!(page.total, 2);
+    assert!(page.next.is_none());
+}
+
+#[tokio::test]
+async fn playlist_tracks_are_loaded_by_pages() {
+    let loader = <PlaylistLoader as ContainerLoader<FakeSpotify>>::new(PLAYLIST.to_owned());
+
+    let first = loader.clone().load_page(library(), 0).await.unwrap();
+    assert_eq!(uris(&first.items), vec![FIRST, SECOND, FIRST, THIRD]);
+    assert!(first.next.is_none());
+
+    let rest = loader.load_page(library(), 3).await.unwrap();
+    assert_eq!(uris(&rest.items), vec![THIRD]);
+    assert_eq!(rest.total, 4);
+}
+
+#[tokio::test]
+async fn unknown_playlist_fails_with_no_mirror() {
+    let result = <PlaylistLoader as ContainerLoader<FakeSpotify>>::new("spotify:playlist:unknown".to_owned())
+        .load_page(library(), 0)
+        .await;
+
+    assert!(result.is_err());
+}
+
+#[tokio::test]
+async fn album_tracks_are_loaded() {
+    let page = <AlbumLoader as ContainerLoader<FakeSpotify>>::new(ALBUM.to_owned())
+        .load_page(library(), 0)
+        .await
+        .unwrap();
+
+    assert_eq!(uris(&page.items), vec![FIRST, SECOND, THIRD]);
+}
+
+#[tokio::test]
+async fn top_tracks_are_loaded() {
+    let page = <MyTopTracksLoader as ContainerLoader<FakeSpotify>>::new(())
+        .load_page(library(), 0)
+        .await
+        .unwrap();
+
+    assert_eq!(uris(&page.items), vec![SECOND, FIRST]);
+}
+
+#[tokio::test]
+async fn queue_tracks_are_loaded_in_queue_order() {
+    let spotify = library();
+    spotify
+        .write()
+        .await
+        .enqueue_tracks(&[THIRD.to_owned(), FIRST.to_owned(), THIRD.to_owned()])
+        .await
+        .unwrap();
+
+    let loader = <QueueLoader as ContainerLoader<FakeSpotify>>::new(PlaybackQueueId);
+    assert_eq!(
+        <QueueLoader as ContainerLoader<FakeSpotify>>::parent_id(&loader),
+        &PlaybackQueueId
+    );
+
+    let tracks = loader.load_page(spotify, ()).await.unwrap();
+    assert_eq!(uris(&tracks), vec![THIRD, FIRST, THIRD]);
+}
@@ -1,70 +1,202 @@
-// TODO: Mode
-#![allow(dead_code)]
-
 use crate::{
-    loaders::common::ContainerLoader,
-    services::api::{EpisodesStorageApi, PlaybackQueueApi, SearchApi, ThreadSafe, TracksStorageApi},
+    loaders::common::{or_mirrored, ContainerLoader},
+    services::api::{EpisodesStorageApi, LibraryMirrorApi, PlaybackQueueApi, SearchApi, ThreadSafe, TracksStorageApi},
     utils::AsyncCell,
 };
 use async_trait::async_trait;
 use rspotify::{client::ClientResult, model::*};
 use serde_json::{Map, Value};
+use std::{fmt::Debug, ops::RangeInclusive};
+use thiserror::Error;
 
 const NAME: &str = "tracks";
 
-#[derive(Clone, Copy)]
+const MAX_SEEDS: usize = 5;
+
+const MAX_RECOMMENDATIONS: u32 = 100;
+
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum RecommendError {
+    #[error("at least one seed genre, artist or track is required")]
+    NoSeeds,
+    #[error("too many seeds ({0}), at most {} seeds are allowed", MAX_SEEDS)]
+    TooManySeeds(usize),
+    #[error("{0} value {1} is out of range {2}")]
+    OutOfRange(&'static str, String, String),
+    #[error("{0} min value is greater than max value")]
+    InvalidRange(&'static str),
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
 pub struct Seed<Val: Copy> {
-    min: Option<Val>,
-    max: Option<Val>,
-    target: Option<Val>,
+    pub min: Option<Val>,
+    pub max: Option<Val>,
+    pub target: Option<Val>,
 }
 
-#[derive(Clone, Copy)]
+impl<Val: Copy + PartialOrd + Debug + Into<Value>> Seed<Val> {
+    pub fn new(min: Option<Val>, max: Option<Val>, target: Option<Val>) -> Option<Self> {
+        if min.is_none() && max.is_none() && target.is_none() {
+            None
+        } else {
+            Some(Seed { min, max, target })
+        }
+    }
+
+    fn validate(&self, name: &'static str, range: RangeInclusive<Val>) -> Result<(), RecommendError> {
+        for value in [self.min, self.max, self.target].iter().flatten() {
+            if !range.contains(value) {
+                return Err(RecommendError::OutOfRange(
+                    name,
+                    format!("{:?}", value),
+                    format!("{:?}..={:?}", range.start(), range.end()),
+                ));
+            }
+        }
+
+        match (self.min, self.max, self.target) {
+            (Some(min), Some(max), _) if min > max => Err(RecommendError::InvalidRange(name)),
+            (Some(min), _, Some(target)) if target < min => Err(RecommendError::OutOfRange(
+                name,
+                format!("{:?}", target),
+                format!("{:?}..", min),
+            )),
+            (_, Some(max), Some(target)) if target > max => Err(RecommendError::OutOfRange(
+                name,
+                format!("{:?}", target),
+                format!("..={:?}", max),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    fn write_to(&self, name: &str, tunables: &mut Map<String, Value>) {
+        if let Some(min) = self.min {
+            tunables.insert(format!("min_{}", name), min.into());
+        }
+        if let Some(max) = self.max {
+            tunables.insert(format!("max_{}", name), max.into());
+        }
+        if let Some(target) = self.target {
+            tunables.insert(format!("target_{}", name), target.into());
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum Mode {
     Minor = 0,
     Major = 1,
 }
 
-#[derive(Clone)]
-pub struct RecommendLoader {
-    seed_artists: Option<Vec<String>>,
-    seed_genres: Option<Vec<String>>,
-    seed_tracks: Option<Vec<String>>,
-    tunables: Map<String, Value>,
-    /*
-    accousticness: Option<Seed<f32>>,
-    dancability: Option<Seed<f32>>,
-    duration_ms: Option<Seed<u32>>,
-    energy: Option<Seed<f32>>,
-    instrumentalness: Option<Seed<f32>>,
-    key: Option<Seed<u8>>,
-    liveness: Option<Seed<f32>>,
-    loadness: Option<Seed<f32>>,
-    mode: Option<Mode>,
-    popularity: Option<Seed<u8>>,
-    speechness: Option<Seed<f32>>,
-    tempo: Option<Seed<f32>>,
-    time_signature: Option<Seed<u8>>,
-    valence: Option<Seed<f32>>,
-     */
+/// Recommendation query: seeds plus optional tunable track attributes,
+/// see <https://developer.spotify.com/documentation/web-api/reference/browse/get-recommendations/>
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RecommendParams {
+    pub seed_artists: Vec<String>,
+    pub seed_genres: Vec<String>,
+    pub seed_tracks: Vec<String>,
+
+    pub acousticness: Option<Seed<f32>>,
+    pub danceability: Option<Seed<f32>>,
+    pub duration_ms: Option<Seed<u32>>,
+    pub energy: Option<Seed<f32>>,
+    pub instrumentalness: Option<Seed<f32>>,
+    pub key: Option<Seed<u8>>,
+    pub liveness: Option<Seed<f32>>,
+    pub loudness: Option<Seed<f32>>,
+    pub mode: Option<Mode>,
+    pub popularity: Option<Seed<u8>>,
+    pub speechiness: Option<Seed<f32>>,
+    pub tempo: Option<Seed<f32>>,
+    pub time_signature: Option<Seed<u8>>,
+    pub valence: Option<Seed<f32>>,
 }
 
-impl RecommendLoader {
-    fn extract_vec_string(params: &mut Map<String, Value>, key: &str, max_items: usize) -> Option<Vec<String>> {
-        params.remove(key).and_then(|seed| match seed {
-            Value::Array(values) => Some(
-                values
-                    .into_iter()
-                    .take(max_items)
-                    .flat_map(|value| match value {
-                        Value::String(value) => Some(value),
-                        _ => None,
-                    })
-                    .collect::<Vec<_>>(),
-            ),
-            _ => None,
-        })
+impl RecommendParams {
+    pub fn seeds_count(&self) -> usize { self.seed_artists.len() + self.seed_genres.len() + self.seed_tracks.len() }
+
+    pub fn validate(&self) -> Result<(), RecommendError> {
+        match self.seeds_count() {
+            0 => return Err(RecommendError::NoSeeds),
+            count if count > MAX_SEEDS => return Err(RecommendError::TooManySeeds(count)),
+            _ => {}
+        }
+
+        macro_rules! validate {
+            ($($field:ident: $range:expr),+) => {
+                $(if let Some(ref seed) = self.$field {
+                    seed.validate(stringify!($field), $range)?;
+                })+
+            };
+        }
+
+        validate! {
+            acousticness: 0.0..=1.0,
+            danceability: 0.0..=1.0,
+            duration_ms: 0..=u32::MAX,
+            energy: 0.0..=1.0,
+            instrumentalness: 0.0..=1.0,
+            key: 0..=11,
+            liveness: 0.0..=1.0,
+            loudness: -60.0..=0.0,
+            popularity: 0..=100,
+            speechiness: 0.0..=1.0,
+            tempo: 0.0..=f32::MAX,
+            time_signature: 1..=u8::MAX,
+            valence: 0.0..=1.0
+        }
+
+        Ok(())
     }
+
+    pub fn tunables(&self) -> Map<String, Value> {
+        let mut tunables = Map::new();
+
+        macro_rules! tune {
+            ($($field:ident),+) => {
+                $(if let Some(ref seed) = self.$field {
+                    seed.write_to(stringify!($field), &mut tunables);
+                })+
+            };
+        }
+
+        tune!(
+            acousticness,
+            danceability,
+            duration_ms,
+            energy,
+            instrumentalness,
+            key,
+            liveness,
+            loudness,
+            popularity,
+            speechiness,
+            tempo,
+            time_signature,
+            valence
+        );
+
+        if let Some(mode) = self.mode {
+            tunables.insert("min_mode".into(), (mode as u8).into());
+            tunables.insert("max_mode".into(), (mode as u8).into());
+        }
+
+        tunables
+    }
+
+    fn seeds(seeds: &[String]) -> Option<Vec<String>> {
+        if seeds.is_empty() {
+            None
+        } else {
+            Some(seeds.to_vec())
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct RecommendLoader {
+    params: RecommendParams,
 }
 
 #[async_trait]
@@ -74,103 +206,28 @@ where
 {
     type Item = SimplifiedTrack;
     type Page = Vec<Self::Item>;
-    type ParentId = Map<String, Value>;
+    type ParentId = RecommendParams;
 
     const NAME: &'static str = "recommended tracks";
 
-    fn new(mut tunables: Self::ParentId) -> Self {
-        let seed_artists = Self::extract_vec_string(&mut tunables, "seed_artists", 5);
-        let seed_genres = Self::extract_vec_string(&mut tunables, "seed_genres", 5);
-        let seed_tracks = Self::extract_vec_string(&mut tunables, "seed_tracks", 5);
-        /*
-        tunables.retain(|key| {
-            matches!(
-                &*key,
-                "min_accousticness"
-                    | "max_acousticness"
-                    | "target_acousticness"
-                    | "min_danceability"
-                    | "max_danceability"
-                    | "target_danceability"
-                    | "min_duration_ms"
-                    | "max_duration_ms"
-                    | "target_duration_ms"
-                    | "min_energy"
-                    | "max_energy"
-                    | "target_energy"
-                    | "min_instrumentalness"
-                    | "max_instrumentalness"
-                    | "target_instrumentalness"
-                    | "min_key"
-                    | "max_key"
-                    | "target_key"
-                    | "min_liveness"
-                    | "max_liveness"
-                    | "target_liveness"
-                    | "min_loadness"
-                    | "max_loudness"
-                    | "target_loudness"
-                    | "min_mode"
-                    | "max_mode"
-                    | "target_mode"
-                    | "min_popularity"
-                    | "max_popularity"
-                    | "target_popularity"
-                    | "min_speechiness"
-                    | "max_speechiness"
-                    | "target_speechiness"
-                    | "min_tempo"
-                    | "max_tempo"
-                    | "target_tempo"
-                    | "max_time_signature"
-                    | "min_time_signature"
-                    | "target_time_signature"
-                    | "min_valence"
-                    | "max_valence"
-                    | "target_valence"
-            )
-        });
-         */
-
-        Self {
-            seed_artists,
-            seed_genres,
-            seed_tracks,
-            tunables,
-        }
-    }
+    fn new(params: Self::ParentId) -> Self { RecommendLoader { params } }
 
-    fn parent_id(&self) -> &Self::ParentId {
-        &self.tunables
-        //let mut params = self.tunables.clone();
-        //if let Some(ref seed_artists) = self.seed_artists {
-        //    params.insert("seed_artists".into(),
-        // Value::from(seed_artists.clone()));
-        //}
-        //if let Some(ref seed_genres) = self.seed_genres {
-        //    params.insert("seed_genres".into(),
-        // Value::from(seed_genres.clone()));
-        //}
-        //if let Some(ref seed_tracks) = self.seed_tracks {
-        //    params.insert("seed_tracks".into(),
-        // Value::from(seed_tracks.clone()));
-        //}
-        //params
-    }
+    fn parent_id(&self) -> &Self::ParentId { &self.params }
 
     #[allow(clippy::unit_arg)]
     async fn load_page(self, spotify: AsyncCell<Client>, _offset: ()) -> ClientResult<Self::Page> {
-        let RecommendLoader {
-            seed_tracks,
-            seed_genres,
-            seed_artists,
-            tunables,
-        } = self;
+        let params = self.params;
 
         spotify
             .read()
             .await
-            .get_recommended_tracks(seed_tracks, seed_genres, seed_artists, tunables, 100)
+            .get_recommended_tracks(
+                RecommendParams::seeds(&params.seed_genres),
+                RecommendParams::seeds(&params.seed_artists),
+                RecommendParams::seeds(&params.seed_tracks),
+                params.tunables(),
+                MAX_RECOMMENDATIONS,
+            )
             .await
     }
 }
@@ -181,7 +238,7 @@ pub struct SavedLoader(usize);
 #[async_trait]
 impl<Client> ContainerLoader<Client> for SavedLoader
 where
-    Client: TracksStorageApi + ThreadSafe,
+    Client: TracksStorageApi + LibraryMirrorApi + ThreadSafe,
 {
     type Item = SavedTrack;
     type Page = Page<Self::Item>;
@@ -194,7 +251,8 @@ where
     fn parent_id(&self) -> &Self::ParentId { &() }
 
     async fn load_page(self, client: AsyncCell<Client>, offset: u32) -> ClientResult<Self::Page> {
-        client.read().await.get_my_tracks(offset, 20).await
+        let client = client.read().await;
+        or_mirrored(client.get_my_tracks(offset, 20).await, || client.mirrored_my_tracks(offset))
     }
 
     fn epoch(&self) -> usize { self.0 }
@@ -226,6 +284,11 @@ where
     fn epoch(&self) -> usize { self.0 }
 }
 
+/// Parent id of the app playback queue, tells the queue list from other
+/// lists without a parent
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PlaybackQueueId;
+
 #[derive(Clone, Copy)]
 pub struct QueueLoader(usize);
 
@@ -236,13 +299,13 @@ where
 {
     type Item = FullTrack;
     type Page = Vec<Self::Item>;
-    type ParentId = ();
+    type ParentId = PlaybackQueueId;
 
     const NAME: &'static str = NAME;
 
     fn new(_id: Self::ParentId) -> Self { QueueLoader(rand::random()) }
 
-    fn parent_id(&self) -> &Self::ParentId { &() }
+    fn parent_id(&self) -> &Self::ParentId { &PlaybackQueueId }
 
     #[allow(clippy::unit_arg)]
     async fn load_page(self, client: AsyncCell<Client>, _offset: ()) -> ClientResult<Self::Page> {
@@ -285,7 +348,7 @@ pub struct PlaylistLoader {
 #[async_trait]
 impl<Client> ContainerLoader<Client> for PlaylistLoader
 where
-    Client: TracksStorageApi + ThreadSafe,
+    Client: TracksStorageApi + LibraryMirrorApi + ThreadSafe,
 {
     type Item = PlaylistItem;
     type Page = Page<Self::Item>;
@@ -296,7 +359,10 @@ where
     fn parent_id(&self) -> &Self::ParentId { &self.uri }
 
     async fn load_page(self, spotify: AsyncCell<Client>, offset: u32) -> ClientResult<Self::Page> {
-        spotify.read().await.get_playlist_tracks(&self.uri, offset, 10).await
+        let client = spotify.read().await;
+        or_mirrored(client.get_playlist_tracks(&self.uri, offset, 10).await, || {
+            client.mirrored_playlist_tracks(&self.uri, offset)
+        })
     }
 }
 
@@ -375,3 +441,120 @@ where
         spotify.read().await.get_artist_top_tracks(&self.artist_id).await
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params(seeds: usize) -> RecommendParams {
+        RecommendParams {
+            seed_tracks: (0..seeds).map(|index| format!("spotify:track:{}", index)).collect(),
+            ..RecommendParams::default()
+        }
+    }
+
+    #[test]
+    fn seeds_and_tunables_in_range_are_valid() {
+        let params = RecommendParams {
+            seed_genres: vec!["techno".to_owned()],
+            energy: Seed::new(Some(0.0), Some(1.0), Some(0.5)),
+            key: Seed::new(None, None, Some(11)),
+            loudness: Seed::new(Some(-60.0), Some(-10.0), None),
+            tempo: Seed::new(Some(120.0), Some(120.0), Some(120.0)),
+            ..params(4)
+        };
+
+        assert_eq!(params.validate(), Ok(()));
+    }
+
+    #[test]
+    fn empty_seed_is_not_created() {
+        assert_eq!(Seed::<u8>::new(None, None, None), None);
+        assert_eq!(
+            Seed::new(None, Some(3u8), None),
+            Some(Seed {
+                min: None,
+                max: Some(3),
+                target: None
+            })
+        );
+    }
+
+    #[test]
+    fn seeds_are_required() {
+        let params = RecommendParams {
+            energy: Seed::new(None, None, Some(0.5)),
+            ..params(0)
+        };
+
+        assert_eq!(params.validate(), Err(RecommendError::NoSeeds));
+    }
+
+    #[test]
+    fn at_most_five_seeds_are_allowed() {
+        assert_eq!(params(5).validate(), Ok(()));
+        assert_eq!(params(6).validate(), Err(RecommendError::TooManySeeds(6)));
+
+        let mixed = RecommendParams {
+            seed_artists: vec!["spotify:artist:1".to_owned(), "spotify:artist:2".to_owned()],
+            seed_genres: vec!["house".to_owned()],
+            ..params(3)
+        };
+        assert_eq!(mixed.validate(), Err(RecommendError::TooManySeeds(6)));
+    }
+
+    #[test]
+    fn values_out_of_range_are_rejected() {
+        let energy = RecommendParams {
+            energy: Seed::new(None, Some(1.5), None),
+            ..params(1)
+        };
+        assert_eq!(
+            energy.validate(),
+            Err(RecommendError::OutOfRange("energy", "1.5".into(), "0.0..=1.0".into()))
+        );
+
+        let key = RecommendParams {
+            key: Seed::new(Some(12), None, None),
+            ..params(1)
+        };
+        assert_eq!(
+            key.validate(),
+            Err(RecommendError::OutOfRange("key", "12".into(), "0..=11".into()))
+        );
+
+        let loudness = RecommendParams {
+            loudness: Seed::new(None, None, Some(3.0)),
+            ..params(1)
+        };
+        assert_eq!(
+            loudness.validate(),
+            Err(RecommendError::OutOfRange("loudness", "3.0".into(), "-60.0..=0.0".into()))
+        );
+    }
+
+    #[test]
+    fn min_greater_than_max_is_rejected() {
+        let params = RecommendParams {
+            tempo: Seed::new(Some(130.0), Some(120.0), None),
+            ..params(1)
+        };
+
+        assert_eq!(params.validate(), Err(RecommendError::InvalidRange("tempo")));
+    }
+
+    #[test]
+    fn target_outside_min_and_max_is_rejected() {
+        let below = Seed::new(Some(30u8), Some(60), Some(20));
+        assert_eq!(
+            below.unwrap().validate("popularity", 0..=100),
+            Err(RecommendError::OutOfRange("popularity", "20".into(), "30..".into()))
+        );
+
+        let above = Seed::new(Some(30u8), Some(60), Some(70));
+        assert_eq!(
+            above.unwrap().validate("popularity", 0..=100),
+            Err(RecommendError::OutOfRange("popularity", "70".into(), "..=60".into()))
+        );
+    }
+}
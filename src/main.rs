@@ -1,16 +1,22 @@
 use relm::Widget;
-use spodjfy::{Config, LoginService, Params, RefreshTokenService, Spotify, Win};
+use spodjfy::{
+    Cli, Config, ControlService, CrossfadeService, LibrarySyncService, LoginService, MprisService, Params, PlaybackStateService,
+    QueueFeederService, RefreshTokenService, Spotify, TracksCache, Win,
+};
 use std::sync::Arc;
 use tokio::sync::RwLock;
 
 fn main() {
     env_logger::init();
 
+    if let Some(command) = Cli::from_args_or_exit().command {
+        std::process::exit(command.run());
+    }
+
     let config = Config::new();
     let settings = config.load_settings();
-    let spotify_cache_path = config.spotify_token_file();
-
-    let (client_id, client_secret) = (settings.client_id.clone(), settings.client_secret.clone());
+    let account = settings.account().cloned().unwrap_or_default();
+    let spotify_cache_path = config.spotify_token_file(&account.name);
 
     let mut runtime = tokio::runtime::Builder::new()
         .threaded_scheduler()
@@ -19,7 +25,14 @@ fn main() {
         .build()
         .unwrap();
 
-    let mut spotify = Spotify::new(client_id, client_secret, spotify_cache_path);
+    let mut spotify = Spotify::new(account.client_id, account.client_secret, spotify_cache_path);
+
+    match TracksCache::open(config.tracks_cache_dir()) {
+        Ok(cache) => spotify.set_tracks_cache(cache),
+        Err(error) => log::error!("failed to open tracks cache: {}", error),
+    }
+
+    spotify.open_account_storage(&config, &account.name);
 
     runtime.block_on(async {
         spotify.load_token_from_cache().await;
@@ -28,9 +41,16 @@ fn main() {
     let spotify = Arc::new(RwLock::new(spotify));
 
     LoginService::new(spotify.clone()).spawn(&runtime);
+    ControlService::new(spotify.clone(), config.control_socket_file()).spawn(&runtime);
     RefreshTokenService::new(spotify.clone()).spawn(&runtime);
+    QueueFeederService::new(spotify.clone()).spawn(&runtime);
+    PlaybackStateService::new(spotify.clone()).spawn(&runtime);
+    CrossfadeService::new(spotify.clone()).spawn(&runtime);
+    LibrarySyncService::new(spotify.clone()).spawn(&runtime);
 
     let pool = runtime.handle().clone();
 
+    let _mpris = MprisService::start(pool.clone(), spotify.clone());
+
     Win::run(Params { pool, settings, spotify }).unwrap();
 }
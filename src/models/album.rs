@@ -1,4 +1,4 @@
-use crate::models::common::*;
+use crate::{models::common::*, services::store::StorageModel};
 use chrono::{DateTime, Utc};
 use gdk_pixbuf::Pixbuf;
 use glib::{IsA, StaticType, Type};
@@ -389,3 +389,9 @@ impl Empty for SimplifiedAlbum {
 
     fn is_empty(&self) -> bool { self.name.is_empty() }
 }
+
+impl StorageModel for SavedAlbum {
+    const TREE_NAME: &'static str = "saved_albums";
+
+    fn key(&self) -> &str { &self.album.id }
+}
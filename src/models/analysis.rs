@@ -0,0 +1,85 @@
+use crate::models::key::Key;
+use rspotify::model::AudioAnalysis;
+
+/// Track section with its own key and tempo
+#[derive(Clone, Debug, PartialEq)]
+pub struct Section {
+    pub start_ms: u32,
+    pub duration_ms: u32,
+    pub key: Option<Key>,
+    pub tempo: f32,
+}
+
+/// Loudness level (in dB) at some point of a track
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LoudnessPoint {
+    pub time_ms: u32,
+    pub loudness: f32,
+}
+
+/// Audio analysis reduced to the parts needed to draw a track overview,
+/// all times are in milliseconds to match playback position
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TrackAnalysis {
+    pub duration_ms: u32,
+    pub sections: Vec<Section>,
+    pub bars: Vec<u32>,
+    pub beats: Vec<u32>,
+    pub loudness: Vec<LoudnessPoint>,
+}
+
+/// Converts audio analysis time in seconds to milliseconds
+pub fn to_ms(secs: f32) -> u32 { (secs.max(0.0) * 1000.0).round() as u32 }
+
+impl From<AudioAnalysis> for TrackAnalysis {
+    fn from(analysis: AudioAnalysis) -> Self {
+        let sections = analysis
+            .sections
+            .iter()
+            .map(|section| Section {
+                start_ms: to_ms(section.time_interval.start),
+                duration_ms: to_ms(section.time_interval.duration),
+                key: Key::from_pitch(section.key, section.mode > 0.5),
+                tempo: section.tempo,
+            })
+            .collect();
+
+        // Each segment contributes its starting and peak loudness
+        let loudness = analysis
+            .segments
+            .iter()
+            .flat_map(|segment| {
+                let start = segment.time_interval.start;
+
+                vec![
+                    LoudnessPoint {
+                        time_ms: to_ms(start),
+                        loudness: segment.loudness_start,
+                    },
+                    LoudnessPoint {
+                        time_ms: to_ms(start + segment.loudness_max_time),
+                        loudness: segment.loudness_max,
+                    },
+                ]
+            })
+            .collect();
+
+        TrackAnalysis {
+            duration_ms: to_ms(analysis.track.duration),
+            sections,
+            bars: analysis.bars.iter().map(|bar| to_ms(bar.start)).collect(),
+            beats: analysis.beats.iter().map(|beat| to_ms(beat.start)).collect(),
+            loudness,
+        }
+    }
+}
+
+impl TrackAnalysis {
+    /// Loudness range across the whole track, as `(min, max)` in dB
+    pub fn loudness_range(&self) -> Option<(f32, f32)> {
+        self.loudness.iter().fold(None, |range, point| match range {
+            None => Some((point.loudness, point.loudness)),
+            Some((min, max)) => Some((min.min(point.loudness), max.max(point.loudness))),
+        })
+    }
+}
@@ -1,4 +1,4 @@
-use crate::models::common::*;
+use crate::{models::common::*, services::store::StorageModel};
 use gdk_pixbuf::Pixbuf;
 use glib::{IsA, StaticType, Type};
 use gtk::prelude::GtkListStoreExtManual;
@@ -196,3 +196,9 @@ impl Merge for FullArtist {
         }
     }
 }
+
+impl StorageModel for FullArtist {
+    const TREE_NAME: &'static str = "artists";
+
+    fn key(&self) -> &str { &self.id }
+}
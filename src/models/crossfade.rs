@@ -0,0 +1,323 @@
+//! # Crossfade timing
+//!
+//! Spotify Connect doesn't let us control crossfade, so it's emulated with
+//! volume: near the end of a track the volume is ramped down, the player skips
+//! to the next track at the mix out point, and the volume is ramped back up.
+//! Here are only the calculations, the scheduling is done by
+//! [`CrossfadeService`](crate::services::spotify::CrossfadeService).
+
+use crate::models::analysis::to_ms;
+use rspotify::model::AudioAnalysis;
+use serde::{Deserialize, Serialize};
+use std::{
+    f32::consts::FRAC_PI_2,
+    sync::atomic::{AtomicBool, AtomicU32, AtomicU8, Ordering},
+};
+
+/// Default length of each of volume ramps, in milliseconds
+pub const DEFAULT_FADE_MS: u32 = 5000;
+
+/// Mix out point is never earlier than this before the end of a track,
+/// so that the next queued track can be edited until shortly before the end
+pub const MAX_MIX_OUT_LEAD_MS: u32 = 12_000;
+
+/// Time between volume changes during a ramp
+pub const FADE_STEP_MS: u32 = 500;
+
+static CURRENT_ENABLED: AtomicBool = AtomicBool::new(false);
+
+static CURRENT_FADE_MS: AtomicU32 = AtomicU32::new(DEFAULT_FADE_MS);
+
+static CURRENT_CURVE: AtomicU8 = AtomicU8::new(FadeCurve::Linear as u8);
+
+/// Shape of volume ramps
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FadeCurve {
+    Linear = 0,
+    /// Sine curve, keeps perceived loudness steadier than linear one
+    EqualPower = 1,
+    /// Smooth start and end, steep in the middle
+    SCurve = 2,
+}
+
+impl Default for FadeCurve {
+    fn default() -> Self { FadeCurve::Linear }
+}
+
+impl FadeCurve {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            FadeCurve::Linear => "linear",
+            FadeCurve::EqualPower => "equal_power",
+            FadeCurve::SCurve => "s_curve",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<FadeCurve> {
+        match name {
+            "linear" => Some(FadeCurve::Linear),
+            "equal_power" => Some(FadeCurve::EqualPower),
+            "s_curve" => Some(FadeCurve::SCurve),
+            _ => None,
+        }
+    }
+
+    /// Volume gain (0 to 1) at a position (0 to 1) of a fade in
+    pub fn gain(self, position: f32) -> f32 {
+        let position = position.max(0.0).min(1.0);
+
+        match self {
+            FadeCurve::Linear => position,
+            FadeCurve::EqualPower => (position * FRAC_PI_2).sin(),
+            FadeCurve::SCurve => position * position * (3.0 - 2.0 * position),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CrossfadeConfig {
+    pub enabled: bool,
+    pub fade_ms: u32,
+    pub curve: FadeCurve,
+}
+
+pub fn config() -> CrossfadeConfig {
+    CrossfadeConfig {
+        enabled: CURRENT_ENABLED.load(Ordering::Relaxed),
+        fade_ms: CURRENT_FADE_MS.load(Ordering::Relaxed),
+        curve: match CURRENT_CURVE.load(Ordering::Relaxed) {
+            1 => FadeCurve::EqualPower,
+            2 => FadeCurve::SCurve,
+            _ => FadeCurve::Linear,
+        },
+    }
+}
+
+pub fn set_config(config: CrossfadeConfig) {
+    CURRENT_ENABLED.store(config.enabled, Ordering::Relaxed);
+    CURRENT_FADE_MS.store(config.fade_ms, Ordering::Relaxed);
+    CURRENT_CURVE.store(config.curve as u8, Ordering::Relaxed);
+}
+
+/// Position to skip to the next track at, in milliseconds: the start of the
+/// track fade out, or the start of its last section if there's no fade out,
+/// or the very end if neither is close enough to the end
+pub fn mix_out_point(duration_ms: u32, analysis: Option<&AudioAnalysis>) -> u32 {
+    let window = duration_ms.saturating_sub(MAX_MIX_OUT_LEAD_MS)..duration_ms;
+
+    let analysis = match analysis {
+        Some(analysis) => analysis,
+        None => return duration_ms,
+    };
+
+    let fade_out = to_ms(analysis.track.start_of_fade_out);
+    if window.contains(&fade_out) {
+        return fade_out;
+    }
+
+    analysis
+        .sections
+        .iter()
+        .map(|section| to_ms(section.time_interval.start))
+        .filter(|start| window.contains(start))
+        .last()
+        .unwrap_or(duration_ms)
+}
+
+/// Earliest position a fade out can start at, whatever the mix out point is
+pub fn earliest_fade_out(duration_ms: u32, fade_ms: u32) -> u32 {
+    duration_ms.saturating_sub(MAX_MIX_OUT_LEAD_MS).saturating_sub(fade_ms)
+}
+
+/// Position to start the fade out at to skip at the mix out point
+pub fn fade_out_start(mix_out_ms: u32, fade_ms: u32) -> u32 { mix_out_ms.saturating_sub(fade_ms) }
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Fade {
+    In,
+    Out,
+}
+
+/// Volume changes of a ramp as `(offset_ms, volume)` pairs, offsets are from
+/// the ramp start, fade out ends in silence and fade in ends in `volume`
+pub fn fade_steps(volume: u8, fade_ms: u32, curve: FadeCurve, fade: Fade) -> Vec<(u32, u8)> {
+    let steps = (fade_ms / FADE_STEP_MS).max(1);
+
+    (1..=steps)
+        .map(|step| {
+            let position = step as f32 / steps as f32;
+            let gain = match fade {
+                Fade::In => curve.gain(position),
+                Fade::Out => curve.gain(1.0 - position),
+            };
+
+            ((step - 1) * fade_ms / steps, (volume as f32 * gain).round() as u8)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const DURATION_MS: u32 = 200_000;
+
+    /// Audio analysis of a 200 seconds track with fade out and sections
+    /// starting at the given seconds
+    fn analysis(start_of_fade_out: f32, sections: &[f32]) -> AudioAnalysis {
+        let sections = sections
+            .iter()
+            .map(|&start| {
+                json!({
+                    "start": start,
+                    "duration": 10.0,
+                    "confidence": 1.0,
+                    "loudness": -8.0,
+                    "tempo": 120.0,
+                    "tempo_confidence": 1.0,
+                    "key": 0,
+                    "key_confidence": 1.0,
+                    "mode": 1,
+                    "mode_confidence": 1.0,
+                    "time_signature": 4,
+                    "time_signature_confidence": 1.0,
+                })
+            })
+            .collect::<Vec<_>>();
+
+        serde_json::from_value(json!({
+            "bars": [],
+            "beats": [],
+            "tatums": [],
+            "segments": [],
+            "sections": sections,
+            "meta": {
+                "analyzer_version": "4.0.0",
+                "platform": "Linux",
+                "detailed_status": "OK",
+                "status_code": 0,
+                "timestamp": 0,
+                "analysis_time": 1.0,
+                "input_process": "libvorbisfile L+R 44100->22050",
+            },
+            "track": {
+                "num_samples": 4_410_000,
+                "duration": DURATION_MS as f32 / 1000.0,
+                "sample_md5": "",
+                "offset_seconds": 0,
+                "window_seconds": 0,
+                "analysis_sample_rate": 22050,
+                "analysis_channels": 1,
+                "end_of_fade_in": 0.0,
+                "start_of_fade_out": start_of_fade_out,
+                "loudness": -8.0,
+                "tempo": 120.0,
+                "tempo_confidence": 1.0,
+                "time_signature": 4,
+                "time_signature_confidence": 1.0,
+                "key": 0,
+                "key_confidence": 1.0,
+                "mode": 1,
+                "mode_confidence": 1.0,
+                "codestring": "",
+                "code_version": 3.15,
+                "echoprintstring": "",
+                "echoprint_version": 4.15,
+                "synchstring": "",
+                "synch_version": 1.0,
+                "rhythmstring": "",
+                "rhythm_version": 1.0,
+            },
+        }))
+        .unwrap()
+    }
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!((actual - expected).abs() < 1e-4, "{} is not {}", actual, expected);
+    }
+
+    #[test]
+    fn mix_out_is_at_fade_out_inside_window() {
+        let analysis = analysis(193.5, &[0.0, 120.0, 195.0]);
+
+        assert_eq!(mix_out_point(DURATION_MS, Some(&analysis)), 193_500);
+    }
+
+    #[test]
+    fn mix_out_falls_back_to_last_section_inside_window() {
+        // Fade out is too early, the section at 120s is too
+        let analysis = analysis(150.0, &[0.0, 120.0, 190.0, 196.5]);
+
+        assert_eq!(mix_out_point(DURATION_MS, Some(&analysis)), 196_500);
+    }
+
+    #[test]
+    fn mix_out_is_at_the_end_with_nothing_inside_window() {
+        let analysis = analysis(150.0, &[0.0, 120.0]);
+
+        assert_eq!(mix_out_point(DURATION_MS, Some(&analysis)), DURATION_MS);
+    }
+
+    #[test]
+    fn mix_out_is_at_the_end_with_no_analysis() {
+        assert_eq!(mix_out_point(DURATION_MS, None), DURATION_MS);
+    }
+
+    #[test]
+    fn short_track_window_starts_at_the_beginning() {
+        let analysis = analysis(5.0, &[0.0]);
+
+        assert_eq!(mix_out_point(10_000, Some(&analysis)), 5000);
+    }
+
+    #[test]
+    fn fade_out_steps_go_from_almost_full_volume_to_silence() {
+        let steps = fade_steps(80, 5000, FadeCurve::Linear, Fade::Out);
+
+        assert_eq!(steps.len(), 10);
+        assert_eq!(steps.first(), Some(&(0, 72)));
+        assert_eq!(steps.last(), Some(&(4500, 0)));
+        assert!(steps.windows(2).all(|pair| pair[0].1 >= pair[1].1));
+    }
+
+    #[test]
+    fn fade_in_steps_end_at_full_volume() {
+        let steps = fade_steps(80, 5000, FadeCurve::EqualPower, Fade::In);
+
+        assert_eq!(steps.len(), 10);
+        assert_eq!(steps.first().map(|&(offset, _)| offset), Some(0));
+        assert_eq!(steps.last(), Some(&(4500, 80)));
+        assert!(steps.windows(2).all(|pair| pair[0].1 <= pair[1].1));
+    }
+
+    #[test]
+    fn fade_shorter_than_step_is_a_single_change() {
+        assert_eq!(fade_steps(80, FADE_STEP_MS / 2, FadeCurve::Linear, Fade::Out), vec![(0, 0)]);
+        assert_eq!(fade_steps(80, FADE_STEP_MS / 2, FadeCurve::SCurve, Fade::In), vec![(0, 80)]);
+        assert_eq!(fade_steps(80, 0, FadeCurve::Linear, Fade::In), vec![(0, 80)]);
+    }
+
+    #[test]
+    fn all_curves_go_from_silence_to_full_volume() {
+        for &curve in &[FadeCurve::Linear, FadeCurve::EqualPower, FadeCurve::SCurve] {
+            assert_close(curve.gain(0.0), 0.0);
+            assert_close(curve.gain(1.0), 1.0);
+            // Position out of range is clamped
+            assert_close(curve.gain(-0.5), 0.0);
+            assert_close(curve.gain(1.5), 1.0);
+        }
+    }
+
+    #[test]
+    fn curves_differ_in_the_middle() {
+        assert_close(FadeCurve::Linear.gain(0.5), 0.5);
+        assert_close(FadeCurve::EqualPower.gain(0.5), 0.70710677);
+        assert_close(FadeCurve::SCurve.gain(0.5), 0.5);
+
+        assert_close(FadeCurve::Linear.gain(0.25), 0.25);
+        assert_close(FadeCurve::SCurve.gain(0.25), 0.15625);
+        assert!(FadeCurve::EqualPower.gain(0.25) > FadeCurve::Linear.gain(0.25));
+    }
+}
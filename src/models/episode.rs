@@ -38,6 +38,14 @@ impl MissingColumns for FullEpisode {
             COL_TRACK_ARTISTS,
             COL_TRACK_ALBUM,
             COL_TRACK_BPM,
+            COL_TRACK_KEY,
+            COL_TRACK_ENERGY,
+            COL_TRACK_DANCEABILITY,
+            COL_TRACK_VALENCE,
+            COL_TRACK_LOUDNESS,
+            COL_TRACK_ACOUSTICNESS,
+            COL_TRACK_INSTRUMENTALNESS,
+            COL_TRACK_TIME_SIGNATURE,
             COL_TRACK_RATE,
             COL_TRACK_SAVED,
         ]
@@ -81,6 +89,14 @@ impl MissingColumns for SimplifiedEpisode {
             COL_TRACK_ARTISTS,
             COL_TRACK_ALBUM,
             COL_TRACK_BPM,
+            COL_TRACK_KEY,
+            COL_TRACK_ENERGY,
+            COL_TRACK_DANCEABILITY,
+            COL_TRACK_VALENCE,
+            COL_TRACK_LOUDNESS,
+            COL_TRACK_ACOUSTICNESS,
+            COL_TRACK_INSTRUMENTALNESS,
+            COL_TRACK_TIME_SIGNATURE,
             COL_TRACK_RATE,
             COL_TRACK_SAVED,
         ]
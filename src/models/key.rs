@@ -0,0 +1,99 @@
+use serde_derive::{Deserialize, Serialize};
+use std::{
+    fmt,
+    sync::atomic::{AtomicU8, Ordering},
+};
+
+static CURRENT_NOTATION: AtomicU8 = AtomicU8::new(KeyNotation::Camelot as u8);
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyNotation {
+    Camelot = 0,
+    OpenKey = 1,
+}
+
+impl Default for KeyNotation {
+    fn default() -> Self { KeyNotation::Camelot }
+}
+
+impl KeyNotation {
+    pub fn current() -> KeyNotation {
+        match CURRENT_NOTATION.load(Ordering::Relaxed) {
+            1 => KeyNotation::OpenKey,
+            _ => KeyNotation::Camelot,
+        }
+    }
+
+    pub fn set_current(notation: KeyNotation) { CURRENT_NOTATION.store(notation as u8, Ordering::Relaxed); }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            KeyNotation::Camelot => "camelot",
+            KeyNotation::OpenKey => "open_key",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<KeyNotation> {
+        match name {
+            "camelot" => Some(KeyNotation::Camelot),
+            "open_key" => Some(KeyNotation::OpenKey),
+            _ => None,
+        }
+    }
+}
+
+/// Musical key as a position on the Camelot wheel:
+/// number 1..=12 and minor (A) or major (B) ring
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub struct Key {
+    pub number: u8,
+    pub major: bool,
+}
+
+impl Key {
+    /// Builds key from Spotify pitch class notation (0 = C, 1 = C#/Db, …, 11 =
+    /// B), returns `None` for unknown key (pitch class -1)
+    pub fn from_pitch(pitch: i32, major: bool) -> Option<Key> {
+        if !(0..12).contains(&pitch) {
+            return None;
+        }
+
+        let shift = if major { 7 } else { 4 };
+
+        Some(Key {
+            number: ((7 * pitch + shift) % 12) as u8 + 1,
+            major,
+        })
+    }
+
+    /// Packs key into a store value, sorting in wheel order (1A, 1B, 2A, …,
+    /// 12B), 0 is reserved for unknown key
+    pub fn to_code(self) -> u32 { (self.number as u32 - 1) * 2 + self.major as u32 + 1 }
+
+    pub fn from_code(code: u32) -> Option<Key> {
+        if code == 0 || code > 24 {
+            return None;
+        }
+
+        Some(Key {
+            number: ((code - 1) / 2) as u8 + 1,
+            major: (code - 1) % 2 == 1,
+        })
+    }
+
+    pub fn display(self, notation: KeyNotation) -> KeyDisplay { KeyDisplay(self, notation) }
+}
+
+pub struct KeyDisplay(Key, KeyNotation);
+
+impl fmt::Display for KeyDisplay {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let KeyDisplay(key, notation) = *self;
+
+        match notation {
+            KeyNotation::Camelot => write!(f, "{}{}", key.number, if key.major { 'B' } else { 'A' }),
+            KeyNotation::OpenKey => write!(f, "{}{}", (key.number + 4) % 12 + 1, if key.major { 'd' } else { 'm' }),
+        }
+    }
+}
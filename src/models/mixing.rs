@@ -0,0 +1,257 @@
+use crate::models::key::Key;
+use rspotify::model::AudioFeatures;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+/// Default max tempo difference between mixed tracks, in percents
+pub const DEFAULT_TEMPO_TOLERANCE: f32 = 6.0;
+
+// Stored in hundredths of percent
+static CURRENT_TEMPO_TOLERANCE: AtomicU32 = AtomicU32::new(600);
+
+pub fn tempo_tolerance() -> f32 { CURRENT_TEMPO_TOLERANCE.load(Ordering::Relaxed) as f32 / 100.0 }
+
+pub fn set_tempo_tolerance(tolerance: f32) {
+    CURRENT_TEMPO_TOLERANCE.store((tolerance.max(0.0) * 100.0).round() as u32, Ordering::Relaxed);
+}
+
+/// Mixing relevant traits of a track
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Harmony {
+    pub key: Option<Key>,
+    pub tempo: f32,
+}
+
+impl Harmony {
+    pub fn new(key: Option<Key>, tempo: f32) -> Harmony { Harmony { key, tempo } }
+
+    pub fn from_features(feats: &AudioFeatures) -> Harmony {
+        Harmony {
+            key: Key::from_pitch(feats.key, feats.mode > 0.5),
+            tempo: feats.tempo,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum KeyMatch {
+    Same,
+    Relative,
+    Adjacent,
+}
+
+impl KeyMatch {
+    pub fn between(from: Key, to: Key) -> Option<KeyMatch> {
+        if from == to {
+            Some(KeyMatch::Same)
+        } else if from.number == to.number {
+            Some(KeyMatch::Relative)
+        } else if from.major == to.major && matches!((from.number + 12 - to.number) % 12, 1 | 11) {
+            Some(KeyMatch::Adjacent)
+        } else {
+            None
+        }
+    }
+
+    fn weight(self) -> f32 {
+        match self {
+            KeyMatch::Same => 1.0,
+            KeyMatch::Relative => 0.8,
+            KeyMatch::Adjacent => 0.7,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TempoMatch {
+    Same,
+    HalfTime,
+    DoubleTime,
+}
+
+impl TempoMatch {
+    /// Finds the closest way to beat match `to` with `from`,
+    /// returns match kind and tempo difference in percents
+    pub fn between(from: f32, to: f32, tolerance: f32) -> Option<(TempoMatch, f32)> {
+        if from <= 0.0 || to <= 0.0 {
+            return None;
+        }
+
+        [
+            (TempoMatch::Same, 1.0),
+            (TempoMatch::HalfTime, 2.0),
+            (TempoMatch::DoubleTime, 0.5),
+        ]
+        .iter()
+        .map(|&(kind, factor)| (kind, (to * factor - from).abs() / from * 100.0))
+        .filter(|&(_, diff)| diff <= tolerance)
+        .min_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal))
+    }
+
+    fn weight(self) -> f32 {
+        match self {
+            TempoMatch::Same => 1.0,
+            TempoMatch::HalfTime | TempoMatch::DoubleTime => 0.9,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Compatibility {
+    pub key: KeyMatch,
+    pub tempo: TempoMatch,
+    pub tempo_diff: f32,
+    pub tolerance: f32,
+}
+
+impl Compatibility {
+    /// Checks if `to` track mixes well after `from` track,
+    /// tracks with unknown key or tempo are never compatible
+    pub fn between(from: &Harmony, to: &Harmony, tolerance: f32) -> Option<Compatibility> {
+        let key = KeyMatch::between(from.key?, to.key?)?;
+        let (tempo, tempo_diff) = TempoMatch::between(from.tempo, to.tempo, tolerance)?;
+
+        Some(Compatibility {
+            key,
+            tempo,
+            tempo_diff,
+            tolerance,
+        })
+    }
+
+    /// Match quality from 0.0 (barely compatible) to 1.0 (perfect match)
+    pub fn score(&self) -> f32 {
+        let tempo_fit = if self.tolerance > 0.0 {
+            1.0 - self.tempo_diff / self.tolerance * 0.5
+        } else {
+            1.0
+        };
+
+        self.key.weight() * self.tempo.weight() * tempo_fit
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(number: u8, major: bool) -> Option<Key> { Some(Key { number, major }) }
+
+    fn harmony(number: u8, major: bool, tempo: f32) -> Harmony { Harmony::new(key(number, major), tempo) }
+
+    #[test]
+    fn same_key_matches() {
+        assert_eq!(
+            KeyMatch::between(Key { number: 8, major: false }, Key { number: 8, major: false }),
+            Some(KeyMatch::Same)
+        );
+    }
+
+    #[test]
+    fn relative_key_matches() {
+        assert_eq!(
+            KeyMatch::between(Key { number: 8, major: false }, Key { number: 8, major: true }),
+            Some(KeyMatch::Relative)
+        );
+    }
+
+    #[test]
+    fn adjacent_keys_match() {
+        let from = Key { number: 8, major: false };
+
+        assert_eq!(
+            KeyMatch::between(from, Key { number: 7, major: false }),
+            Some(KeyMatch::Adjacent)
+        );
+        assert_eq!(
+            KeyMatch::between(from, Key { number: 9, major: false }),
+            Some(KeyMatch::Adjacent)
+        );
+    }
+
+    #[test]
+    fn adjacent_keys_wrap_around_wheel() {
+        let twelve = Key { number: 12, major: true };
+        let one = Key { number: 1, major: true };
+
+        assert_eq!(KeyMatch::between(twelve, one), Some(KeyMatch::Adjacent));
+        assert_eq!(KeyMatch::between(one, twelve), Some(KeyMatch::Adjacent));
+    }
+
+    #[test]
+    fn distant_keys_do_not_match() {
+        let from = Key { number: 8, major: false };
+
+        assert_eq!(
+            KeyMatch::between(from, Key {
+                number: 10,
+                major: false
+            }),
+            None
+        );
+        assert_eq!(KeyMatch::between(from, Key { number: 9, major: true }), None);
+        assert_eq!(KeyMatch::between(from, Key { number: 2, major: false }), None);
+    }
+
+    #[test]
+    fn tempo_within_tolerance_matches() {
+        let (kind, diff) = TempoMatch::between(120.0, 125.0, 6.0).unwrap();
+
+        assert_eq!(kind, TempoMatch::Same);
+        assert!((diff - 4.1667).abs() < 0.01);
+        assert_eq!(TempoMatch::between(120.0, 130.0, 6.0), None);
+    }
+
+    #[test]
+    fn tempo_matches_half_and_double_time() {
+        assert_eq!(
+            TempoMatch::between(128.0, 64.0, 1.0).map(|(kind, _)| kind),
+            Some(TempoMatch::HalfTime)
+        );
+        assert_eq!(
+            TempoMatch::between(70.0, 141.0, 2.0).map(|(kind, _)| kind),
+            Some(TempoMatch::DoubleTime)
+        );
+    }
+
+    #[test]
+    fn unknown_tempo_never_matches() {
+        assert_eq!(TempoMatch::between(0.0, 120.0, 6.0), None);
+        assert_eq!(TempoMatch::between(120.0, 0.0, 6.0), None);
+    }
+
+    #[test]
+    fn unknown_key_is_never_compatible() {
+        let from = harmony(8, false, 120.0);
+
+        assert_eq!(Compatibility::between(&from, &Harmony::new(None, 120.0), 6.0), None);
+    }
+
+    #[test]
+    fn compatibility_needs_both_key_and_tempo() {
+        let from = harmony(8, false, 120.0);
+
+        assert!(Compatibility::between(&from, &harmony(9, false, 122.0), 6.0).is_some());
+        assert_eq!(Compatibility::between(&from, &harmony(9, false, 140.0), 6.0), None);
+        assert_eq!(Compatibility::between(&from, &harmony(3, false, 120.0), 6.0), None);
+    }
+
+    #[test]
+    fn perfect_match_scores_highest() {
+        let from = harmony(8, false, 120.0);
+        let score = |to: Harmony| Compatibility::between(&from, &to, 6.0).unwrap().score();
+
+        assert!((score(harmony(8, false, 120.0)) - 1.0).abs() < f32::EPSILON);
+        assert!(score(harmony(8, false, 120.0)) > score(harmony(8, true, 120.0)));
+        assert!(score(harmony(8, true, 120.0)) > score(harmony(9, false, 120.0)));
+        assert!(score(harmony(8, false, 120.0)) > score(harmony(8, false, 60.0)));
+        assert!(score(harmony(8, false, 121.0)) > score(harmony(8, false, 126.0)));
+    }
+
+    #[test]
+    fn zero_tolerance_accepts_only_exact_tempo() {
+        let from = harmony(8, false, 120.0);
+
+        assert!(Compatibility::between(&from, &harmony(8, false, 120.0), 0.0).is_some());
+        assert_eq!(Compatibility::between(&from, &harmony(8, false, 120.5), 0.0), None);
+    }
+}
@@ -1,10 +1,15 @@
 pub mod album;
+pub mod analysis;
 pub mod artist;
 pub mod category;
 pub mod common;
+pub mod crossfade;
 pub mod episode;
+pub mod key;
+pub mod mixing;
 pub mod page;
 pub mod playlist;
+pub mod set;
 pub mod show;
 pub mod track;
 pub mod user;
@@ -13,6 +18,7 @@ pub use album::*;
 pub use artist::*;
 pub use category::*;
 pub use common::*;
+pub use key::*;
 pub use page::*;
 pub use playlist::*;
 pub use show::*;
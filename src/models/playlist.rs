@@ -1,4 +1,4 @@
-use crate::models::common::*;
+use crate::{models::common::*, services::store::StorageModel};
 use gdk_pixbuf::Pixbuf;
 use glib::{IsA, StaticType, Type};
 use gtk::prelude::GtkListStoreExtManual;
@@ -100,6 +100,26 @@ impl RowLike for SimplifiedPlaylist {
     fn append_to_store<S: IsA<gtk::ListStore>>(&self, store: &S) -> gtk::TreeIter { self.insert_into_store(store) }
 }
 
+/// Playlist details editable by its owner
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PlaylistDetails {
+    pub name: String,
+    pub description: String,
+    pub public: bool,
+    pub collaborative: bool,
+}
+
+impl From<&FullPlaylist> for PlaylistDetails {
+    fn from(playlist: &FullPlaylist) -> Self {
+        PlaylistDetails {
+            name: playlist.name.clone(),
+            description: playlist.description.clone(),
+            public: playlist.public.unwrap_or(false),
+            collaborative: playlist.collaborative,
+        }
+    }
+}
+
 impl PlaylistLike for FullPlaylist {
     fn id(&self) -> &str { &self.id }
 
@@ -262,3 +282,9 @@ impl Merge for FullPlaylist {
         }
     }
 }
+
+impl StorageModel for SimplifiedPlaylist {
+    const TREE_NAME: &'static str = "playlists";
+
+    fn key(&self) -> &str { &self.id }
+}
@@ -0,0 +1,354 @@
+use crate::{
+    models::{
+        key::{Key, KeyNotation},
+        mixing::{tempo_tolerance, Compatibility, Harmony, KeyMatch},
+    },
+    services::store::StorageModel,
+    utils::{csv_field, humanize_time},
+};
+use itertools::Itertools;
+use rspotify::model::{AudioFeatures, FullTrack};
+use serde_derive::{Deserialize, Serialize};
+use std::fmt::Write;
+
+/// Set entry with everything needed to plan and export a set without API
+/// requests
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SetTrack {
+    pub uri: String,
+    pub name: String,
+    pub artists: String,
+    pub duration_ms: u32,
+    pub tempo: f32,
+    pub key: Option<u32>,
+}
+
+impl SetTrack {
+    pub fn new(track: &FullTrack, features: Option<&AudioFeatures>) -> SetTrack {
+        SetTrack {
+            uri: track.uri.clone(),
+            name: track.name.clone(),
+            artists: track.artists.iter().map(|artist| &artist.name).join(", "),
+            duration_ms: track.duration_ms,
+            tempo: features.map_or(0.0, |feats| feats.tempo),
+            key: features
+                .and_then(|feats| Key::from_pitch(feats.key, feats.mode > 0.5))
+                .map(Key::to_code),
+        }
+    }
+
+    pub fn key(&self) -> Option<Key> { self.key.and_then(Key::from_code) }
+
+    pub fn harmony(&self) -> Harmony { Harmony::new(self.key(), self.tempo) }
+
+    pub fn key_name(&self) -> String {
+        self.key()
+            .map(|key| key.display(KeyNotation::current()).to_string())
+            .unwrap_or_default()
+    }
+}
+
+/// Mix from the previous track into this one
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transition {
+    /// Time from the set start when the track starts
+    pub start_ms: u32,
+    /// Tempo change in BPM, `None` for the first track or unknown tempo
+    pub tempo_delta: Option<f32>,
+    /// Key relation to the previous track, `None` if keys clash or unknown
+    pub key_match: Option<KeyMatch>,
+    /// Whether tracks can be mixed within current tempo tolerance
+    pub compatible: bool,
+}
+
+impl Transition {
+    pub fn tempo_delta_label(&self) -> String { self.tempo_delta.map_or_else(String::new, |delta| format!("{:+.1}", delta)) }
+
+    pub fn key_match_label(&self, is_first: bool) -> &'static str {
+        match self.key_match {
+            _ if is_first => "",
+            Some(KeyMatch::Same) => "same key",
+            Some(KeyMatch::Relative) => "relative",
+            Some(KeyMatch::Adjacent) => "adjacent",
+            None => "clash",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct DjSet {
+    pub name: String,
+    pub tracks: Vec<SetTrack>,
+}
+
+impl StorageModel for DjSet {
+    const TREE_NAME: &'static str = "sets";
+
+    fn key(&self) -> &str { &self.name }
+}
+
+impl DjSet {
+    pub fn new(name: String, tracks: Vec<SetTrack>) -> DjSet { DjSet { name, tracks } }
+
+    pub fn duration_ms(&self) -> u32 { self.tracks.iter().map(|track| track.duration_ms).sum() }
+
+    pub fn uris(&self) -> Vec<String> { self.tracks.iter().map(|track| track.uri.clone()).collect() }
+
+    /// Transition into every track of the set, the first one has no previous
+    /// track
+    pub fn transitions(&self) -> Vec<Transition> {
+        let tolerance = tempo_tolerance();
+        let mut start_ms = 0;
+        let mut prev: Option<&SetTrack> = None;
+
+        self.tracks
+            .iter()
+            .map(|track| {
+                let transition = match prev {
+                    Some(prev) => Transition {
+                        start_ms,
+                        tempo_delta: if prev.tempo > 0.0 && track.tempo > 0.0 {
+                            Some(track.tempo - prev.tempo)
+                        } else {
+                            None
+                        },
+                        key_match: prev.key().zip(track.key()).and_then(|(from, to)| KeyMatch::between(from, to)),
+                        compatible: Compatibility::between(&prev.harmony(), &track.harmony(), tolerance).is_some(),
+                    },
+                    None => Transition {
+                        start_ms,
+                        tempo_delta: None,
+                        key_match: None,
+                        compatible: true,
+                    },
+                };
+
+                start_ms += track.duration_ms;
+                prev = Some(track);
+                transition
+            })
+            .collect()
+    }
+
+    pub fn to_m3u(&self) -> String {
+        let mut output = String::from("#EXTM3U\n");
+        let _ = writeln!(output, "#PLAYLIST:{}", self.name);
+
+        for track in &self.tracks {
+            let _ = writeln!(
+                output,
+                "#EXTINF:{},{} - {}",
+                track.duration_ms / 1000,
+                track.artists,
+                track.name
+            );
+            let _ = writeln!(output, "{}", track.uri);
+        }
+
+        output
+    }
+
+    pub fn to_csv(&self) -> String {
+        let mut output = String::from("position,start,uri,artists,name,duration,bpm,key,bpm_delta,key_match\n");
+
+        for (index, (track, transition)) in self.tracks.iter().zip(self.transitions()).enumerate() {
+            let _ = writeln!(
+                output,
+                "{},{},{},{},{},{},{:.1},{},{},{}",
+                index + 1,
+                humanize_time(transition.start_ms),
+                track.uri,
+                csv_field(&track.artists),
+                csv_field(&track.name),
+                humanize_time(track.duration_ms),
+                track.tempo,
+                track.key_name(),
+                transition.tempo_delta_label(),
+                transition.key_match_label(index == 0),
+            );
+        }
+
+        output
+    }
+
+    /// Plain text sheet to print and keep at hand while playing the set
+    pub fn to_cue_sheet(&self) -> String {
+        let mut output = String::new();
+
+        let _ = writeln!(output, "{}", self.name);
+        let _ = writeln!(
+            output,
+            "{} tracks, {}\n",
+            self.tracks.len(),
+            humanize_time(self.duration_ms())
+        );
+
+        for (index, (track, transition)) in self.tracks.iter().zip(self.transitions()).enumerate() {
+            let _ = writeln!(
+                output,
+                "{:>3}. {:>8}  {} - {}",
+                index + 1,
+                humanize_time(transition.start_ms),
+                track.artists,
+                track.name
+            );
+
+            let _ = write!(output, "               {:.0} BPM  {}", track.tempo, track.key_name());
+
+            if index > 0 {
+                let _ = write!(
+                    output,
+                    "  ({} BPM, {}{})",
+                    transition.tempo_delta_label(),
+                    transition.key_match_label(false),
+                    if transition.compatible { "" } else { ", hard mix" }
+                );
+            }
+
+            output.push('\n');
+        }
+
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn track(number: u32, artists: &str, name: &str, duration_ms: u32, tempo: f32, key: Option<u32>) -> SetTrack {
+        SetTrack {
+            uri: format!("spotify:track:{}", number),
+            name: name.to_owned(),
+            artists: artists.to_owned(),
+            duration_ms,
+            tempo,
+            key,
+        }
+    }
+
+    /// 8A at 120 BPM, 8B at 124 BPM, 3A at 140 BPM
+    fn set() -> DjSet {
+        DjSet::new("Warm up".to_owned(), vec![
+            track(1, "A", "Intro", 180_000, 120.0, Some(15)),
+            track(2, "B, C", "Say \"hi\"", 200_000, 124.0, Some(16)),
+            track(3, "D", "Line\rbreak", 240_000, 140.0, Some(5)),
+        ])
+    }
+
+    #[test]
+    fn transitions_start_after_previous_tracks() {
+        let starts = set()
+            .transitions()
+            .iter()
+            .map(|transition| transition.start_ms)
+            .collect::<Vec<_>>();
+
+        assert_eq!(starts, vec![0, 180_000, 380_000]);
+        assert_eq!(set().duration_ms(), 620_000);
+    }
+
+    #[test]
+    fn transitions_have_tempo_deltas_and_key_matches() {
+        let transitions = set().transitions();
+
+        assert_eq!(transitions[0], Transition {
+            start_ms: 0,
+            tempo_delta: None,
+            key_match: None,
+            compatible: true,
+        });
+        assert_eq!(transitions[1].tempo_delta, Some(4.0));
+        assert_eq!(transitions[1].key_match, Some(KeyMatch::Relative));
+        assert!(transitions[1].compatible);
+        assert_eq!(transitions[2].tempo_delta, Some(16.0));
+        assert_eq!(transitions[2].key_match, None);
+        assert!(!transitions[2].compatible);
+    }
+
+    #[test]
+    fn transitions_from_unknown_tempo_and_key_have_no_delta() {
+        let mut set = set();
+        set.tracks.push(track(4, "E", "Unanalyzed", 100_000, 0.0, None));
+
+        let last = set.transitions()[3];
+
+        assert_eq!(last.start_ms, 620_000);
+        assert_eq!(last.tempo_delta, None);
+        assert_eq!(last.key_match, None);
+        assert!(!last.compatible);
+    }
+
+    #[test]
+    fn transition_labels() {
+        let transitions = set().transitions();
+
+        assert_eq!(transitions[0].tempo_delta_label(), "");
+        assert_eq!(transitions[0].key_match_label(true), "");
+        assert_eq!(transitions[1].tempo_delta_label(), "+4.0");
+        assert_eq!(transitions[1].key_match_label(false), "relative");
+        assert_eq!(transitions[2].key_match_label(false), "clash");
+
+        let same = Transition {
+            tempo_delta: Some(-2.0),
+            key_match: Some(KeyMatch::Same),
+            ..transitions[1]
+        };
+        assert_eq!(same.tempo_delta_label(), "-2.0");
+        assert_eq!(same.key_match_label(false), "same key");
+
+        let adjacent = Transition {
+            key_match: Some(KeyMatch::Adjacent),
+            ..transitions[1]
+        };
+        assert_eq!(adjacent.key_match_label(false), "adjacent");
+    }
+
+    #[test]
+    fn set_is_exported_to_m3u() {
+        assert_eq!(
+            set().to_m3u(),
+            concat!(
+                "#EXTM3U\n",
+                "#PLAYLIST:Warm up\n",
+                "#EXTINF:180,A - Intro\n",
+                "spotify:track:1\n",
+                "#EXTINF:200,B, C - Say \"hi\"\n",
+                "spotify:track:2\n",
+                "#EXTINF:240,D - Line\rbreak\n",
+                "spotify:track:3\n",
+            )
+        );
+    }
+
+    #[test]
+    fn set_is_exported_to_csv_with_quoted_fields() {
+        assert_eq!(
+            set().to_csv(),
+            concat!(
+                "position,start,uri,artists,name,duration,bpm,key,bpm_delta,key_match\n",
+                "1,0:00,spotify:track:1,A,Intro,3:00,120.0,8A,,\n",
+                "2,3:00,spotify:track:2,\"B, C\",\"Say \"\"hi\"\"\",3:20,124.0,8B,+4.0,relative\n",
+                "3,6:20,spotify:track:3,D,\"Line\rbreak\",4:00,140.0,3A,+16.0,clash\n",
+            )
+        );
+    }
+
+    #[test]
+    fn set_is_exported_to_cue_sheet() {
+        assert_eq!(
+            set().to_cue_sheet(),
+            concat!(
+                "Warm up\n",
+                "3 tracks, 10:20\n",
+                "\n",
+                "  1.     0:00  A - Intro\n",
+                "               120 BPM  8A\n",
+                "  2.     3:00  B, C - Say \"hi\"\n",
+                "               124 BPM  8B  (+4.0 BPM, relative)\n",
+                "  3.     6:20  D - Line\rbreak\n",
+                "               140 BPM  3A  (+16.0 BPM, clash, hard mix)\n",
+            )
+        );
+    }
+}
@@ -11,8 +11,8 @@ use glib::{IsA, StaticType, Type};
 use gtk::prelude::GtkListStoreExtManual;
 use itertools::Itertools;
 use rspotify::model::{
-    FullTrack, Image, PlayHistory, PlayingItem, PlaylistItem, SavedTrack, SimplifiedAlbum, SimplifiedArtist, SimplifiedTrack,
-    Type as ModelType,
+    AudioFeatures, FullTrack, Image, PlayHistory, PlayingItem, PlaylistItem, SavedTrack, SimplifiedAlbum, SimplifiedArtist,
+    SimplifiedTrack, Type as ModelType,
 };
 use std::{collections::HashMap, time::SystemTime};
 
@@ -35,6 +35,14 @@ pub mod constants {
     pub const COL_TRACK_ARTIST_URI: u32 = 14;
     pub const COL_TRACK_RATE: u32 = 15;
     pub const COL_TRACK_SAVED: u32 = 16;
+    pub const COL_TRACK_KEY: u32 = 17;
+    pub const COL_TRACK_ENERGY: u32 = 18;
+    pub const COL_TRACK_DANCEABILITY: u32 = 19;
+    pub const COL_TRACK_VALENCE: u32 = 20;
+    pub const COL_TRACK_LOUDNESS: u32 = 21;
+    pub const COL_TRACK_ACOUSTICNESS: u32 = 22;
+    pub const COL_TRACK_INSTRUMENTALNESS: u32 = 23;
+    pub const COL_TRACK_TIME_SIGNATURE: u32 = 24;
 }
 pub use self::constants::*;
 
@@ -151,6 +159,14 @@ impl<T: TrackLike> RowLike for T {
             String::static_type(), // first artist uri
             u32::static_type(),    // rate/popularity
             bool::static_type(),   // saved in library
+            u32::static_type(),    // camelot key code
+            f32::static_type(),    // energy
+            f32::static_type(),    // danceability
+            f32::static_type(),    // valence
+            f32::static_type(),    // loudness
+            f32::static_type(),    // acousticness
+            f32::static_type(),    // instrumentalness
+            u32::static_type(),    // time signature
         ]
     }
 
@@ -372,6 +388,46 @@ impl MissingColumns for SimplifiedTrack {
     }
 }
 
+/// Stands in for a track Spotify doesn't know, so that lists of stored uris
+/// keep one row per uri
+pub fn unknown_track(uri: &str) -> FullTrack {
+    FullTrack {
+        album: SimplifiedAlbum::empty(),
+        artists: Vec::new(),
+        available_markets: Vec::new(),
+        disc_number: 0,
+        duration_ms: 0,
+        explicit: false,
+        external_ids: HashMap::new(),
+        external_urls: Default::default(),
+        href: None,
+        id: None,
+        is_local: false,
+        is_playable: Some(false),
+        linked_from: None,
+        restrictions: None,
+        name: format!("Unknown track {}", uri),
+        popularity: 0,
+        preview_url: None,
+        track_number: 0,
+        _type: ModelType::Track,
+        uri: uri.to_owned(),
+    }
+}
+
+/// Lines tracks up with the uris they were asked for, with a placeholder for
+/// every uri there's no track for
+pub fn tracks_for_uris(uris: &[String], tracks: Vec<FullTrack>) -> Vec<FullTrack> {
+    let tracks = tracks
+        .into_iter()
+        .map(|track| (track.uri.clone(), track))
+        .collect::<HashMap<_, _>>();
+
+    uris.iter()
+        .map(|uri| tracks.get(uri).cloned().unwrap_or_else(|| unknown_track(uri)))
+        .collect()
+}
+
 impl ToFull for SimplifiedTrack {
     type Full = FullTrack;
 
@@ -519,3 +575,15 @@ impl StorageModel for FullTrack {
 
     fn key(&self) -> &str { self.id() }
 }
+
+impl StorageModel for AudioFeatures {
+    const TREE_NAME: &'static str = "features";
+
+    fn key(&self) -> &str { &self.id }
+}
+
+impl StorageModel for SavedTrack {
+    const TREE_NAME: &'static str = "saved_tracks";
+
+    fn key(&self) -> &str { self.track.id() }
+}
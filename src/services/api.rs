@@ -1,11 +1,15 @@
+use crate::{
+    models::{playlist::PlaylistDetails, set::DjSet},
+    services::store::StorageError,
+};
 use async_trait::async_trait;
 use rspotify::{
     client::ClientResult,
     model::{
         AudioAnalysis, AudioFeatures, Category, CurrentPlaybackContext, CursorBasedPage, Device, FullAlbum, FullArtist,
         FullEpisode, FullPlaylist, FullShow, FullTrack, Page, PlayHistory, PlaylistItem, PrivateUser, PublicUser, RepeatState,
-        SavedAlbum, SavedTrack, Show, SimplifiedAlbum, SimplifiedEpisode, SimplifiedPlaylist, SimplifiedShow, SimplifiedTrack,
-        Type,
+        SavedAlbum, SavedTrack, SearchResult, SearchType, Show, SimplifiedAlbum, SimplifiedEpisode, SimplifiedPlaylist,
+        SimplifiedShow, SimplifiedTrack, Type,
     },
 };
 use serde_json::{Map, Value};
@@ -15,9 +19,17 @@ impl<T> ThreadSafe for T where T: Send + Sync + 'static {}
 
 #[async_trait]
 pub trait PlaybackQueueApi {
-    async fn dequeue_tracks(&mut self, uris: &[String]) -> ClientResult<()>;
+    /// Removes tracks at the given queue positions
+    async fn dequeue_tracks(&mut self, positions: &[usize]) -> ClientResult<()>;
     async fn enqueue_tracks(&mut self, uris: &[String]) -> ClientResult<()>;
+    async fn insert_queue_tracks(&mut self, position: usize, uris: &[String]) -> ClientResult<()>;
+    async fn move_queue_track(&mut self, from: usize, to: usize) -> ClientResult<()>;
+    async fn reorder_queue_tracks(&mut self, uris: &[String]) -> ClientResult<()>;
     async fn get_queue_tracks(&self) -> ClientResult<Vec<FullTrack>>;
+
+    /// Hands the next queued track over to Spotify player queue, only once
+    /// while the given track plays
+    async fn feed_queue_track(&mut self, playing_uri: &str) -> ClientResult<Option<String>>;
 }
 
 #[async_trait]
@@ -33,6 +45,26 @@ pub trait PlaylistsStorageApi {
     async fn add_my_playlists(&self, uris: &[String], public: bool) -> ClientResult<()>;
     async fn remove_my_playlists(&self, uris: &[String]) -> ClientResult<()>;
     async fn are_my_playlists(&self, uris: &[String]) -> ClientResult<Vec<bool>>;
+
+    async fn create_my_playlist(&self, details: &PlaylistDetails) -> ClientResult<FullPlaylist>;
+    async fn update_playlist_details(&self, uri: &str, details: &PlaylistDetails) -> ClientResult<()>;
+    async fn add_playlist_tracks(&self, uri: &str, uris: &[String], position: Option<u32>) -> ClientResult<String>;
+    /// Removes `(uri, position)` items, positions are in the given snapshot
+    async fn remove_playlist_tracks(
+        &self,
+        uri: &str,
+        tracks: &[(String, u32)],
+        snapshot_id: Option<&str>,
+    ) -> ClientResult<String>;
+    async fn reorder_playlist_tracks(
+        &self,
+        uri: &str,
+        range_start: u32,
+        range_length: u32,
+        insert_before: u32,
+        snapshot_id: Option<&str>,
+    ) -> ClientResult<String>;
+    async fn replace_playlist_tracks(&self, uri: &str, uris: &[String]) -> ClientResult<()>;
 }
 
 #[async_trait]
@@ -123,6 +155,32 @@ pub trait PlaybackControlApi {
 
     async fn get_my_devices(&self) -> ClientResult<Vec<Device>>;
     async fn use_device(&self, id: &str, play: bool) -> ClientResult<()>;
+
+    /// Plays tracks and episodes on their own, and anything else as a context
+    async fn play_uri(&self, uri: String) -> ClientResult<()> {
+        if uri.starts_with("spotify:track:") || uri.starts_with("spotify:episode:") {
+            self.play_tracks(&[uri]).await
+        } else {
+            self.play_context(uri, None).await
+        }
+    }
+}
+
+/// Offline copy of user's library, used when Spotify API is unreachable
+pub trait LibraryMirrorApi {
+    fn mirrored_my_tracks(&self, offset: u32) -> Option<Page<SavedTrack>>;
+    fn mirrored_my_albums(&self, offset: u32) -> Option<Page<SavedAlbum>>;
+    fn mirrored_my_artists(&self) -> Option<CursorBasedPage<FullArtist>>;
+    fn mirrored_my_playlists(&self, offset: u32) -> Option<Page<SimplifiedPlaylist>>;
+    fn mirrored_playlist_tracks(&self, uri: &str, offset: u32) -> Option<Page<PlaylistItem>>;
+}
+
+/// Locally saved DJ sets
+pub trait SetsStorageApi {
+    fn get_set_names(&self) -> Result<Vec<String>, StorageError>;
+    fn get_set(&self, name: &str) -> Result<Option<DjSet>, StorageError>;
+    fn save_set(&self, set: DjSet) -> Result<(), StorageError>;
+    fn delete_set(&self, name: &str) -> Result<(), StorageError>;
 }
 
 #[async_trait]
@@ -135,6 +193,8 @@ pub trait SearchApi {
         tunables: Map<String, Value>,
         limit: u32,
     ) -> ClientResult<Vec<SimplifiedTrack>>;
+
+    async fn search(&self, query: &str, kind: SearchType, offset: u32, limit: u32) -> ClientResult<SearchResult>;
 }
 
 #[async_trait]
@@ -0,0 +1,161 @@
+use crate::services::store::{Collection, Storage, StorageError, StorageModel};
+use rspotify::{
+    client::ClientResult,
+    model::{AudioFeatures, FullTrack},
+};
+use serde_derive::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    future::Future,
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// Tracks metadata can change (popularity, availability), so keep it for a week
+const TRACKS_TTL: Duration = Duration::from_secs(7 * 24 * 3600);
+
+/// Audio features are computed once and almost never change
+const FEATURES_TTL: Duration = Duration::from_secs(90 * 24 * 3600);
+
+#[derive(Serialize, Deserialize)]
+pub struct Cached<T> {
+    cached_at: u64,
+    model: T,
+}
+
+impl<T> Cached<T> {
+    fn new(model: T) -> Self {
+        Cached {
+            cached_at: unix_now(),
+            model,
+        }
+    }
+
+    fn is_fresh(&self, ttl: Duration) -> bool { self.cached_at + ttl.as_secs() > unix_now() }
+}
+
+impl<T: StorageModel<Key = str>> StorageModel for Cached<T> {
+    const TREE_NAME: &'static str = T::TREE_NAME;
+
+    fn key(&self) -> &str { self.model.key() }
+}
+
+/// Read-through cache for tracks and audio features,
+/// stored in sled database under the app cache dir
+pub struct TracksCache {
+    storage: Storage,
+    tracks: Collection<Cached<FullTrack>>,
+    features: Collection<Cached<AudioFeatures>>,
+}
+
+impl TracksCache {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<TracksCache, StorageError> {
+        let storage = Storage::new(path)?;
+        let cache = TracksCache {
+            tracks: storage.collection()?,
+            features: storage.collection()?,
+            storage,
+        };
+
+        cache.evict()?;
+
+        Ok(cache)
+    }
+
+    /// Removes all expired entries
+    pub fn evict(&self) -> Result<usize, StorageError> {
+        Ok(Self::evict_collection(&self.tracks, TRACKS_TTL)? + Self::evict_collection(&self.features, FEATURES_TTL)?)
+    }
+
+    fn evict_collection<T: StorageModel<Key = str>>(
+        collection: &Collection<Cached<T>>,
+        ttl: Duration,
+    ) -> Result<usize, StorageError> {
+        let expired = collection
+            .iter()
+            .filter_map(|item| match item {
+                Ok(item) if item.is_fresh(ttl) => None,
+                Ok(item) => Some(Ok(item.key().to_owned())),
+                Err(error) => Some(Err(error)),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        collection.delete_all(expired.iter().map(String::as_str))?;
+
+        Ok(expired.len())
+    }
+
+    pub fn clear(&self) -> Result<(), StorageError> {
+        self.tracks.clear()?;
+        self.features.clear()?;
+        self.storage.flush()?;
+
+        Ok(())
+    }
+
+    pub async fn get_tracks<F, R>(&self, uris: &[String], fetch: F) -> ClientResult<Vec<FullTrack>>
+    where
+        F: FnOnce(Vec<String>) -> R,
+        R: Future<Output = ClientResult<Vec<FullTrack>>>,
+    {
+        Self::read_through(&self.tracks, TRACKS_TTL, uris, fetch).await
+    }
+
+    pub async fn get_tracks_features<F, R>(&self, uris: &[String], fetch: F) -> ClientResult<Vec<AudioFeatures>>
+    where
+        F: FnOnce(Vec<String>) -> R,
+        R: Future<Output = ClientResult<Vec<AudioFeatures>>>,
+    {
+        Self::read_through(&self.features, FEATURES_TTL, uris, fetch).await
+    }
+
+    /// Fetches only items missing from the cache (or expired) and returns
+    /// all items in the order of requested uris, cache errors are logged
+    /// and never fail the request. Items Spotify doesn't know are skipped,
+    /// so the result can be shorter than `uris`, match items by uri.
+    async fn read_through<T, F, R>(
+        collection: &Collection<Cached<T>>,
+        ttl: Duration,
+        uris: &[String],
+        fetch: F,
+    ) -> ClientResult<Vec<T>>
+    where
+        T: StorageModel<Key = str> + Clone,
+        F: FnOnce(Vec<String>) -> R,
+        R: Future<Output = ClientResult<Vec<T>>>,
+    {
+        let mut found = HashMap::with_capacity(uris.len());
+        let mut missing = Vec::new();
+
+        for uri in uris {
+            let id = uri_to_id(uri);
+
+            match collection.get(id) {
+                Ok(Some(item)) if item.is_fresh(ttl) => {
+                    found.insert(id.to_owned(), item.model);
+                }
+                Ok(_) => missing.push(uri.clone()),
+                Err(error) => {
+                    warn!("tracks cache read error: {}", error);
+                    missing.push(uri.clone());
+                }
+            }
+        }
+
+        if !missing.is_empty() {
+            let fetched = fetch(missing).await?;
+
+            if let Err(error) = collection.put_all(fetched.iter().cloned().map(Cached::new)) {
+                warn!("tracks cache write error: {}", error);
+            }
+
+            found.extend(fetched.into_iter().map(|item| (item.key().to_owned(), item)));
+        }
+
+        Ok(uris.iter().filter_map(|uri| found.get(uri_to_id(uri)).cloned()).collect())
+    }
+}
+
+fn uri_to_id(uri: &str) -> &str { uri.rsplit(':').next().unwrap_or(uri) }
+
+fn unix_now() -> u64 { SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_secs()) }
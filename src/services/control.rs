@@ -0,0 +1,439 @@
+//! # Local control API
+//!
+//! Newline delimited JSON over a Unix socket. Every line sent to the socket is
+//! a command, and every command gets a response line back:
+//!
+//! ```text
+//! > {"command": "play", "uri": "spotify:track:4uLU6hMCjMI75M1A2tKUQC"}
+//! < {"ok":true}
+//! > {"command": "seek", "position_ms": 30000}
+//! < {"ok":true}
+//! > {"command": "status"}
+//! < {"ok":true,"result":{"is_playing":true,...}}
+//! > {"command": "rewind"}
+//! < {"ok":false,"error":"invalid request: unknown variant `rewind`, ..."}
+//! ```
+//!
+//! Commands: `play` (with optional `uri`, resumes playback without it),
+//! `pause`, `next`, `previous`, `seek` (`position_ms`), `volume` (`percent`),
+//! `enqueue` (`uris`), `status` and `subscribe`. After `subscribe` the app
+//! events are sent to the connection as lines like `{"event":"paused"}`.
+
+use crate::{
+    services::api::{PlaybackControlApi, PlaybackQueueApi},
+    subscribe,
+    utils::AsyncCell,
+    AppEvent,
+};
+use futures_util::TryFutureExt;
+use rspotify::{client::ClientResult, model::CurrentPlaybackContext};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    fs::{DirBuilder, Permissions},
+    io::{Error, ErrorKind},
+    os::unix::fs::{DirBuilderExt, PermissionsExt},
+    path::PathBuf,
+};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::{UnixListener, UnixStream},
+    runtime::Runtime,
+    sync::{
+        broadcast::RecvError,
+        mpsc::{unbounded_channel, UnboundedSender},
+    },
+    task::JoinHandle,
+};
+
+#[derive(Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+enum Request {
+    Play { uri: Option<String> },
+    Pause,
+    Next,
+    Previous,
+    Seek { position_ms: u32 },
+    Volume { percent: u8 },
+    Enqueue { uris: Vec<String> },
+    Status,
+    Subscribe,
+}
+
+#[derive(Serialize)]
+struct Response {
+    ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl Response {
+    fn ok(result: Option<Value>) -> Response {
+        Response {
+            ok: true,
+            result,
+            error: None,
+        }
+    }
+
+    fn error(error: String) -> Response {
+        Response {
+            ok: false,
+            result: None,
+            error: Some(error),
+        }
+    }
+}
+
+/// App event as sent to subscribers
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum Event {
+    AuthError { message: String },
+    TokenRefreshed,
+    Error { message: String },
+    RateLimited { retry_after_secs: u64 },
+    LoginSucceeded,
+    LoginFailed { reason: String },
+    AccountSwitched,
+    PlaybackState { state: Option<CurrentPlaybackContext> },
+    TrackChanged { uri: Option<String> },
+    Paused,
+    Resumed,
+    DeviceChanged { device_id: Option<String> },
+    VolumeChanged { percent: u32 },
+}
+
+impl From<AppEvent> for Event {
+    fn from(event: AppEvent) -> Self {
+        match event {
+            AppEvent::SpotifyAuthError(message) => Event::AuthError { message },
+            AppEvent::SpotifyTokenRefreshed => Event::TokenRefreshed,
+            AppEvent::SpotifyError(message) => Event::Error { message },
+            AppEvent::SpotifyRateLimited(timeout) => Event::RateLimited {
+                retry_after_secs: timeout.as_secs(),
+            },
+            AppEvent::SpotifyLoginSucceeded => Event::LoginSucceeded,
+            AppEvent::SpotifyLoginFailed(reason) => Event::LoginFailed { reason },
+            AppEvent::SpotifyAccountSwitched => Event::AccountSwitched,
+            AppEvent::PlaybackStateUpdated(state) => Event::PlaybackState { state: (*state).clone() },
+            AppEvent::PlaybackTrackChanged(uri) => Event::TrackChanged { uri },
+            AppEvent::PlaybackPaused => Event::Paused,
+            AppEvent::PlaybackResumed => Event::Resumed,
+            AppEvent::PlaybackDeviceChanged(device_id) => Event::DeviceChanged { device_id },
+            AppEvent::PlaybackVolumeChanged(percent) => Event::VolumeChanged { percent },
+        }
+    }
+}
+
+pub struct ControlService<C> {
+    client: AsyncCell<C>,
+    path: PathBuf,
+}
+
+impl<C> ControlService<C>
+where
+    C: PlaybackControlApi + PlaybackQueueApi + Send + Sync + 'static,
+{
+    pub fn new(client: AsyncCell<C>, path: PathBuf) -> ControlService<C> { ControlService { client, path } }
+
+    pub fn spawn(self, runtime: &Runtime) -> JoinHandle<Result<!, Error>> {
+        runtime.spawn(self.run().inspect_err(|error| {
+            error!("control server error (no local control is possible): {}", error);
+        }))
+    }
+
+    pub async fn run(self) -> Result<!, Error> {
+        let mut server = self.bind().await?;
+
+        info!("control server is listening at {}", self.path.display());
+
+        loop {
+            let (stream, _) = server.accept().await?;
+            tokio::spawn(Self::handle(self.client.clone(), stream));
+        }
+    }
+
+    /// Binds the socket, replacing the one left by a crashed instance.
+    /// Anyone who can connect can control playback, so the socket is made
+    /// accessible to the user only, and so is its dir, before the socket is
+    /// bound, as the socket is created with default permissions.
+    async fn bind(&self) -> Result<UnixListener, Error> {
+        if let Some(dir) = self.path.parent() {
+            DirBuilder::new().recursive(true).mode(0o700).create(dir)?;
+            // Mode is only set on dirs created above
+            std::fs::set_permissions(dir, Permissions::from_mode(0o700))?;
+        }
+
+        if self.path.exists() {
+            if UnixStream::connect(&self.path).await.is_ok() {
+                return Err(Error::new(ErrorKind::AddrInUse, "another instance is running"));
+            }
+
+            std::fs::remove_file(&self.path)?;
+        }
+
+        let listener = UnixListener::bind(&self.path)?;
+        std::fs::set_permissions(&self.path, Permissions::from_mode(0o600))?;
+
+        Ok(listener)
+    }
+
+    async fn handle(client: AsyncCell<C>, stream: UnixStream) {
+        let (reader, mut writer) = tokio::io::split(stream);
+        let (tx, mut rx) = unbounded_channel::<String>();
+
+        // Responses and events are written by one task, so they are never mixed up
+        tokio::spawn(async move {
+            while let Some(line) = rx.recv().await {
+                if let Err(error) = writer.write_all(line.as_bytes()).await {
+                    debug!("control connection closed: {}", error);
+                    break;
+                }
+            }
+        });
+
+        let mut lines = BufReader::new(reader).lines();
+
+        loop {
+            let line = match lines.next_line().await {
+                Ok(Some(line)) => line,
+                Ok(None) => break,
+                Err(error) => {
+                    debug!("failed to read control command: {}", error);
+                    break;
+                }
+            };
+
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let response = match serde_json::from_str(&line) {
+                Ok(request) => match Self::execute(&client, &tx, request).await {
+                    Ok(result) => Response::ok(result),
+                    Err(error) => Response::error(error.to_string()),
+                },
+                Err(error) => Response::error(format!("invalid request: {}", error)),
+            };
+
+            if tx.send(to_line(&response)).is_err() {
+                break;
+            }
+        }
+    }
+
+    async fn execute(client: &AsyncCell<C>, tx: &UnboundedSender<String>, request: Request) -> ClientResult<Option<Value>> {
+        match request {
+            Request::Play { uri: None } => client.read().await.start_playback().await?,
+            Request::Play { uri: Some(uri) } => client.read().await.play_uri(uri).await?,
+            Request::Pause => client.read().await.pause_playback().await?,
+            Request::Next => client.read().await.play_next_track().await?,
+            Request::Previous => client.read().await.play_prev_track().await?,
+            Request::Seek { position_ms } => client.read().await.seek_track(position_ms).await?,
+            Request::Volume { percent } => client.read().await.set_volume(percent.min(100)).await?,
+            Request::Enqueue { uris } => client.write().await.enqueue_tracks(&uris).await?,
+            Request::Status => {
+                let state = client.read().await.get_playback_state().await?;
+                return Ok(serde_json::to_value(state).ok());
+            }
+            Request::Subscribe => Self::forward_events(tx.clone()),
+        }
+
+        Ok(None)
+    }
+
+    /// Forwards app events to the connection until it's closed
+    fn forward_events(tx: UnboundedSender<String>) {
+        let mut events = subscribe();
+
+        tokio::spawn(async move {
+            loop {
+                match events.recv().await {
+                    Ok(event) => {
+                        if tx.send(to_line(&Event::from(event))).is_err() {
+                            break;
+                        }
+                    }
+                    Err(RecvError::Lagged(count)) => warn!("control subscriber skipped {} events", count),
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        });
+    }
+}
+
+fn to_line<T: Serialize>(value: &T) -> String {
+    let mut line = serde_json::to_string(value).unwrap_or_default();
+    line.push('\n');
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{broadcast, services::FakeSpotify};
+    use std::{sync::Arc, time::Duration};
+    use tokio::{
+        io::{split, Lines, ReadHalf, WriteHalf},
+        sync::RwLock,
+        time::timeout,
+    };
+
+    const FIRST: &str = "spotify:track:0track00000000000000001";
+    const SECOND: &str = "spotify:track:0track00000000000000002";
+
+    type Connection = (Lines<BufReader<ReadHalf<UnixStream>>>, WriteHalf<UnixStream>);
+
+    fn socket_dir(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("spodjfy-control-test-{}-{}", name, std::process::id()))
+    }
+
+    /// Connects to a control connection served with the library fixture
+    fn connect() -> (Connection, AsyncCell<FakeSpotify>) {
+        let client = Arc::new(RwLock::new(FakeSpotify::library()));
+        let (stream, server) = UnixStream::pair().unwrap();
+        tokio::spawn(ControlService::handle(client.clone(), server));
+
+        let (reader, writer) = split(stream);
+        ((BufReader::new(reader).lines(), writer), client)
+    }
+
+    async fn send(connection: &mut Connection, line: &str) {
+        connection.1.write_all(format!("{}\n", line).as_bytes()).await.unwrap();
+    }
+
+    async fn receive(connection: &mut Connection) -> Value {
+        let line = timeout(Duration::from_secs(2), connection.0.next_line())
+            .await
+            .expect("no line received in time")
+            .unwrap()
+            .expect("connection closed");
+
+        serde_json::from_str(&line).unwrap()
+    }
+
+    async fn request(connection: &mut Connection, line: &str) -> Value {
+        send(connection, line).await;
+        receive(connection).await
+    }
+
+    #[tokio::test]
+    async fn socket_is_private_to_user() {
+        let dir = socket_dir("new");
+        let path = dir.join("run").join("control.sock");
+        let service = ControlService::new(Arc::new(RwLock::new(FakeSpotify::library())), path.clone());
+
+        let _listener = service.bind().await.unwrap();
+        let socket_mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        let dir_mode = std::fs::metadata(path.parent().unwrap()).unwrap().permissions().mode();
+
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(socket_mode & 0o777, 0o600);
+        assert_eq!(dir_mode & 0o777, 0o700);
+    }
+
+    #[tokio::test]
+    async fn existing_socket_dir_is_made_private() {
+        let dir = socket_dir("existing");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::set_permissions(&dir, Permissions::from_mode(0o755)).unwrap();
+
+        let service = ControlService::new(Arc::new(RwLock::new(FakeSpotify::library())), dir.join("control.sock"));
+
+        let _listener = service.bind().await.unwrap();
+        let dir_mode = std::fs::metadata(&dir).unwrap().permissions().mode();
+
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(dir_mode & 0o777, 0o700);
+    }
+
+    #[tokio::test]
+    async fn commands_get_responses_in_order() {
+        let (mut connection, client) = connect();
+
+        send(
+            &mut connection,
+            &format!(r#"{{"command": "enqueue", "uris": ["{}"]}}"#, FIRST),
+        )
+        .await;
+        send(&mut connection, "").await;
+        send(
+            &mut connection,
+            &format!(r#"{{"command": "enqueue", "uris": ["{}"]}}"#, SECOND),
+        )
+        .await;
+
+        assert_eq!(receive(&mut connection).await, serde_json::json!({ "ok": true }));
+        assert_eq!(receive(&mut connection).await, serde_json::json!({ "ok": true }));
+
+        let queue = client.read().await.get_queue_tracks().await.unwrap();
+        assert_eq!(queue.iter().map(|track| &*track.uri).collect::<Vec<_>>(), vec![FIRST, SECOND]);
+    }
+
+    #[tokio::test]
+    async fn status_has_playback_state() {
+        let (mut connection, _) = connect();
+
+        assert_eq!(
+            request(&mut connection, r#"{"command": "status"}"#).await,
+            serde_json::json!({ "ok": true, "result": null })
+        );
+    }
+
+    #[tokio::test]
+    async fn failed_commands_get_errors() {
+        let (mut connection, _) = connect();
+
+        // Nothing plays in the fixture
+        let response = request(&mut connection, r#"{"command": "seek", "position_ms": 30000}"#).await;
+
+        assert_eq!(response["ok"], false);
+        assert!(response["error"].is_string());
+        assert!(response.get("result").is_none());
+    }
+
+    #[tokio::test]
+    async fn invalid_requests_are_rejected() {
+        let (mut connection, _) = connect();
+
+        for line in &[
+            r#"{"command": "rewind"}"#,
+            r#"{"command": "seek"}"#,
+            r#"{"uri": "spotify:track:1"}"#,
+            "play",
+        ] {
+            let response = request(&mut connection, line).await;
+
+            assert_eq!(response["ok"], false, "{}", line);
+            assert!(
+                response["error"].as_str().unwrap().starts_with("invalid request: "),
+                "{}",
+                line
+            );
+        }
+
+        // Connection is still usable after invalid requests
+        assert_eq!(request(&mut connection, r#"{"command": "status"}"#).await["ok"], true);
+    }
+
+    #[tokio::test]
+    async fn subscribers_get_events() {
+        let (mut connection, _) = connect();
+
+        assert_eq!(
+            request(&mut connection, r#"{"command": "subscribe"}"#).await,
+            serde_json::json!({ "ok": true })
+        );
+
+        let _ = broadcast(AppEvent::PlaybackVolumeChanged(42));
+
+        // Events broadcast by other tests can come as well
+        let expected = serde_json::json!({ "event": "volume_changed", "percent": 42 });
+        while receive(&mut connection).await != expected {}
+    }
+}
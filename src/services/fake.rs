@@ -0,0 +1,1023 @@
+//! # Fake Spotify client
+//!
+//! A stand-in for [`Spotify`](crate::services::Spotify) which implements all
+//! the api traits over an in-memory library loaded from fixture JSON, so that
+//! loaders and services can be exercised with no network access.
+//!
+//! Fixture is a single JSON object with the same shapes Web API returns,
+//! all the fields are optional:
+//!
+//! ```json
+//! {
+//!   "me": { "id": "me", ... },
+//!   "tracks": [{ "uri": "spotify:track:1", ... }],
+//!   "features": [{ "uri": "spotify:track:1", "tempo": 124.0, ... }],
+//!   "saved_tracks": [{ "added_at": "2020-11-01T00:00:00Z", "track": { ... } }],
+//!   "followed_playlists": ["spotify:playlist:1"]
+//! }
+//! ```
+//!
+//! Changes made through the api (saving tracks, editing playlists, playback
+//! control) are applied to the fixture and can be checked with
+//! [`FakeSpotify::fixture`].
+//!
+//! It's only built for tests, or with `fake` feature for other crates.
+
+use crate::{
+    models::{playlist::PlaylistDetails, set::DjSet, track::tracks_for_uris, Empty, HasUri, ToSimple, Wrapper},
+    services::{api::*, queue::PlaybackQueue, store::StorageError},
+};
+use async_trait::async_trait;
+use reqwest::StatusCode;
+use rspotify::{
+    client::{ClientError, ClientResult},
+    model::*,
+};
+use serde_derive::Deserialize;
+use serde_json::{Map, Value};
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::Path,
+    sync::{Mutex, MutexGuard},
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum FixtureError {
+    #[error("failed to read fixture: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("invalid fixture: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+/// Library served by [`FakeSpotify`], items are referred to by uris
+#[derive(Default, Deserialize)]
+#[serde(default)]
+pub struct Fixture {
+    pub me: Option<PrivateUser>,
+    pub users: Vec<PublicUser>,
+
+    pub tracks: Vec<FullTrack>,
+    pub features: Vec<AudioFeatures>,
+    pub analysis: HashMap<String, AudioAnalysis>,
+    pub albums: Vec<FullAlbum>,
+    pub artists: Vec<FullArtist>,
+    pub related_artists: HashMap<String, Vec<String>>,
+    pub playlists: Vec<FullPlaylist>,
+    pub shows: Vec<FullShow>,
+    pub episodes: Vec<FullEpisode>,
+    pub categories: Vec<Category>,
+    pub category_playlists: HashMap<String, Vec<String>>,
+    pub featured_playlists: Vec<String>,
+    pub new_releases: Vec<String>,
+
+    pub saved_tracks: Vec<SavedTrack>,
+    pub saved_albums: Vec<SavedAlbum>,
+    pub saved_shows: Vec<Show>,
+    pub followed_artists: Vec<String>,
+    pub followed_playlists: Vec<String>,
+    pub followed_users: Vec<String>,
+    pub top_tracks: Vec<String>,
+    pub top_artists: Vec<String>,
+    pub recent: Vec<PlayHistory>,
+
+    pub devices: Vec<Device>,
+    pub playback: Option<CurrentPlaybackContext>,
+}
+
+pub struct FakeSpotify {
+    fixture: Mutex<Fixture>,
+    queue: PlaybackQueue,
+    sets: Mutex<BTreeMap<String, DjSet>>,
+}
+
+impl FakeSpotify {
+    pub fn new(fixture: Fixture) -> FakeSpotify {
+        FakeSpotify {
+            fixture: Mutex::new(fixture),
+            queue: PlaybackQueue::default(),
+            sets: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn from_json(json: &str) -> Result<FakeSpotify, FixtureError> { Ok(FakeSpotify::new(serde_json::from_str(json)?)) }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<FakeSpotify, FixtureError> {
+        FakeSpotify::from_json(&std::fs::read_to_string(path)?)
+    }
+
+    /// Library of `tests/fixtures/library.json`
+    #[cfg(test)]
+    pub fn library() -> FakeSpotify {
+        FakeSpotify::from_json(include_str!("../../tests/fixtures/library.json")).expect("invalid library fixture")
+    }
+
+    /// Current state of the served library
+    pub fn fixture(&self) -> MutexGuard<Fixture> { self.fixture.lock().unwrap_or_else(|error| error.into_inner()) }
+
+    fn me(&self) -> ClientResult<PrivateUser> { self.fixture().me.clone().ok_or_else(not_found) }
+}
+
+/// Same error Web API responds with to unknown ids
+fn not_found() -> ClientError { ClientError::StatusCode(StatusCode::NOT_FOUND) }
+
+/// Checks if an item with the uri is referred to by `uri_or_id`
+fn is_item(uri_or_id: &str, uri: &str) -> bool { uri == uri_or_id || uri.rsplit(':').next() == Some(uri_or_id) }
+
+fn find<'a, T: HasUri>(items: &'a [T], uri: &str) -> ClientResult<&'a T> {
+    items.iter().find(|item| is_item(uri, item.uri())).ok_or_else(not_found)
+}
+
+fn find_mut<'a, T: HasUri>(items: &'a mut [T], uri: &str) -> ClientResult<&'a mut T> {
+    items.iter_mut().find(|item| is_item(uri, item.uri())).ok_or_else(not_found)
+}
+
+fn find_all<T: HasUri + Clone>(items: &[T], uris: &[String]) -> ClientResult<Vec<T>> {
+    uris.iter().map(|uri| find(items, uri).map(Clone::clone)).collect()
+}
+
+fn contains(uris: &[String], uri: &str) -> bool { uris.iter().any(|item| is_item(uri, item)) }
+
+fn page<T: Clone>(items: &[T], offset: u32, limit: u32) -> Page<T> {
+    let start = (offset as usize).min(items.len());
+    let end = (start + limit as usize).min(items.len());
+
+    Page {
+        href: String::new(),
+        items: items[start..end].to_vec(),
+        limit,
+        next: if end < items.len() { Some(String::new()) } else { None },
+        offset,
+        previous: if start > 0 { Some(String::new()) } else { None },
+        total: items.len() as u32,
+    }
+}
+
+fn follow(followed: &mut Vec<String>, uris: &[String]) {
+    for uri in uris {
+        if !contains(followed, uri) {
+            followed.push(uri.clone());
+        }
+    }
+}
+
+fn unfollow(followed: &mut Vec<String>, uris: &[String]) { followed.retain(|uri| !uris.iter().any(|item| is_item(item, uri))); }
+
+fn by_artist(artists: &[SimplifiedArtist], uri: &str) -> bool { artists.iter().any(|artist| is_item(uri, &artist.uri)) }
+
+/// Every query term (with field filters stripped) must be found in some of the
+/// texts
+fn search_match<'a, I: IntoIterator<Item = &'a str>>(query: &str, texts: I) -> bool {
+    let haystack = texts.into_iter().collect::<Vec<_>>().join(" ").to_lowercase();
+
+    query
+        .split_whitespace()
+        .map(|term| term.rsplit(':').next().unwrap_or(term).trim_matches('"').to_lowercase())
+        .all(|term| haystack.contains(&term))
+}
+
+fn next_snapshot(playlist: &mut FullPlaylist) -> String {
+    let version = playlist.snapshot_id.parse::<u64>().unwrap_or(0) + 1;
+    playlist.snapshot_id = version.to_string();
+    playlist.snapshot_id.clone()
+}
+
+#[async_trait]
+impl TracksStorageApi for FakeSpotify {
+    async fn get_track(&self, uri: &str) -> ClientResult<FullTrack> { find(&self.fixture().tracks, uri).map(Clone::clone) }
+
+    async fn get_tracks(&self, uris: &[String]) -> ClientResult<Vec<FullTrack>> { find_all(&self.fixture().tracks, uris) }
+
+    async fn get_track_analysis(&self, uri: &str) -> ClientResult<AudioAnalysis> {
+        let fixture = self.fixture();
+        let track = find(&fixture.tracks, uri)?;
+        fixture.analysis.get(&track.uri).cloned().ok_or_else(not_found)
+    }
+
+    async fn get_tracks_features(&self, uris: &[String]) -> ClientResult<Vec<AudioFeatures>> {
+        let fixture = self.fixture();
+
+        uris.iter()
+            .map(|uri| {
+                fixture
+                    .features
+                    .iter()
+                    .find(|feats| is_item(uri, &feats.uri))
+                    .cloned()
+                    .ok_or_else(not_found)
+            })
+            .collect()
+    }
+
+    async fn get_my_tracks(&self, offset: u32, limit: u32) -> ClientResult<Page<SavedTrack>> {
+        Ok(page(&self.fixture().saved_tracks, offset, limit))
+    }
+
+    async fn get_my_top_tracks(&self, offset: u32, limit: u32) -> ClientResult<Page<FullTrack>> {
+        let fixture = self.fixture();
+        Ok(page(&find_all(&fixture.tracks, &fixture.top_tracks)?, offset, limit))
+    }
+
+    async fn get_recent_tracks(&self, limit: u32) -> ClientResult<Vec<PlayHistory>> {
+        Ok(self.fixture().recent.iter().take(limit as usize).cloned().collect())
+    }
+
+    async fn get_playlist_tracks(&self, uri: &str, offset: u32, limit: u32) -> ClientResult<Page<PlaylistItem>> {
+        Ok(page(&find(&self.fixture().playlists, uri)?.tracks.items, offset, limit))
+    }
+
+    async fn get_album_tracks(&self, uri: &str, offset: u32, limit: u32) -> ClientResult<Page<SimplifiedTrack>> {
+        Ok(page(&find(&self.fixture().albums, uri)?.tracks.items, offset, limit))
+    }
+
+    async fn get_artist_top_tracks(&self, uri: &str) -> ClientResult<Vec<FullTrack>> {
+        Ok(self
+            .fixture()
+            .tracks
+            .iter()
+            .filter(|track| by_artist(&track.artists, uri))
+            .take(10)
+            .cloned()
+            .collect())
+    }
+
+    async fn add_my_tracks(&self, uris: &[String]) -> ClientResult<()> {
+        let mut fixture = self.fixture();
+        let tracks = find_all(&fixture.tracks, uris)?;
+
+        for track in tracks {
+            if !fixture.saved_tracks.iter().any(|saved| saved.track.uri == track.uri) {
+                fixture.saved_tracks.insert(0, SavedTrack::wrap(track));
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn remove_my_tracks(&self, uris: &[String]) -> ClientResult<()> {
+        self.fixture().saved_tracks.retain(|saved| !contains(uris, &saved.track.uri));
+        Ok(())
+    }
+
+    async fn are_my_tracks(&self, uris: &[String]) -> ClientResult<Vec<bool>> {
+        let fixture = self.fixture();
+
+        Ok(uris
+            .iter()
+            .map(|uri| fixture.saved_tracks.iter().any(|saved| is_item(uri, &saved.track.uri)))
+            .collect())
+    }
+}
+
+#[async_trait]
+impl AlbumsStorageApi for FakeSpotify {
+    async fn get_album(&self, uri: &str) -> ClientResult<FullAlbum> { find(&self.fixture().albums, uri).map(Clone::clone) }
+
+    async fn get_albums(&self, uris: &[String]) -> ClientResult<Vec<FullAlbum>> { find_all(&self.fixture().albums, uris) }
+
+    async fn get_my_albums(&self, offset: u32, limit: u32) -> ClientResult<Page<SavedAlbum>> {
+        Ok(page(&self.fixture().saved_albums, offset, limit))
+    }
+
+    async fn get_artist_albums(&self, uri: &str, offset: u32, limit: u32) -> ClientResult<Page<SimplifiedAlbum>> {
+        let albums = self
+            .fixture()
+            .albums
+            .iter()
+            .filter(|album| by_artist(&album.artists, uri))
+            .map(ToSimple::to_simple)
+            .collect::<Vec<_>>();
+
+        Ok(page(&albums, offset, limit))
+    }
+
+    async fn get_new_releases(&self, offset: u32, limit: u32) -> ClientResult<Page<SimplifiedAlbum>> {
+        let fixture = self.fixture();
+        let albums = find_all(&fixture.albums, &fixture.new_releases)?
+            .into_iter()
+            .map(ToSimple::into_simple)
+            .collect::<Vec<_>>();
+
+        Ok(page(&albums, offset, limit))
+    }
+
+    async fn add_my_albums(&self, uris: &[String]) -> ClientResult<()> {
+        let mut fixture = self.fixture();
+        let albums = find_all(&fixture.albums, uris)?;
+
+        for album in albums {
+            if !fixture.saved_albums.iter().any(|saved| saved.album.uri == album.uri) {
+                fixture.saved_albums.insert(0, SavedAlbum::wrap(album));
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn remove_my_albums(&self, uris: &[String]) -> ClientResult<()> {
+        self.fixture().saved_albums.retain(|saved| !contains(uris, &saved.album.uri));
+        Ok(())
+    }
+
+    async fn are_my_albums(&self, uris: &[String]) -> ClientResult<Vec<bool>> {
+        let fixture = self.fixture();
+
+        Ok(uris
+            .iter()
+            .map(|uri| fixture.saved_albums.iter().any(|saved| is_item(uri, &saved.album.uri)))
+            .collect())
+    }
+}
+
+#[async_trait]
+impl ArtistsStorageApi for FakeSpotify {
+    async fn get_artist(&self, uri: &str) -> ClientResult<FullArtist> { find(&self.fixture().artists, uri).map(Clone::clone) }
+
+    async fn get_artists(&self, uris: &[String]) -> ClientResult<Vec<FullArtist>> { find_all(&self.fixture().artists, uris) }
+
+    async fn get_my_artists(&self, cursor: Option<String>, limit: u32) -> ClientResult<CursorBasedPage<FullArtist>> {
+        let fixture = self.fixture();
+        let artists = find_all(&fixture.artists, &fixture.followed_artists)?;
+
+        // Cursor is the id of the last artist on the previous page
+        let start = cursor
+            .and_then(|cursor| artists.iter().position(|artist| artist.id == cursor))
+            .map_or(0, |index| index + 1);
+        let items = artists.iter().skip(start).take(limit as usize).cloned().collect::<Vec<_>>();
+        let after = if start + items.len() < artists.len() {
+            items.last().map(|artist| artist.id.clone())
+        } else {
+            None
+        };
+
+        Ok(CursorBasedPage {
+            href: String::new(),
+            limit,
+            next: after.as_ref().map(|_| String::new()),
+            cursors: Cursor { after },
+            total: Some(artists.len() as u32),
+            items,
+        })
+    }
+
+    async fn get_my_top_artists(&self, offset: u32, limit: u32) -> ClientResult<Page<FullArtist>> {
+        let fixture = self.fixture();
+        Ok(page(&find_all(&fixture.artists, &fixture.top_artists)?, offset, limit))
+    }
+
+    async fn get_artist_related_artists(&self, uri: &str) -> ClientResult<Vec<FullArtist>> {
+        let fixture = self.fixture();
+        let artist = find(&fixture.artists, uri)?;
+
+        match fixture.related_artists.get(&artist.uri) {
+            Some(related) => find_all(&fixture.artists, related),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    async fn add_my_artists(&self, uris: &[String]) -> ClientResult<()> {
+        follow(&mut self.fixture().followed_artists, uris);
+        Ok(())
+    }
+
+    async fn remove_my_artists(&self, uris: &[String]) -> ClientResult<()> {
+        unfollow(&mut self.fixture().followed_artists, uris);
+        Ok(())
+    }
+
+    async fn are_my_artists(&self, uris: &[String]) -> ClientResult<Vec<bool>> {
+        let fixture = self.fixture();
+        Ok(uris.iter().map(|uri| contains(&fixture.followed_artists, uri)).collect())
+    }
+}
+
+#[async_trait]
+impl PlaylistsStorageApi for FakeSpotify {
+    async fn get_playlist(&self, uri: &str) -> ClientResult<FullPlaylist> {
+        find(&self.fixture().playlists, uri).map(Clone::clone)
+    }
+
+    async fn get_playlists(&self, uris: &[String]) -> ClientResult<Vec<FullPlaylist>> {
+        find_all(&self.fixture().playlists, uris)
+    }
+
+    async fn get_my_playlists(&self, offset: u32, limit: u32) -> ClientResult<Page<SimplifiedPlaylist>> {
+        let fixture = self.fixture();
+        let playlists = find_all(&fixture.playlists, &fixture.followed_playlists)?
+            .into_iter()
+            .map(ToSimple::into_simple)
+            .collect::<Vec<_>>();
+
+        Ok(page(&playlists, offset, limit))
+    }
+
+    async fn get_user_playlists(&self, user_id: &str, offset: u32, limit: u32) -> ClientResult<Page<SimplifiedPlaylist>> {
+        let playlists = self
+            .fixture()
+            .playlists
+            .iter()
+            .filter(|playlist| is_item(user_id, &playlist.owner.uri))
+            .map(ToSimple::to_simple)
+            .collect::<Vec<_>>();
+
+        Ok(page(&playlists, offset, limit))
+    }
+
+    async fn get_featured_playlists(&self, offset: u32, limit: u32) -> ClientResult<Page<SimplifiedPlaylist>> {
+        let fixture = self.fixture();
+        let playlists = find_all(&fixture.playlists, &fixture.featured_playlists)?
+            .into_iter()
+            .map(ToSimple::into_simple)
+            .collect::<Vec<_>>();
+
+        Ok(page(&playlists, offset, limit))
+    }
+
+    async fn get_categories(&self, offset: u32, limit: u32) -> ClientResult<Page<Category>> {
+        Ok(page(&self.fixture().categories, offset, limit))
+    }
+
+    async fn get_category_playlists(&self, category_id: &str, offset: u32, limit: u32) -> ClientResult<Page<SimplifiedPlaylist>> {
+        let fixture = self.fixture();
+        let uris = fixture.category_playlists.get(category_id).ok_or_else(not_found)?;
+        let playlists = find_all(&fixture.playlists, uris)?
+            .into_iter()
+            .map(ToSimple::into_simple)
+            .collect::<Vec<_>>();
+
+        Ok(page(&playlists, offset, limit))
+    }
+
+    async fn add_my_playlists(&self, uris: &[String], _public: bool) -> ClientResult<()> {
+        follow(&mut self.fixture().followed_playlists, uris);
+        Ok(())
+    }
+
+    async fn remove_my_playlists(&self, uris: &[String]) -> ClientResult<()> {
+        unfollow(&mut self.fixture().followed_playlists, uris);
+        Ok(())
+    }
+
+    async fn are_my_playlists(&self, uris: &[String]) -> ClientResult<Vec<bool>> {
+        let fixture = self.fixture();
+        Ok(uris.iter().map(|uri| contains(&fixture.followed_playlists, uri)).collect())
+    }
+
+    async fn create_my_playlist(&self, details: &PlaylistDetails) -> ClientResult<FullPlaylist> {
+        let owner = self.me()?.into_simple();
+        let mut fixture = self.fixture();
+        let id = format!("fake{}", fixture.playlists.len() + 1);
+
+        let playlist = FullPlaylist {
+            collaborative: details.collaborative,
+            description: details.description.clone(),
+            external_urls: HashMap::new(),
+            followers: Followers { total: 0 },
+            href: String::new(),
+            images: Vec::new(),
+            name: details.name.clone(),
+            owner,
+            public: Some(details.public && !details.collaborative),
+            snapshot_id: "1".to_owned(),
+            tracks: Page::empty(),
+            _type: Type::Playlist,
+            uri: format!("spotify:playlist:{}", id),
+            id,
+        };
+
+        fixture.playlists.push(playlist.clone());
+        fixture.followed_playlists.insert(0, playlist.uri.clone());
+
+        Ok(playlist)
+    }
+
+    async fn update_playlist_details(&self, uri: &str, details: &PlaylistDetails) -> ClientResult<()> {
+        let mut fixture = self.fixture();
+        let playlist = find_mut(&mut fixture.playlists, uri)?;
+
+        playlist.name = details.name.clone();
+        playlist.description = details.description.clone();
+        playlist.public = Some(details.public && !details.collaborative);
+        playlist.collaborative = details.collaborative;
+
+        Ok(())
+    }
+
+    async fn add_playlist_tracks(&self, uri: &str, uris: &[String], position: Option<u32>) -> ClientResult<String> {
+        let mut fixture = self.fixture();
+        let tracks = find_all(&fixture.tracks, uris)?;
+        let playlist = find_mut(&mut fixture.playlists, uri)?;
+        let items = &mut playlist.tracks.items;
+        let position = position.map_or(items.len(), |position| (position as usize).min(items.len()));
+
+        items.splice(position..position, tracks.into_iter().map(PlaylistItem::wrap));
+        playlist.tracks.total = items.len() as u32;
+
+        Ok(next_snapshot(playlist))
+    }
+
+    async fn remove_playlist_tracks(
+        &self,
+        uri: &str,
+        tracks: &[(String, u32)],
+        _snapshot_id: Option<&str>,
+    ) -> ClientResult<String> {
+        let mut fixture = self.fixture();
+        let playlist = find_mut(&mut fixture.playlists, uri)?;
+        let mut position = 0;
+
+        playlist.tracks.items.retain(|item| {
+            let removed = tracks
+                .iter()
+                .any(|(uri, removed)| *removed == position && is_item(uri, item.uri()));
+            position += 1;
+            !removed
+        });
+        playlist.tracks.total = playlist.tracks.items.len() as u32;
+
+        Ok(next_snapshot(playlist))
+    }
+
+    async fn reorder_playlist_tracks(
+        &self,
+        uri: &str,
+        range_start: u32,
+        range_length: u32,
+        insert_before: u32,
+        _snapshot_id: Option<&str>,
+    ) -> ClientResult<String> {
+        let mut fixture = self.fixture();
+        let playlist = find_mut(&mut fixture.playlists, uri)?;
+        let items = &mut playlist.tracks.items;
+        let (start, end, before) = (
+            range_start as usize,
+            (range_start + range_length) as usize,
+            insert_before as usize,
+        );
+
+        if end > items.len() || before > items.len() {
+            return Err(ClientError::StatusCode(StatusCode::BAD_REQUEST));
+        }
+
+        let range = items.drain(start..end).collect::<Vec<_>>();
+        let before = if before > start {
+            before.saturating_sub(range.len()).max(start)
+        } else {
+            before
+        };
+        items.splice(before..before, range);
+
+        Ok(next_snapshot(playlist))
+    }
+
+    async fn replace_playlist_tracks(&self, uri: &str, uris: &[String]) -> ClientResult<()> {
+        let mut fixture = self.fixture();
+        let tracks = find_all(&fixture.tracks, uris)?;
+        let playlist = find_mut(&mut fixture.playlists, uri)?;
+
+        playlist.tracks.items = tracks.into_iter().map(PlaylistItem::wrap).collect();
+        playlist.tracks.total = playlist.tracks.items.len() as u32;
+        next_snapshot(playlist);
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl UsersStorageApi for FakeSpotify {
+    async fn get_my_profile(&self) -> ClientResult<PrivateUser> { self.me() }
+
+    async fn get_user_profile(&self, uri: &str) -> ClientResult<PublicUser> {
+        self.fixture()
+            .users
+            .iter()
+            .find(|user| is_item(uri, &user.uri))
+            .cloned()
+            .ok_or_else(not_found)
+    }
+
+    async fn add_my_users(&self, uris: &[String]) -> ClientResult<()> {
+        follow(&mut self.fixture().followed_users, uris);
+        Ok(())
+    }
+
+    async fn remove_my_users(&self, uris: &[String]) -> ClientResult<()> {
+        unfollow(&mut self.fixture().followed_users, uris);
+        Ok(())
+    }
+
+    async fn are_my_users(&self, uris: &[String]) -> ClientResult<Vec<bool>> {
+        let fixture = self.fixture();
+        Ok(uris.iter().map(|uri| contains(&fixture.followed_users, uri)).collect())
+    }
+}
+
+#[async_trait]
+impl ShowsStorageApi for FakeSpotify {
+    async fn get_show(&self, uri: &str) -> ClientResult<FullShow> { find(&self.fixture().shows, uri).map(Clone::clone) }
+
+    async fn get_shows(&self, uris: &[String]) -> ClientResult<Vec<SimplifiedShow>> {
+        Ok(find_all(&self.fixture().shows, uris)?
+            .into_iter()
+            .map(ToSimple::into_simple)
+            .collect())
+    }
+
+    async fn get_my_shows(&self, offset: u32, limit: u32) -> ClientResult<Page<Show>> {
+        Ok(page(&self.fixture().saved_shows, offset, limit))
+    }
+
+    async fn add_my_shows(&self, uris: &[String]) -> ClientResult<()> {
+        let mut fixture = self.fixture();
+        let shows = find_all(&fixture.shows, uris)?;
+
+        for show in shows {
+            if !fixture.saved_shows.iter().any(|saved| saved.show.uri == show.uri) {
+                fixture.saved_shows.insert(0, Show::wrap(show.into_simple()));
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn remove_my_shows(&self, uris: &[String]) -> ClientResult<()> {
+        self.fixture().saved_shows.retain(|saved| !contains(uris, &saved.show.uri));
+        Ok(())
+    }
+
+    async fn are_my_shows(&self, uris: &[String]) -> ClientResult<Vec<bool>> {
+        let fixture = self.fixture();
+
+        Ok(uris
+            .iter()
+            .map(|uri| fixture.saved_shows.iter().any(|saved| is_item(uri, &saved.show.uri)))
+            .collect())
+    }
+}
+
+#[async_trait]
+impl EpisodesStorageApi for FakeSpotify {
+    async fn get_episode(&self, uri: &str) -> ClientResult<FullEpisode> { find(&self.fixture().episodes, uri).map(Clone::clone) }
+
+    async fn get_episodes(&self, uris: &[String]) -> ClientResult<Vec<FullEpisode>> { find_all(&self.fixture().episodes, uris) }
+
+    async fn get_show_episodes(&self, uri: &str, offset: u32, limit: u32) -> ClientResult<Page<SimplifiedEpisode>> {
+        Ok(page(&find(&self.fixture().shows, uri)?.episodes.items, offset, limit))
+    }
+}
+
+impl FakeSpotify {
+    /// Applies a change to playback state, like the real API fails if nothing
+    /// plays
+    fn update_playback<F: FnOnce(&mut Fixture) -> ClientResult<()>>(&self, update: F) -> ClientResult<()> {
+        let mut fixture = self.fixture();
+
+        if fixture.playback.is_none() {
+            return Err(not_found());
+        }
+
+        update(&mut fixture)
+    }
+
+    fn play_item(&self, item: PlayingItem) -> ClientResult<()> {
+        self.update_playback(|fixture| {
+            if let Some(ref mut playback) = fixture.playback {
+                playback.item = Some(item);
+                playback.progress_ms = Some(0);
+                playback.is_playing = true;
+            }
+            Ok(())
+        })
+    }
+}
+
+#[async_trait]
+impl PlaybackControlApi for FakeSpotify {
+    async fn get_playback_state(&self) -> ClientResult<Option<CurrentPlaybackContext>> { Ok(self.fixture().playback.clone()) }
+
+    async fn play_context(&self, uri: String, start_uri: Option<String>) -> ClientResult<()> {
+        let track_uri = match start_uri {
+            Some(start_uri) => start_uri,
+            None => {
+                let fixture = self.fixture();
+
+                let first_uri = if let Ok(playlist) = find(&fixture.playlists, &uri) {
+                    playlist.tracks.items.first().map(|item| item.uri().to_owned())
+                } else if let Ok(album) = find(&fixture.albums, &uri) {
+                    album.tracks.items.first().map(|track| track.uri.clone())
+                } else {
+                    fixture
+                        .tracks
+                        .iter()
+                        .find(|track| by_artist(&track.artists, &uri))
+                        .map(|track| track.uri.clone())
+                };
+
+                first_uri.ok_or_else(not_found)?
+            }
+        };
+
+        let track = self.get_track(&track_uri).await?;
+        self.play_item(PlayingItem::Track(track))
+    }
+
+    async fn play_tracks(&self, uris: &[String]) -> ClientResult<()> {
+        match uris.first() {
+            Some(uri) => {
+                let track = self.get_track(uri).await?;
+                self.play_item(PlayingItem::Track(track))
+            }
+            None => Ok(()),
+        }
+    }
+
+    async fn start_playback(&self) -> ClientResult<()> {
+        self.update_playback(|fixture| {
+            fixture.playback.iter_mut().for_each(|playback| playback.is_playing = true);
+            Ok(())
+        })
+    }
+
+    async fn pause_playback(&self) -> ClientResult<()> {
+        self.update_playback(|fixture| {
+            fixture.playback.iter_mut().for_each(|playback| playback.is_playing = false);
+            Ok(())
+        })
+    }
+
+    async fn play_next_track(&self) -> ClientResult<()> { self.step_track(1) }
+
+    async fn play_prev_track(&self) -> ClientResult<()> { self.step_track(-1) }
+
+    async fn seek_track(&self, pos: u32) -> ClientResult<()> {
+        self.update_playback(|fixture| {
+            fixture
+                .playback
+                .iter_mut()
+                .for_each(|playback| playback.progress_ms = Some(pos));
+            Ok(())
+        })
+    }
+
+    async fn set_volume(&self, value: u8) -> ClientResult<()> {
+        self.update_playback(|fixture| {
+            fixture
+                .playback
+                .iter_mut()
+                .for_each(|playback| playback.device.volume_percent = Some(value as u32));
+            Ok(())
+        })
+    }
+
+    async fn set_shuffle(&self, value: bool) -> ClientResult<()> {
+        self.update_playback(|fixture| {
+            fixture
+                .playback
+                .iter_mut()
+                .for_each(|playback| playback.shuffle_state = value);
+            Ok(())
+        })
+    }
+
+    async fn set_repeat_mode(&self, mode: RepeatState) -> ClientResult<()> {
+        self.update_playback(|fixture| {
+            fixture.playback.iter_mut().for_each(|playback| playback.repeat_state = mode);
+            Ok(())
+        })
+    }
+
+    async fn get_my_devices(&self) -> ClientResult<Vec<Device>> { Ok(self.fixture().devices.clone()) }
+
+    async fn use_device(&self, id: &str, _play: bool) -> ClientResult<()> {
+        let mut fixture = self.fixture();
+        let device = fixture
+            .devices
+            .iter()
+            .find(|device| device.id.as_deref() == Some(id))
+            .cloned()
+            .ok_or_else(not_found)?;
+
+        for device in &mut fixture.devices {
+            device.is_active = device.id.as_deref() == Some(id);
+        }
+
+        if let Some(ref mut playback) = fixture.playback {
+            playback.device = device;
+        }
+
+        Ok(())
+    }
+}
+
+impl FakeSpotify {
+    /// Moves playback to the next or previous track of the fixture tracks
+    fn step_track(&self, step: isize) -> ClientResult<()> {
+        self.update_playback(|fixture| {
+            let current = match fixture.playback.as_ref().and_then(|playback| playback.item.as_ref()) {
+                Some(item) => fixture.tracks.iter().position(|track| track.uri == item.uri()),
+                None => None,
+            };
+
+            let next = current
+                .map(|index| index as isize + step)
+                .filter(|&index| index >= 0)
+                .and_then(|index| fixture.tracks.get(index as usize))
+                .cloned()
+                .ok_or_else(not_found)?;
+
+            if let Some(ref mut playback) = fixture.playback {
+                playback.item = Some(PlayingItem::Track(next));
+                playback.progress_ms = Some(0);
+            }
+
+            Ok(())
+        })
+    }
+}
+
+#[async_trait]
+impl PlaybackQueueApi for FakeSpotify {
+    async fn dequeue_tracks(&mut self, positions: &[usize]) -> ClientResult<()> {
+        self.queue.remove(positions).expect("in-memory queue never fails");
+        Ok(())
+    }
+
+    async fn enqueue_tracks(&mut self, uris: &[String]) -> ClientResult<()> {
+        self.queue.push(uris).expect("in-memory queue never fails");
+        Ok(())
+    }
+
+    async fn insert_queue_tracks(&mut self, position: usize, uris: &[String]) -> ClientResult<()> {
+        self.queue.insert(position, uris).expect("in-memory queue never fails");
+        Ok(())
+    }
+
+    async fn move_queue_track(&mut self, from: usize, to: usize) -> ClientResult<()> {
+        self.queue.move_track(from, to).expect("in-memory queue never fails");
+        Ok(())
+    }
+
+    async fn reorder_queue_tracks(&mut self, uris: &[String]) -> ClientResult<()> {
+        self.queue.reorder(uris).expect("in-memory queue never fails");
+        Ok(())
+    }
+
+    async fn get_queue_tracks(&self) -> ClientResult<Vec<FullTrack>> {
+        let uris = self.queue.uris().iter().cloned().collect::<Vec<_>>();
+        let fixture = self.fixture();
+        // Unknown tracks are skipped like Spotify does
+        let tracks = uris
+            .iter()
+            .filter_map(|uri| find(&fixture.tracks, uri).ok().cloned())
+            .collect();
+
+        Ok(tracks_for_uris(&uris, tracks))
+    }
+
+    async fn feed_queue_track(&mut self, playing_uri: &str) -> ClientResult<Option<String>> {
+        Ok(self.queue.pop_during(playing_uri).expect("in-memory queue never fails"))
+    }
+}
+
+impl LibraryMirrorApi for FakeSpotify {
+    fn mirrored_my_tracks(&self, _offset: u32) -> Option<Page<SavedTrack>> { None }
+
+    fn mirrored_my_albums(&self, _offset: u32) -> Option<Page<SavedAlbum>> { None }
+
+    fn mirrored_my_artists(&self) -> Option<CursorBasedPage<FullArtist>> { None }
+
+    fn mirrored_my_playlists(&self, _offset: u32) -> Option<Page<SimplifiedPlaylist>> { None }
+
+    fn mirrored_playlist_tracks(&self, _uri: &str, _offset: u32) -> Option<Page<PlaylistItem>> { None }
+}
+
+impl SetsStorageApi for FakeSpotify {
+    fn get_set_names(&self) -> Result<Vec<String>, StorageError> {
+        Ok(self
+            .sets
+            .lock()
+            .unwrap_or_else(|error| error.into_inner())
+            .keys()
+            .cloned()
+            .collect())
+    }
+
+    fn get_set(&self, name: &str) -> Result<Option<DjSet>, StorageError> {
+        Ok(self.sets.lock().unwrap_or_else(|error| error.into_inner()).get(name).cloned())
+    }
+
+    fn save_set(&self, set: DjSet) -> Result<(), StorageError> {
+        self.sets
+            .lock()
+            .unwrap_or_else(|error| error.into_inner())
+            .insert(set.name.clone(), set);
+        Ok(())
+    }
+
+    fn delete_set(&self, name: &str) -> Result<(), StorageError> {
+        self.sets.lock().unwrap_or_else(|error| error.into_inner()).remove(name);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl SearchApi for FakeSpotify {
+    async fn get_recommended_tracks(
+        &self,
+        _seed_genres: Option<Vec<String>>,
+        _seed_artists: Option<Vec<String>>,
+        seed_tracks: Option<Vec<String>>,
+        _tunables: Map<String, Value>,
+        limit: u32,
+    ) -> ClientResult<Vec<SimplifiedTrack>> {
+        let seed_tracks = seed_tracks.unwrap_or_default();
+
+        Ok(self
+            .fixture()
+            .tracks
+            .iter()
+            .filter(|track| !contains(&seed_tracks, &track.uri))
+            .take(limit as usize)
+            .map(ToSimple::to_simple)
+            .collect())
+    }
+
+    async fn search(&self, query: &str, kind: SearchType, offset: u32, limit: u32) -> ClientResult<SearchResult> {
+        let fixture = self.fixture();
+
+        Ok(match kind {
+            SearchType::Track => SearchResult::Tracks(page(
+                &fixture
+                    .tracks
+                    .iter()
+                    .filter(|track| {
+                        search_match(
+                            query,
+                            std::iter::once(&*track.name).chain(track.artists.iter().map(|artist| &*artist.name)),
+                        )
+                    })
+                    .cloned()
+                    .collect::<Vec<_>>(),
+                offset,
+                limit,
+            )),
+            SearchType::Album => SearchResult::Albums(page(
+                &fixture
+                    .albums
+                    .iter()
+                    .filter(|album| {
+                        search_match(
+                            query,
+                            std::iter::once(&*album.name).chain(album.artists.iter().map(|artist| &*artist.name)),
+                        )
+                    })
+                    .map(ToSimple::to_simple)
+                    .collect::<Vec<_>>(),
+                offset,
+                limit,
+            )),
+            SearchType::Artist => SearchResult::Artists(page(
+                &fixture
+                    .artists
+                    .iter()
+                    .filter(|artist| search_match(query, vec![&*artist.name]))
+                    .cloned()
+                    .collect::<Vec<_>>(),
+                offset,
+                limit,
+            )),
+            SearchType::Playlist => SearchResult::Playlists(page(
+                &fixture
+                    .playlists
+                    .iter()
+                    .filter(|playlist| search_match(query, vec![&*playlist.name, &*playlist.description]))
+                    .map(ToSimple::to_simple)
+                    .collect::<Vec<_>>(),
+                offset,
+                limit,
+            )),
+            SearchType::Show => SearchResult::Shows(page(
+                &fixture
+                    .shows
+                    .iter()
+                    .filter(|show| search_match(query, vec![&*show.name, &*show.publisher]))
+                    .map(ToSimple::to_simple)
+                    .collect::<Vec<_>>(),
+                offset,
+                limit,
+            )),
+            SearchType::Episode => SearchResult::Episodes(page(
+                &fixture
+                    .episodes
+                    .iter()
+                    .filter(|episode| search_match(query, vec![&*episode.name]))
+                    .map(ToSimple::to_simple)
+                    .collect::<Vec<_>>(),
+                offset,
+                limit,
+            )),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests;
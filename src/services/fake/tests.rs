@@ -0,0 +1,177 @@
+use super::*;
+
+const FIRST: &str = "spotify:track:0track00000000000000001";
+const SECOND: &str = "spotify:track:0track00000000000000002";
+const THIRD: &str = "spotify:track:0track00000000000000003";
+const PLAYLIST: &str = "spotify:playlist:0playlist00000000000001";
+
+fn uris(items: &[&str]) -> Vec<String> { items.iter().map(|&uri| uri.to_owned()).collect() }
+
+fn playlist_uris(spotify: &FakeSpotify) -> Vec<String> {
+    spotify.fixture().playlists[0]
+        .tracks
+        .items
+        .iter()
+        .map(|item| item.uri().to_owned())
+        .collect()
+}
+
+#[test]
+fn library_fixture_is_loaded() {
+    let spotify = FakeSpotify::library();
+    let fixture = spotify.fixture();
+
+    assert_eq!(fixture.tracks.len(), 3);
+    assert_eq!(fixture.features.len(), 3);
+    assert_eq!(fixture.albums[0].tracks.items.len(), 3);
+    assert_eq!(fixture.playlists[0].tracks.items.len(), 4);
+    assert_eq!(fixture.me.as_ref().map(|me| &*me.id), Some("fixture"));
+}
+
+#[tokio::test]
+async fn items_are_found_by_uri_or_id() {
+    let spotify = FakeSpotify::library();
+
+    assert_eq!(spotify.get_track(FIRST).await.unwrap().uri, FIRST);
+    assert_eq!(spotify.get_track("0track00000000000000002").await.unwrap().uri, SECOND);
+    assert!(spotify.get_track("spotify:track:unknown").await.is_err());
+}
+
+#[tokio::test]
+async fn features_are_returned_in_asked_order() {
+    let spotify = FakeSpotify::library();
+    let features = spotify.get_tracks_features(&uris(&[THIRD, FIRST])).await.unwrap();
+
+    assert_eq!(features.iter().map(|feats| &*feats.uri).collect::<Vec<_>>(), vec![
+        THIRD, FIRST
+    ]);
+}
+
+#[tokio::test]
+async fn playlist_tracks_are_removed_by_position() {
+    let spotify = FakeSpotify::library();
+
+    let snapshot_id = spotify
+        .remove_playlist_tracks(PLAYLIST, &[(FIRST.to_owned(), 2)], Some("1"))
+        .await
+        .unwrap();
+
+    assert_eq!(snapshot_id, "2");
+    assert_eq!(playlist_uris(&spotify), uris(&[FIRST, SECOND, THIRD]));
+}
+
+#[tokio::test]
+async fn playlist_track_is_kept_if_position_has_another_track() {
+    let spotify = FakeSpotify::library();
+
+    spotify
+        .remove_playlist_tracks(PLAYLIST, &[(SECOND.to_owned(), 0)], None)
+        .await
+        .unwrap();
+
+    assert_eq!(playlist_uris(&spotify), uris(&[FIRST, SECOND, FIRST, THIRD]));
+}
+
+#[tokio::test]
+async fn playlist_tracks_are_added_and_reordered() {
+    let spotify = FakeSpotify::library();
+
+    spotify.add_playlist_tracks(PLAYLIST, &uris(&[THIRD]), Some(0)).await.unwrap();
+    assert_eq!(playlist_uris(&spotify), uris(&[THIRD, FIRST, SECOND, FIRST, THIRD]));
+
+    // Moves the first two tracks to the end
+    spotify.reorder_playlist_tracks(PLAYLIST, 0, 2, 5, None).await.unwrap();
+    assert_eq!(playlist_uris(&spotify), uris(&[SECOND, FIRST, THIRD, THIRD, FIRST]));
+}
+
+#[tokio::test]
+async fn followed_playlists_are_checked() {
+    let spotify = FakeSpotify::library();
+
+    assert_eq!(
+        spotify
+            .are_my_playlists(&uris(&[PLAYLIST, "spotify:playlist:unknown"]))
+            .await
+            .unwrap(),
+        vec![true, false]
+    );
+
+    spotify.remove_my_playlists(&uris(&[PLAYLIST])).await.unwrap();
+    assert_eq!(spotify.are_my_playlists(&uris(&[PLAYLIST])).await.unwrap(), vec![false]);
+}
+
+#[tokio::test]
+async fn queue_is_edited_by_position() {
+    let mut spotify = FakeSpotify::library();
+
+    spotify.enqueue_tracks(&uris(&[FIRST, SECOND, FIRST])).await.unwrap();
+    spotify.insert_queue_tracks(0, &uris(&[THIRD])).await.unwrap();
+    assert_eq!(spotify.queue.uris(), &uris(&[THIRD, FIRST, SECOND, FIRST]));
+
+    // Only the chosen one of repeated tracks is removed
+    spotify.dequeue_tracks(&[3]).await.unwrap();
+    assert_eq!(spotify.queue.uris(), &uris(&[THIRD, FIRST, SECOND]));
+
+    spotify.move_queue_track(2, 0).await.unwrap();
+    assert_eq!(spotify.queue.uris(), &uris(&[SECOND, THIRD, FIRST]));
+
+    let tracks = spotify.get_queue_tracks().await.unwrap();
+    assert_eq!(tracks.iter().map(|track| &*track.uri).collect::<Vec<_>>(), vec![
+        SECOND, THIRD, FIRST
+    ]);
+}
+
+#[tokio::test]
+async fn unknown_queued_tracks_keep_their_rows() {
+    let mut spotify = FakeSpotify::library();
+
+    spotify
+        .enqueue_tracks(&uris(&[FIRST, "spotify:track:unknown", SECOND]))
+        .await
+        .unwrap();
+
+    let tracks = spotify.get_queue_tracks().await.unwrap();
+    assert_eq!(tracks.iter().map(|track| &*track.uri).collect::<Vec<_>>(), vec![
+        FIRST,
+        "spotify:track:unknown",
+        SECOND
+    ]);
+    assert_eq!(tracks[1].is_playable, Some(false));
+
+    // Positions of the rows are the positions in the queue
+    spotify.dequeue_tracks(&[2]).await.unwrap();
+    assert_eq!(spotify.queue.uris(), &uris(&[FIRST, "spotify:track:unknown"]));
+}
+
+#[tokio::test]
+async fn queue_is_fed_from_the_top() {
+    let mut spotify = FakeSpotify::library();
+
+    spotify.enqueue_tracks(&uris(&[SECOND, THIRD])).await.unwrap();
+
+    assert_eq!(spotify.feed_queue_track(FIRST).await.unwrap().as_deref(), Some(SECOND));
+    assert_eq!(spotify.feed_queue_track(SECOND).await.unwrap().as_deref(), Some(THIRD));
+    assert_eq!(spotify.feed_queue_track(THIRD).await.unwrap(), None);
+}
+
+#[tokio::test]
+async fn queue_is_fed_once_per_playing_track() {
+    let mut spotify = FakeSpotify::library();
+
+    spotify.enqueue_tracks(&uris(&[SECOND, THIRD])).await.unwrap();
+
+    assert_eq!(spotify.feed_queue_track(FIRST).await.unwrap().as_deref(), Some(SECOND));
+    // Both queue feeder and crossfade ask for the next track near the end
+    assert_eq!(spotify.feed_queue_track(FIRST).await.unwrap(), None);
+    assert_eq!(spotify.queue.uris(), &uris(&[THIRD]));
+}
+
+#[tokio::test]
+async fn queue_feeding_waits_for_tracks_added_later() {
+    let mut spotify = FakeSpotify::library();
+
+    assert_eq!(spotify.feed_queue_track(FIRST).await.unwrap(), None);
+
+    spotify.enqueue_tracks(&uris(&[SECOND])).await.unwrap();
+    assert_eq!(spotify.feed_queue_track(FIRST).await.unwrap().as_deref(), Some(SECOND));
+}
@@ -1,18 +1,62 @@
-use crate::services::{spotify::Spotify, SpotifyRef};
+use crate::{
+    broadcast,
+    services::{spotify::Spotify, SpotifyRef},
+    AppEvent,
+};
 use futures_util::TryFutureExt;
+use reqwest::Url;
 use std::{
     io::{Error, ErrorKind},
     net::{IpAddr, Ipv4Addr, SocketAddr},
     sync::Arc,
+    time::Duration,
 };
 use tokio::{
-    io::{AsyncReadExt, AsyncWriteExt},
+    io::{AsyncRead, AsyncReadExt, AsyncWriteExt},
     net::{TcpListener, TcpStream},
     runtime::Runtime,
     sync::RwLock,
     task::JoinHandle,
 };
 
+const CALLBACK_PATH: &str = "/callback";
+
+/// Requests with longer headers are rejected
+const MAX_REQUEST_SIZE: usize = 8192;
+
+/// Connections which don't send the whole request head in time are closed
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// OAuth redirect request
+#[derive(Debug, PartialEq)]
+pub enum Callback {
+    /// User granted access
+    Code { code: String, state: String },
+    /// User denied access or authorization failed
+    Error { error: String, state: Option<String> },
+}
+
+impl Callback {
+    pub fn state(&self) -> Option<&str> {
+        match self {
+            Callback::Code { state, .. } => Some(state),
+            Callback::Error { state, .. } => state.as_deref(),
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum CallbackError {
+    #[error("read error: {0}")]
+    Io(#[from] Error),
+    #[error("bad request: {0}")]
+    BadRequest(&'static str),
+    #[error("not found: {0}")]
+    NotFound(String),
+    #[error("request timed out")]
+    TimedOut,
+}
+
 pub struct LoginService {
     client: SpotifyRef,
 }
@@ -27,8 +71,13 @@ impl LoginService {
     }
 
     pub async fn run(self) -> Result<!, Error> {
-        let (mut server, address) = Self::bind_free_socket().await?;
-        let redirect_uri = format!("http://{}/callback", address);
+        let (server, address) = Self::bind_free_socket().await?;
+        self.serve(server, address).await
+    }
+
+    /// Serves OAuth callbacks on the listener bound to the address
+    pub async fn serve(self, mut server: TcpListener, address: SocketAddr) -> Result<!, Error> {
+        let redirect_uri = format!("http://{}{}", address, CALLBACK_PATH);
 
         info!("login server is listening at {}", redirect_uri);
 
@@ -36,7 +85,7 @@ impl LoginService {
 
         loop {
             let (stream, _) = server.accept().await?;
-            let _ = tokio::task::spawn(Self::handle(self.client.clone(), stream)).await;
+            tokio::task::spawn(Self::handle(self.client.clone(), stream));
         }
     }
 
@@ -60,94 +109,479 @@ impl LoginService {
     }
 
     async fn handle(spotify: Arc<RwLock<Spotify>>, mut stream: TcpStream) {
-        match Self::process(&mut stream).await {
-            Err(err) => {
-                error!("error in oauth callback handler: {}", err);
-
-                let _ = Self::respond(
-                    stream,
-                    "HTTP/1.1 400 BAD REQUEST\r\nContent-Type: text/html\r\nContent-Length: 42\r\n\r\n<html><body><h1>Bad \
-                     request!</h1></body>\r\n",
+        let callback = Self::process_in_time(&mut stream, REQUEST_TIMEOUT).await;
+        let known_state = match callback {
+            Ok(ref callback) => Self::is_auth_state(&spotify, callback.state()).await,
+            Err(_) => true,
+        };
+
+        let (status, page) = match callback {
+            Err(CallbackError::NotFound(path)) => {
+                debug!("unknown path requested: {}", path);
+                ("404 NOT FOUND", page("Not found", "Nothing to see here."))
+            }
+            Err(CallbackError::TimedOut) => {
+                debug!("oauth callback request timed out");
+                ("408 REQUEST TIMEOUT", page("Request timeout", "The request took too long."))
+            }
+            Err(error) => {
+                error!("error in oauth callback handler: {}", error);
+                (
+                    "400 BAD REQUEST",
+                    page("Bad request", "This doesn't look like a Spotify login callback."),
                 )
-                .await;
             }
-            Ok(None) => {
-                warn!("request with empty url");
-
-                let _ = Self::respond(
-                    stream,
-                    "HTTP/1.1 400 BAD REQUEST\r\nContent-Type: text/html\r\nContent-Length: 40\r\n\r\n<html><body><h1>Empty \
-                     URL!</h1></body>\r\n",
+            Ok(_) if !known_state => {
+                warn!("oauth callback with unknown state");
+                login_failed("unexpected login response, try to log in again");
+                (
+                    "400 BAD REQUEST",
+                    page(
+                        "Login failed",
+                        "This login link has expired, open the authorization URL from the app again.",
+                    ),
+                )
+            }
+            Ok(Callback::Error { ref error, .. }) if error == "access_denied" => {
+                info!("access denied by user");
+                spotify.write().await.end_authorization();
+                login_failed("access was denied");
+                (
+                    "200 OK",
+                    page(
+                        "Login cancelled",
+                        "You have denied access to your Spotify account. You can close this window.",
+                    ),
+                )
+            }
+            Ok(Callback::Error { error, .. }) => {
+                warn!("oauth error: {}", error);
+                spotify.write().await.end_authorization();
+                login_failed(&error);
+                (
+                    "200 OK",
+                    page("Login failed", &format!("Spotify responded with error: {}", error)),
                 )
-                .await;
             }
-            Ok(Some(code)) => {
+            Ok(Callback::Code { code, .. }) => {
                 info!("oauth code received");
 
                 match spotify.write().await.authorize_user(code).await {
                     Ok(_) => {
-                        let _ = Self::respond(
-                            stream,
-                            "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nContent-Length: 79\r\n\r\n<html><body><h1>Login \
-                             successful!</h1><script>window.close();</script></body>\r\n",
+                        let _ = broadcast(AppEvent::SpotifyLoginSucceeded);
+                        (
+                            "200 OK",
+                            format!(
+                                "{}<script>window.close();</script>",
+                                page("Login successful", "You can close this window now.")
+                            ),
                         )
-                        .await;
                     }
                     Err(error) => {
-                        let message = format!("<html><body><h1>Login error: {:?}</h1></body></html>\r\n", error);
-
-                        let response = format!(
-                            "HTTP/1.1 401 UNAUTHORIZED\r\nContent-Type: text/html\r\nContent-Length: {}\r\n\r\n{}",
-                            message.len(),
-                            message
-                        );
-
-                        let _ = Self::respond(stream, &response).await;
+                        error!("failed to get token: {}", error);
+                        login_failed(&error.to_string());
+                        ("401 UNAUTHORIZED", page("Login error", &error.to_string()))
                     }
                 }
             }
         };
+
+        let _ = Self::respond(stream, status, &page).await;
     }
 
-    async fn process(stream: &mut TcpStream) -> Result<Option<String>, Error> {
-        let mut buffer = [0u8; 2048];
+    async fn is_auth_state(spotify: &RwLock<Spotify>, state: Option<&str>) -> bool {
+        match state {
+            Some(state) => spotify.read().await.is_auth_state(state),
+            None => false,
+        }
+    }
 
-        if stream.read(&mut buffer[..]).await? > 0 {
-            let buffer = String::from_utf8_lossy(&buffer);
+    /// Same as [`process`](Self::process), but gives up if the request head
+    /// is not read in `timeout`, so that a stalled client doesn't hold the
+    /// connection open forever
+    pub async fn process_in_time<R: AsyncRead + Unpin>(stream: &mut R, timeout: Duration) -> Result<Callback, CallbackError> {
+        tokio::time::timeout(timeout, Self::process(stream))
+            .await
+            .unwrap_or(Err(CallbackError::TimedOut))
+    }
 
-            if !buffer.starts_with("GET /callback?code=") {
-                return Err(Error::from(ErrorKind::InvalidInput));
-            }
+    /// Reads the request head and parses OAuth callback params from its request
+    /// line
+    pub async fn process<R: AsyncRead + Unpin>(stream: &mut R) -> Result<Callback, CallbackError> {
+        let mut buffer = Vec::with_capacity(1024);
+        let mut chunk = [0u8; 1024];
 
-            debug!("read data: {}", buffer);
+        // Request line is all we need, but the head is read to the end to respond
+        // after the whole request is sent
+        while !buffer.windows(4).any(|window| window == b"\r\n\r\n") {
+            if buffer.len() > MAX_REQUEST_SIZE {
+                return Err(CallbackError::BadRequest("request is too long"));
+            }
 
-            let url = buffer
-                .lines()
-                .next()
-                .and_then(|line| {
-                    if line.ends_with(" HTTP/1.1") {
-                        line.split(' ').nth(1)
-                    } else {
-                        None
-                    }
-                })
-                .map(|s| {
-                    let start = 15;
-                    let end = s[start..].find('&').map(|p| p + start).unwrap_or_else(|| s.len());
-                    &s[start..end]
-                })
-                .filter(|s| !s.is_empty() && s.chars().all(|c| matches!(c, '0'..='9' | 'a'..='z' | 'A'..='Z' | '-' | '_')))
-                .map(|s| s.to_owned());
-
-            Ok(url)
-        } else {
-            Ok(None)
+            match stream.read(&mut chunk).await? {
+                0 if buffer.is_empty() => return Err(CallbackError::BadRequest("empty request")),
+                0 => break,
+                size => buffer.extend_from_slice(&chunk[..size]),
+            }
         }
+
+        let head = String::from_utf8_lossy(&buffer);
+        debug!("read data: {}", head);
+
+        let request_line = head.lines().next().unwrap_or_default();
+        parse_request_line(request_line)
     }
 
-    async fn respond(mut stream: TcpStream, message: &str) -> Result<(), Error> {
-        stream.write_all(message.as_bytes()).await?;
+    async fn respond(mut stream: TcpStream, status: &str, page: &str) -> Result<(), Error> {
+        let response = format!(
+            "HTTP/1.1 {}\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status,
+            page.len(),
+            page
+        );
+
+        stream.write_all(response.as_bytes()).await?;
         stream.flush().await?;
         Ok(())
     }
 }
+
+/// Parses `GET /callback?code=...&state=... HTTP/1.1` line
+pub fn parse_request_line(line: &str) -> Result<Callback, CallbackError> {
+    let mut parts = line.split(' ');
+
+    let (method, target, version) = match (parts.next(), parts.next(), parts.next(), parts.next()) {
+        (Some(method), Some(target), Some(version), None) => (method, target, version),
+        _ => return Err(CallbackError::BadRequest("malformed request line")),
+    };
+
+    if !version.starts_with("HTTP/1.") {
+        return Err(CallbackError::BadRequest("unsupported protocol"));
+    }
+
+    if method != "GET" {
+        return Err(CallbackError::BadRequest("unsupported method"));
+    }
+
+    if !target.starts_with('/') {
+        return Err(CallbackError::BadRequest("invalid request target"));
+    }
+
+    let url = Url::parse("http://localhost")
+        .and_then(|base| base.join(target))
+        .map_err(|_| CallbackError::BadRequest("invalid request target"))?;
+
+    if url.path() != CALLBACK_PATH {
+        return Err(CallbackError::NotFound(url.path().to_owned()));
+    }
+
+    let param = |name: &str| {
+        url.query_pairs()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.into_owned())
+            .filter(|value| !value.is_empty())
+    };
+
+    match (param("code"), param("error"), param("state")) {
+        (_, Some(error), state) => Ok(Callback::Error { error, state }),
+        (Some(code), None, Some(state)) => Ok(Callback::Code { code, state }),
+        (Some(_), None, None) => Err(CallbackError::BadRequest("missing state")),
+        (None, None, _) => Err(CallbackError::BadRequest("missing code")),
+    }
+}
+
+fn login_failed(reason: &str) { let _ = broadcast(AppEvent::SpotifyLoginFailed(reason.to_owned())); }
+
+fn page(title: &str, message: &str) -> String {
+    format!(
+        "<!DOCTYPE html><html><head><title>{0}</title></head><body><h1>{0}</h1><p>{1}</p></body></html>\r\n",
+        escape_html(title),
+        escape_html(message)
+    )
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::subscribe;
+    use tokio::sync::broadcast::Receiver;
+
+    async fn start_server() -> (SocketAddr, SpotifyRef) {
+        let spotify = Arc::new(RwLock::new(Spotify::new(
+            "client".to_owned(),
+            String::new(),
+            std::env::temp_dir().join("spodjfy-login-test-token.json"),
+        )));
+        let server = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        let address = server.local_addr().unwrap();
+
+        tokio::spawn(LoginService::new(spotify.clone()).serve(server, address));
+
+        (address, spotify)
+    }
+
+    /// State of a login attempt started the way the app does it
+    async fn start_login(spotify: &SpotifyRef) -> String {
+        let url = Url::parse(&spotify.write().await.get_authorize_url().unwrap()).unwrap();
+
+        url.query_pairs()
+            .find(|(key, _)| key == "state")
+            .map(|(_, value)| value.into_owned())
+            .unwrap()
+    }
+
+    /// Serves a single PKCE token request, returns the endpoint url
+    async fn start_token_server(token: &'static str) -> String {
+        let mut listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        let url = format!("http://{}/api/token", listener.local_addr().unwrap());
+
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            let mut chunk = [0u8; 1024];
+
+            // The form has to be read to the end before responding
+            let body_size = loop {
+                let size = stream.read(&mut chunk).await.unwrap();
+                request.extend_from_slice(&chunk[..size]);
+
+                if let Some(end) = request.windows(4).position(|window| window == b"\r\n\r\n") {
+                    let head = String::from_utf8_lossy(&request[..end]).to_lowercase();
+                    let length = head
+                        .lines()
+                        .find_map(|line| line.strip_prefix("content-length:"))
+                        .and_then(|length| length.trim().parse::<usize>().ok())
+                        .unwrap_or(0);
+
+                    break end + 4 + length;
+                }
+            };
+
+            while request.len() < body_size {
+                let size = stream.read(&mut chunk).await.unwrap();
+                request.extend_from_slice(&chunk[..size]);
+            }
+
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                token.len(),
+                token
+            );
+            stream.write_all(response.as_bytes()).await.unwrap();
+        });
+
+        url
+    }
+
+    /// Waits for the first event the filter accepts, other tests broadcast too
+    async fn wait_event<F: Fn(&AppEvent) -> bool>(events: &mut Receiver<AppEvent>, filter: F) -> AppEvent {
+        tokio::time::timeout(Duration::from_secs(2), async {
+            loop {
+                match events.recv().await {
+                    Ok(event) if filter(&event) => return event,
+                    _ => {}
+                }
+            }
+        })
+        .await
+        .expect("event was not broadcast")
+    }
+
+    async fn request(address: SocketAddr, head: &str) -> String {
+        let mut stream = TcpStream::connect(address).await.unwrap();
+        let mut response = String::new();
+
+        stream.write_all(head.as_bytes()).await.unwrap();
+        stream.read_to_string(&mut response).await.unwrap();
+
+        response
+    }
+
+    /// Server side of a new loopback connection, and its client side
+    async fn connection() -> (TcpStream, TcpStream) {
+        let mut listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).await.unwrap();
+        let (server, _) = listener.accept().await.unwrap();
+
+        (server, client)
+    }
+
+    #[tokio::test]
+    async fn stalled_connection_does_not_block_others() {
+        let (address, _) = start_server().await;
+        let _stalled = TcpStream::connect(address).await.unwrap();
+
+        let response = tokio::time::timeout(Duration::from_secs(2), request(address, "GET /other HTTP/1.1\r\n\r\n"))
+            .await
+            .expect("request was not served while another connection is open");
+
+        assert!(response.starts_with("HTTP/1.1 404 NOT FOUND\r\n"));
+    }
+
+    #[tokio::test]
+    async fn callback_with_unknown_state_is_rejected() {
+        let (address, _) = start_server().await;
+        let response = request(
+            address,
+            "GET /callback?code=abc&state=unknown HTTP/1.1\r\nHost: localhost\r\n\r\n",
+        )
+        .await;
+
+        assert!(response.starts_with("HTTP/1.1 400 BAD REQUEST\r\n"));
+        assert!(response.contains("Login failed"));
+    }
+
+    #[tokio::test]
+    async fn denied_access_with_known_state_is_reported() {
+        let (address, spotify) = start_server().await;
+        let state = start_login(&spotify).await;
+        let mut events = subscribe();
+
+        let response = request(
+            address,
+            &format!(
+                "GET /callback?error=access_denied&state={} HTTP/1.1\r\nHost: localhost\r\n\r\n",
+                state
+            ),
+        )
+        .await;
+
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("Login cancelled"));
+        wait_event(
+            &mut events,
+            |event| matches!(event, AppEvent::SpotifyLoginFailed(reason) if reason == "access was denied"),
+        )
+        .await;
+        // The next login attempt gets a new state
+        assert!(!spotify.read().await.is_auth_state(&state));
+    }
+
+    #[tokio::test]
+    async fn code_with_known_state_logs_in() {
+        let (address, spotify) = start_server().await;
+        let token_url = start_token_server(
+            r#"{"access_token":"access","token_type":"Bearer","scope":"user-read-private","expires_in":3600,"refresh_token":"refresh"}"#,
+        )
+        .await;
+        spotify.write().await.set_token_url(token_url);
+        let state = start_login(&spotify).await;
+        let mut events = subscribe();
+
+        let response = request(
+            address,
+            &format!("GET /callback?code=abc&state={} HTTP/1.1\r\nHost: localhost\r\n\r\n", state),
+        )
+        .await;
+
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{}", response);
+        assert!(response.contains("Login successful"));
+        wait_event(&mut events, |event| matches!(event, AppEvent::SpotifyLoginSucceeded)).await;
+        assert!(spotify.read().await.token_expires_at().is_some());
+        assert!(!spotify.read().await.is_auth_state(&state));
+    }
+
+    #[test]
+    fn callback_with_code_is_parsed() {
+        assert_eq!(
+            parse_request_line("GET /callback?code=abc&state=xyz HTTP/1.1").unwrap(),
+            Callback::Code {
+                code: "abc".to_owned(),
+                state: "xyz".to_owned(),
+            }
+        );
+    }
+
+    #[test]
+    fn callback_with_error_is_parsed() {
+        assert_eq!(
+            parse_request_line("GET /callback?error=access_denied&state=xyz HTTP/1.1").unwrap(),
+            Callback::Error {
+                error: "access_denied".to_owned(),
+                state: Some("xyz".to_owned()),
+            }
+        );
+        // Error wins over code, and state is not needed to report it
+        assert_eq!(
+            parse_request_line("GET /callback?code=abc&error=server_error HTTP/1.1").unwrap(),
+            Callback::Error {
+                error: "server_error".to_owned(),
+                state: None,
+            }
+        );
+    }
+
+    #[test]
+    fn callback_without_state_or_code_is_rejected() {
+        assert!(matches!(
+            parse_request_line("GET /callback?code=abc HTTP/1.1"),
+            Err(CallbackError::BadRequest("missing state"))
+        ));
+        assert!(matches!(
+            parse_request_line("GET /callback?code=abc&state= HTTP/1.1"),
+            Err(CallbackError::BadRequest("missing state"))
+        ));
+        assert!(matches!(
+            parse_request_line("GET /callback?state=xyz HTTP/1.1"),
+            Err(CallbackError::BadRequest("missing code"))
+        ));
+    }
+
+    #[test]
+    fn other_requests_are_rejected() {
+        assert!(matches!(
+            parse_request_line("POST /callback?code=abc&state=xyz HTTP/1.1"),
+            Err(CallbackError::BadRequest("unsupported method"))
+        ));
+        assert!(matches!(
+            parse_request_line("GET /callback?code=abc&state=xyz SPDY/3"),
+            Err(CallbackError::BadRequest("unsupported protocol"))
+        ));
+        assert!(matches!(
+            parse_request_line("GET /callback"),
+            Err(CallbackError::BadRequest("malformed request line"))
+        ));
+        assert!(matches!(
+            parse_request_line("GET /favicon.ico HTTP/1.1"),
+            Err(CallbackError::NotFound(path)) if path == "/favicon.ico"
+        ));
+    }
+
+    #[tokio::test]
+    async fn request_head_is_read_in_parts() {
+        let (mut server, mut client) = connection().await;
+
+        client
+            .write_all(b"GET /callback?code=abc&state=xyz HTTP/1.1\r\n")
+            .await
+            .unwrap();
+        client.write_all(b"Host: localhost\r\n\r\n").await.unwrap();
+
+        let callback = LoginService::process_in_time(&mut server, Duration::from_secs(2))
+            .await
+            .unwrap();
+
+        assert_eq!(callback, Callback::Code {
+            code: "abc".to_owned(),
+            state: "xyz".to_owned(),
+        });
+    }
+
+    #[tokio::test]
+    async fn stalled_request_times_out() {
+        let (mut server, mut client) = connection().await;
+
+        client.write_all(b"GET /callback?code=abc").await.unwrap();
+
+        let result = LoginService::process_in_time(&mut server, Duration::from_millis(100)).await;
+
+        assert!(matches!(result, Err(CallbackError::TimedOut)));
+    }
+}
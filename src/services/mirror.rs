@@ -0,0 +1,104 @@
+use crate::services::store::{Collection, Storage, StorageError, StorageModel};
+use rspotify::model::{Cursor, CursorBasedPage, FullArtist, Page, PlaylistItem, SavedAlbum, SavedTrack, SimplifiedPlaylist};
+use serde_derive::{Deserialize, Serialize};
+use std::{cmp::Reverse, path::Path};
+
+/// Playlist tracks snapshot, refreshed only when playlist's `snapshot_id`
+/// changes
+#[derive(Serialize, Deserialize)]
+pub struct MirroredPlaylist {
+    pub id: String,
+    pub snapshot_id: String,
+    pub items: Vec<PlaylistItem>,
+}
+
+impl StorageModel for MirroredPlaylist {
+    const TREE_NAME: &'static str = "playlist_tracks";
+
+    fn key(&self) -> &str { &self.id }
+}
+
+/// Local copy of user's library to browse it when Spotify API is unreachable
+pub struct LibraryMirror {
+    storage: Storage,
+    pub tracks: Collection<SavedTrack>,
+    pub albums: Collection<SavedAlbum>,
+    pub artists: Collection<FullArtist>,
+    pub playlists: Collection<SimplifiedPlaylist>,
+    pub playlist_tracks: Collection<MirroredPlaylist>,
+}
+
+impl LibraryMirror {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<LibraryMirror, StorageError> {
+        let storage = Storage::new(path)?;
+
+        Ok(LibraryMirror {
+            tracks: storage.collection()?,
+            albums: storage.collection()?,
+            artists: storage.collection()?,
+            playlists: storage.collection()?,
+            playlist_tracks: storage.collection()?,
+            storage,
+        })
+    }
+
+    pub fn flush(&self) -> Result<usize, StorageError> { self.storage.flush() }
+
+    pub fn saved_tracks(&self, offset: u32) -> Result<Page<SavedTrack>, StorageError> {
+        let mut items = self.tracks.iter().collect::<Result<Vec<_>, _>>()?;
+        items.sort_by_key(|item| Reverse(item.added_at));
+        Ok(Self::page(items, offset))
+    }
+
+    pub fn saved_albums(&self, offset: u32) -> Result<Page<SavedAlbum>, StorageError> {
+        let mut items = self.albums.iter().collect::<Result<Vec<_>, _>>()?;
+        items.sort_by_key(|item| Reverse(item.added_at));
+        Ok(Self::page(items, offset))
+    }
+
+    pub fn followed_artists(&self) -> Result<CursorBasedPage<FullArtist>, StorageError> {
+        let mut items = self.artists.iter().collect::<Result<Vec<_>, _>>()?;
+        items.sort_by(|a, b| a.name.cmp(&b.name));
+
+        Ok(CursorBasedPage {
+            href: String::new(),
+            limit: items.len() as u32,
+            next: None,
+            cursors: Cursor { after: None },
+            total: Some(items.len() as u32),
+            items,
+        })
+    }
+
+    pub fn saved_playlists(&self, offset: u32) -> Result<Page<SimplifiedPlaylist>, StorageError> {
+        let mut items = self.playlists.iter().collect::<Result<Vec<_>, _>>()?;
+        items.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(Self::page(items, offset))
+    }
+
+    pub fn playlist_tracks(&self, uri: &str, offset: u32) -> Result<Option<Page<PlaylistItem>>, StorageError> {
+        let id = uri.rsplit(':').next().unwrap_or(uri);
+
+        Ok(self
+            .playlist_tracks
+            .get(id)?
+            .map(|playlist| Self::page(playlist.items, offset)))
+    }
+
+    /// Returns all items starting from `offset` as a single last page,
+    /// as the whole collection is decoded anyway
+    fn page<T>(mut items: Vec<T>, offset: u32) -> Page<T> {
+        let total = items.len() as u32;
+        let items = items.split_off((offset as usize).min(items.len()));
+
+        Page {
+            href: String::new(),
+            limit: items.len() as u32,
+            next: None,
+            offset,
+            previous: None,
+            total,
+            items,
+        }
+    }
+}
@@ -1,7 +1,25 @@
 pub mod api;
+pub mod cache;
+mod control;
+#[cfg(any(test, feature = "fake"))]
+pub mod fake;
 mod login;
+pub mod mirror;
+mod mpris;
+pub mod queue;
+pub mod sets;
 pub mod spotify;
 pub mod store;
 
+pub use cache::TracksCache;
+pub use control::ControlService;
+#[cfg(any(test, feature = "fake"))]
+pub use fake::{FakeSpotify, Fixture, FixtureError};
 pub use login::LoginService;
-pub use spotify::{RefreshTokenService, Spotify, SpotifyRef};
+pub use mirror::LibraryMirror;
+pub use mpris::MprisService;
+pub use queue::PlaybackQueue;
+pub use sets::SetsStorage;
+pub use spotify::{
+    CrossfadeService, LibrarySyncService, PlaybackStateService, QueueFeederService, RefreshTokenService, Spotify, SpotifyRef,
+};
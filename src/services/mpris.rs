@@ -0,0 +1,281 @@
+//! # MPRIS2 server
+//!
+//! Exposes playback on D-Bus as `org.mpris.MediaPlayer2.spodjfy`, so media
+//! keys, desktop widgets and `playerctl` can control it. Commands go to
+//! [`PlaybackControlApi`], and the player state is updated from playback
+//! state broadcast by
+//! [`PlaybackStateService`](crate::services::spotify::PlaybackStateService).
+//!
+//! The server is registered on the session bus `DBUS_SESSION_BUS_ADDRESS`
+//! points to, so it can be tried on a private bus started with
+//! `dbus-run-session`. It runs on glib main context, so it must be started
+//! on the main thread.
+
+use crate::{
+    models::{HasDuration, HasImages, HasName, HasUri, TrackLike},
+    observe,
+    services::api::PlaybackControlApi,
+    utils::AsyncCell,
+    AppEvent,
+};
+use mpris_player::{LoopStatus, Metadata, MprisPlayer, PlaybackStatus};
+use relm::Channel;
+use rspotify::{
+    client::ClientResult,
+    model::{CurrentPlaybackContext, PlayingItem, RepeatState},
+};
+use std::{
+    cell::Cell,
+    rc::Rc,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::runtime::Handle;
+
+const MPRIS_NAME: &str = "spodjfy";
+
+const IDENTITY: &str = "Spodjfy";
+
+const DESKTOP_ENTRY: &str = "spodjfy";
+
+/// Track id MPRIS reserves for no track
+const NO_TRACK_ID: &str = "/org/mpris/MediaPlayer2/TrackList/NoTrack";
+
+/// Command got from an MPRIS client
+enum Command {
+    Play,
+    Pause,
+    Next,
+    Previous,
+    Seek(u32),
+    SetVolume(u8),
+    SetShuffle(bool),
+    SetRepeatMode(RepeatState),
+}
+
+impl Command {
+    async fn execute<C: PlaybackControlApi>(self, client: &C) -> ClientResult<()> {
+        match self {
+            Command::Play => client.start_playback().await,
+            Command::Pause => client.pause_playback().await,
+            Command::Next => client.play_next_track().await,
+            Command::Previous => client.play_prev_track().await,
+            Command::Seek(position) => client.seek_track(position).await,
+            Command::SetVolume(value) => client.set_volume(value).await,
+            Command::SetShuffle(value) => client.set_shuffle(value).await,
+            Command::SetRepeatMode(mode) => client.set_repeat_mode(mode).await,
+        }
+    }
+}
+
+/// Last known progress, needed to handle play/pause toggle and relative seeks
+#[derive(Default)]
+struct Progress {
+    is_playing: Cell<bool>,
+    position_ms: Cell<u32>,
+    updated_at: Cell<Option<Instant>>,
+}
+
+impl Progress {
+    fn update(&self, state: &Option<CurrentPlaybackContext>) {
+        let state = state.as_ref();
+
+        self.is_playing.set(state.map_or(false, |state| state.is_playing));
+        self.position_ms.set(state.and_then(|state| state.progress_ms).unwrap_or(0));
+        self.updated_at.set(Some(Instant::now()));
+    }
+
+    /// Position moved by the time passed since the last update
+    fn position_ms(&self) -> u32 {
+        let elapsed = match self.updated_at.get() {
+            Some(updated_at) if self.is_playing.get() => updated_at.elapsed(),
+            _ => Duration::from_secs(0),
+        };
+
+        self.position_ms.get() + elapsed.as_millis() as u32
+    }
+}
+
+pub struct MprisService {
+    _player: Arc<MprisPlayer>,
+}
+
+impl MprisService {
+    pub fn start<C>(pool: Handle, client: AsyncCell<C>) -> MprisService
+    where
+        C: PlaybackControlApi + Send + Sync + 'static,
+    {
+        let player = MprisPlayer::new(MPRIS_NAME.to_owned(), IDENTITY.to_owned(), DESKTOP_ENTRY.to_owned());
+        let progress = Rc::new(Progress::default());
+
+        player.set_can_quit(false);
+        player.set_can_raise(false);
+        player.set_can_control(true);
+        player.set_can_play(true);
+        player.set_can_pause(true);
+        player.set_can_go_next(true);
+        player.set_can_go_previous(true);
+        player.set_can_seek(true);
+        player.set_playback_status(PlaybackStatus::Stopped);
+
+        let commands_pool = pool.clone();
+        let send = move |command: Command| {
+            let client = client.clone();
+
+            commands_pool.spawn(async move {
+                if let Err(error) = command.execute(&*client.read().await).await {
+                    warn!("mpris command failed: {}", error);
+                }
+            });
+        };
+
+        let send = Rc::new(send);
+
+        {
+            let send = send.clone();
+            player.connect_play(move || send(Command::Play));
+        }
+        {
+            let send = send.clone();
+            player.connect_pause(move || send(Command::Pause));
+        }
+        {
+            let send = send.clone();
+            player.connect_stop(move || send(Command::Pause));
+        }
+        {
+            let (send, progress) = (send.clone(), progress.clone());
+            player.connect_play_pause(move || {
+                send(if progress.is_playing.get() {
+                    Command::Pause
+                } else {
+                    Command::Play
+                })
+            });
+        }
+        {
+            let send = send.clone();
+            player.connect_next(move || send(Command::Next));
+        }
+        {
+            let send = send.clone();
+            player.connect_previous(move || send(Command::Previous));
+        }
+        {
+            let (send, progress) = (send.clone(), progress.clone());
+            player.connect_seek(move |offset_us| {
+                let position = progress.position_ms() as i64 + offset_us / 1000;
+                send(Command::Seek(position.max(0) as u32))
+            });
+        }
+        {
+            let send = send.clone();
+            player.connect_volume(move |volume| send(Command::SetVolume((volume.max(0.0).min(1.0) * 100.0) as u8)));
+        }
+        {
+            let send = send.clone();
+            player.connect_shuffle(move |shuffle| send(Command::SetShuffle(shuffle)));
+        }
+        player.connect_loop_status(move |status| {
+            send(Command::SetRepeatMode(match status {
+                LoopStatus::None => RepeatState::Off,
+                LoopStatus::Track => RepeatState::Track,
+                LoopStatus::Playlist => RepeatState::Context,
+            }))
+        });
+
+        let updated_player = player.clone();
+
+        let (_, state_tx) = Channel::new(move |state: Arc<Option<CurrentPlaybackContext>>| {
+            progress.update(&state);
+            Self::update(&updated_player, &state);
+        });
+
+        observe(&pool, move |event| {
+            if let AppEvent::PlaybackStateUpdated(state) = event {
+                let _ = state_tx.send(state);
+            }
+        });
+
+        MprisService { _player: player }
+    }
+
+    fn update(player: &MprisPlayer, state: &Option<CurrentPlaybackContext>) {
+        let state = match state {
+            Some(state) => state,
+            None => {
+                player.set_playback_status(PlaybackStatus::Stopped);
+                player.set_metadata(Metadata::new());
+                return;
+            }
+        };
+
+        player.set_playback_status(if state.is_playing {
+            PlaybackStatus::Playing
+        } else {
+            PlaybackStatus::Paused
+        });
+        player.set_metadata(state.item.as_ref().map_or_else(Metadata::new, Self::metadata));
+        player.set_position(state.progress_ms.unwrap_or(0) as i64 * 1000);
+        player.set_shuffle(state.shuffle_state);
+        player.set_loop_status(match state.repeat_state {
+            RepeatState::Off => LoopStatus::None,
+            RepeatState::Track => LoopStatus::Track,
+            RepeatState::Context => LoopStatus::Playlist,
+        });
+
+        if let Some(volume) = state.device.volume_percent {
+            if let Err(error) = player.set_volume(volume as f64 / 100.0) {
+                warn!("failed to set mpris volume: {}", error);
+            }
+        }
+    }
+
+    fn metadata(item: &PlayingItem) -> Metadata {
+        let mut metadata = Metadata::new();
+
+        metadata.track_id = Some(Self::track_id(item.uri()));
+        metadata.title = Some(item.name().to_owned());
+        metadata.artist = Some(item.artists().iter().map(|artist| artist.name.clone()).collect());
+        metadata.album = item.album().map(|album| album.name.clone());
+        // Spotify lists the largest image first
+        metadata.art_url = item.images().first().map(|image| image.url.clone());
+        metadata.length = Some(item.duration() as i64 * 1000);
+
+        metadata
+    }
+
+    /// D-Bus object path made of item uri, path elements can only have
+    /// ASCII letters, digits and underscores
+    fn track_id(uri: &str) -> String {
+        let name = uri
+            .trim_start_matches("spotify:")
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect::<String>();
+
+        if name.is_empty() {
+            NO_TRACK_ID.to_owned()
+        } else {
+            format!("/org/mpris/MediaPlayer2/{}/{}", MPRIS_NAME, name)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn track_id_is_object_path_of_uri() {
+        assert_eq!(
+            MprisService::track_id("spotify:track:4uLU6hMCjMI75M1A2tKUQC"),
+            "/org/mpris/MediaPlayer2/spodjfy/track_4uLU6hMCjMI75M1A2tKUQC"
+        );
+        assert_eq!(
+            MprisService::track_id("spotify:local:Some+Artist::Title:215"),
+            "/org/mpris/MediaPlayer2/spodjfy/local_Some_Artist__Title_215"
+        );
+        assert_eq!(MprisService::track_id(""), NO_TRACK_ID);
+    }
+}
@@ -0,0 +1,155 @@
+use crate::services::store::{Collection, Storage, StorageError, StorageModel};
+use serde_derive::{Deserialize, Serialize};
+use std::{collections::VecDeque, path::Path};
+
+const QUEUE_KEY: &str = "default";
+
+#[derive(Serialize, Deserialize)]
+struct QueueSnapshot {
+    name: String,
+    uris: VecDeque<String>,
+}
+
+impl StorageModel for QueueSnapshot {
+    const TREE_NAME: &'static str = "queue";
+
+    fn key(&self) -> &str { &self.name }
+}
+
+/// App side queue of track uris, the source of truth for what plays next.
+/// Tracks are handed over to Spotify one at a time by
+/// [`QueueFeederService`](crate::services::spotify::QueueFeederService),
+/// every change is written through to the storage if it's attached.
+#[derive(Default)]
+pub struct PlaybackQueue {
+    uris: VecDeque<String>,
+    /// Track which was playing when the last track was handed over
+    fed_during: Option<String>,
+    storage: Option<(Storage, Collection<QueueSnapshot>)>,
+}
+
+impl PlaybackQueue {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<PlaybackQueue, StorageError> {
+        let storage = Storage::new(path)?;
+        let snapshots = storage.collection::<QueueSnapshot>()?;
+        let uris = snapshots.get(QUEUE_KEY)?.map(|snapshot| snapshot.uris).unwrap_or_default();
+
+        Ok(PlaybackQueue {
+            uris,
+            fed_during: None,
+            storage: Some((storage, snapshots)),
+        })
+    }
+
+    pub fn uris(&self) -> &VecDeque<String> { &self.uris }
+
+    pub fn len(&self) -> usize { self.uris.len() }
+
+    pub fn is_empty(&self) -> bool { self.uris.is_empty() }
+
+    pub fn push(&mut self, uris: &[String]) -> Result<(), StorageError> {
+        self.uris.extend(uris.iter().cloned());
+        self.save()
+    }
+
+    /// Inserts tracks before `position`, or appends them if position is past
+    /// the end
+    pub fn insert(&mut self, position: usize, uris: &[String]) -> Result<(), StorageError> {
+        let position = position.min(self.uris.len());
+
+        for (index, uri) in uris.iter().enumerate() {
+            self.uris.insert(position + index, uri.clone());
+        }
+
+        self.save()
+    }
+
+    /// Removes tracks at the given positions, so that only the chosen ones
+    /// of repeated tracks are removed, out of range positions are ignored
+    pub fn remove(&mut self, positions: &[usize]) -> Result<(), StorageError> {
+        let mut index = 0;
+
+        self.uris.retain(|_| {
+            let keep = !positions.contains(&index);
+            index += 1;
+            keep
+        });
+
+        self.save()
+    }
+
+    /// Moves a single track from one position to another,
+    /// out of range positions are ignored
+    pub fn move_track(&mut self, from: usize, to: usize) -> Result<(), StorageError> {
+        if from >= self.uris.len() || to >= self.uris.len() || from == to {
+            return Ok(());
+        }
+
+        if let Some(uri) = self.uris.remove(from) {
+            self.uris.insert(to, uri);
+        }
+
+        self.save()
+    }
+
+    /// Replaces queue order with the given one, which must contain exactly
+    /// the same tracks, returns `false` if it doesn't
+    pub fn reorder(&mut self, uris: &[String]) -> Result<bool, StorageError> {
+        let mut current = self.uris.iter().collect::<Vec<_>>();
+        let mut reordered = uris.iter().collect::<Vec<_>>();
+
+        current.sort();
+        reordered.sort();
+
+        if current != reordered {
+            return Ok(false);
+        }
+
+        self.uris = uris.iter().cloned().collect();
+        self.save()?;
+
+        Ok(true)
+    }
+
+    pub fn pop(&mut self) -> Result<Option<String>, StorageError> {
+        let uri = self.uris.pop_front();
+        self.save()?;
+        Ok(uri)
+    }
+
+    /// Takes the next track to hand over while the given track plays, only
+    /// once per playing track, so that everyone who needs the next track
+    /// handed over in time can ask for it
+    pub fn pop_during(&mut self, playing_uri: &str) -> Result<Option<String>, StorageError> {
+        if self.fed_during.as_deref() == Some(playing_uri) {
+            return Ok(None);
+        }
+
+        let uri = self.pop()?;
+
+        if uri.is_some() {
+            self.fed_during = Some(playing_uri.to_owned());
+        }
+
+        Ok(uri)
+    }
+
+    /// Returns a track taken with [`pop`](Self::pop) back to the queue head
+    pub fn unpop(&mut self, uri: String) -> Result<(), StorageError> {
+        self.fed_during = None;
+        self.uris.push_front(uri);
+        self.save()
+    }
+
+    fn save(&self) -> Result<(), StorageError> {
+        if let Some((ref storage, ref snapshots)) = self.storage {
+            snapshots.put(QueueSnapshot {
+                name: QUEUE_KEY.to_owned(),
+                uris: self.uris.clone(),
+            })?;
+            storage.flush()?;
+        }
+
+        Ok(())
+    }
+}
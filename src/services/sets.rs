@@ -0,0 +1,48 @@
+use crate::{
+    models::set::DjSet,
+    services::store::{Collection, Storage, StorageError},
+};
+use std::path::Path;
+
+/// Named DJ sets saved locally
+pub struct SetsStorage {
+    storage: Storage,
+    sets: Collection<DjSet>,
+}
+
+impl SetsStorage {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<SetsStorage, StorageError> {
+        let storage = Storage::new(path)?;
+
+        Ok(SetsStorage {
+            sets: storage.collection()?,
+            storage,
+        })
+    }
+
+    pub fn names(&self) -> Result<Vec<String>, StorageError> {
+        let mut names = self
+            .sets
+            .iter()
+            .map(|set| set.map(|set| set.name))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        names.sort();
+
+        Ok(names)
+    }
+
+    pub fn get(&self, name: &str) -> Result<Option<DjSet>, StorageError> { self.sets.get(name) }
+
+    pub fn save(&self, set: DjSet) -> Result<(), StorageError> {
+        self.sets.put(set)?;
+        self.storage.flush()?;
+        Ok(())
+    }
+
+    pub fn delete(&self, name: &str) -> Result<(), StorageError> {
+        self.sets.delete(name)?;
+        self.storage.flush()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,133 @@
+//! # Authorization Code flow
+//!
+//! Authorize URL is built here for both flavours of the flow: with a client
+//! secret, and with PKCE, when only a client ID is known. PKCE tokens are
+//! requested here as well, since the client secret is always sent by
+//! `rspotify`, and tokens of both flavours are refreshed here, without
+//! borrowing the client.
+
+use chrono::Utc;
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use reqwest::Url;
+use rspotify::{
+    client::{ClientError, ClientResult},
+    oauth2::Token,
+};
+use sha2::{Digest, Sha256};
+
+const AUTHORIZE_URL: &str = "https://accounts.spotify.com/authorize";
+
+pub const TOKEN_URL: &str = "https://accounts.spotify.com/api/token";
+
+/// Authorization started with the last authorize URL,
+/// the callback must bring the same state back
+pub struct AuthRequest {
+    pub state: String,
+    code_verifier: Option<String>,
+}
+
+impl AuthRequest {
+    pub fn new(pkce: bool) -> AuthRequest {
+        AuthRequest {
+            state: random_string(16),
+            code_verifier: if pkce { Some(random_string(64)) } else { None },
+        }
+    }
+
+    pub fn is_pkce(&self) -> bool { self.code_verifier.is_some() }
+
+    pub fn authorize_url(&self, client_id: &str, redirect_uri: &str, scope: &str) -> ClientResult<String> {
+        let mut params = vec![
+            ("client_id", client_id.to_owned()),
+            ("response_type", "code".to_owned()),
+            ("redirect_uri", redirect_uri.to_owned()),
+            ("scope", scope.to_owned()),
+            ("state", self.state.clone()),
+        ];
+
+        if let Some(ref verifier) = self.code_verifier {
+            params.push(("code_challenge_method", "S256".to_owned()));
+            params.push(("code_challenge", code_challenge(verifier)));
+        }
+
+        Url::parse_with_params(AUTHORIZE_URL, &params)
+            .map(String::from)
+            .map_err(|error| ClientError::InvalidAuth(error.to_string()))
+    }
+
+    /// Exchanges the code for a token at the token endpoint, only for PKCE
+    /// requests
+    pub async fn request_token(&self, token_url: &str, client_id: &str, redirect_uri: &str, code: &str) -> ClientResult<Token> {
+        let verifier = self
+            .code_verifier
+            .as_deref()
+            .ok_or_else(|| ClientError::InvalidAuth("Not a PKCE authorization".into()))?;
+
+        post_token_form(
+            token_url,
+            &[
+                ("grant_type", "authorization_code"),
+                ("code", code),
+                ("redirect_uri", redirect_uri),
+                ("client_id", client_id),
+                ("code_verifier", verifier),
+            ],
+            None,
+        )
+        .await
+    }
+}
+
+/// Refreshes a token, the client secret is sent only if there is one, a token
+/// got with PKCE is refreshed with the client ID alone
+pub async fn refresh_access_token(
+    token_url: &str,
+    client_id: &str,
+    client_secret: &str,
+    refresh_token: &str,
+) -> ClientResult<Token> {
+    let mut form = vec![("grant_type", "refresh_token"), ("refresh_token", refresh_token)];
+    let credentials = if client_secret.is_empty() {
+        form.push(("client_id", client_id));
+        None
+    } else {
+        Some((client_id, client_secret))
+    };
+
+    let mut token = post_token_form(token_url, &form, credentials).await?;
+
+    // Refresh token is only sent back if it's rotated
+    if token.refresh_token.is_none() {
+        token.refresh_token = Some(refresh_token.to_owned());
+    }
+
+    Ok(token)
+}
+
+async fn post_token_form(token_url: &str, form: &[(&str, &str)], credentials: Option<(&str, &str)>) -> ClientResult<Token> {
+    let to_auth_error = |error: reqwest::Error| ClientError::InvalidAuth(error.to_string());
+
+    let mut request = reqwest::Client::new().post(token_url).form(form);
+    if let Some((id, secret)) = credentials {
+        request = request.basic_auth(id, Some(secret));
+    }
+
+    let mut token: Token = request
+        .send()
+        .await
+        .and_then(reqwest::Response::error_for_status)
+        .map_err(to_auth_error)?
+        .json()
+        .await
+        .map_err(to_auth_error)?;
+
+    token.expires_at = Some(Utc::now() + chrono::Duration::seconds(token.expires_in.as_secs() as i64));
+
+    Ok(token)
+}
+
+fn random_string(len: usize) -> String { thread_rng().sample_iter(&Alphanumeric).take(len).collect() }
+
+fn code_challenge(verifier: &str) -> String {
+    base64::encode_config(Sha256::digest(verifier.as_bytes()), base64::URL_SAFE_NO_PAD)
+}
@@ -0,0 +1,213 @@
+use crate::{
+    models::crossfade::{self, CrossfadeConfig, Fade},
+    services::{
+        api::{PlaybackControlApi, PlaybackQueueApi, TracksStorageApi},
+        spotify::SpotifyRef,
+    },
+    subscribe, AppEvent,
+};
+use futures::TryFutureExt;
+use rspotify::{
+    client::ClientResult,
+    model::{CurrentPlaybackContext, PlayingItem, RepeatState},
+};
+use std::{sync::Arc, time::Duration};
+use tokio::{
+    runtime::Runtime,
+    sync::broadcast::RecvError,
+    task::JoinHandle,
+    time::{delay_until, Instant},
+};
+
+/// Fade out is scheduled this long before it should start, to have time to
+/// get the track analysis and to make the first volume change in time
+const START_AHEAD: Duration = Duration::from_secs(2);
+
+/// Emulates crossfade with volume ramps around a skip to the next track,
+/// at the mix out point found from the track audio analysis. Playback state
+/// is taken from
+/// [`PlaybackStateService`](crate::services::spotify::PlaybackStateService)
+/// broadcasts, and the position is extrapolated from the last one to start
+/// the fade out in time.
+pub struct CrossfadeService {
+    client: SpotifyRef,
+}
+
+impl CrossfadeService {
+    pub fn new(client: SpotifyRef) -> CrossfadeService { CrossfadeService { client } }
+
+    pub fn spawn(self, runtime: &Runtime) -> JoinHandle<Result<!, RecvError>> {
+        runtime.spawn(self.run().inspect_err(|error| {
+            error!("crossfade thread stopped: {:?}", error);
+        }))
+    }
+
+    pub async fn run(self) -> Result<!, RecvError> {
+        let mut events = subscribe();
+        // Last playback state and the time it was received at
+        let mut latest: Option<(Arc<Option<CurrentPlaybackContext>>, Instant)> = None;
+        // Time to check the extrapolated position at, if no new state comes
+        // before it
+        let mut check_at: Option<Instant> = None;
+        // Track faded out last, so that it's faded out only once
+        let mut faded_out: Option<String> = None;
+        // Mix out point of the current track, to get its analysis only once
+        let mut mix_out: Option<(String, u32)> = None;
+
+        loop {
+            let event = match check_at {
+                Some(check_at) => tokio::select! {
+                    event = events.recv() => Some(event),
+                    _ = delay_until(check_at) => None,
+                },
+                None => Some(events.recv().await),
+            };
+
+            match event {
+                Some(Ok(AppEvent::PlaybackStateUpdated(state))) => latest = Some((state, Instant::now())),
+                Some(Ok(_)) => continue,
+                Some(Err(RecvError::Lagged(count))) => {
+                    warn!("crossfade skipped {} events", count);
+                    continue;
+                }
+                Some(Err(closed @ RecvError::Closed)) => return Err(closed),
+                None => {}
+            }
+
+            check_at = match latest {
+                Some((ref state, received_at)) => {
+                    self.poll(state, received_at, crossfade::config(), &mut faded_out, &mut mix_out)
+                        .await
+                }
+                None => None,
+            };
+        }
+    }
+
+    /// Runs crossfade if it's time, returns time to check again at if it's
+    /// not time yet, or `None` if there's nothing to wait for until the next
+    /// playback state
+    async fn poll(
+        &self,
+        state: &Option<CurrentPlaybackContext>,
+        received_at: Instant,
+        config: CrossfadeConfig,
+        faded_out: &mut Option<String>,
+        mix_out: &mut Option<(String, u32)>,
+    ) -> Option<Instant> {
+        if !config.enabled {
+            return None;
+        }
+
+        let (uri, duration, progress, volume) = match state {
+            Some(CurrentPlaybackContext {
+                is_playing: true,
+                progress_ms: Some(progress),
+                item: Some(PlayingItem::Track(track)),
+                repeat_state,
+                device,
+                ..
+            }) if *repeat_state != RepeatState::Track => match device.volume_percent {
+                Some(volume) if volume > 0 => (&track.uri, track.duration_ms, *progress, volume.min(100) as u8),
+                _ => return None,
+            },
+            _ => return None,
+        };
+
+        if faded_out.as_ref() == Some(uri) {
+            return None;
+        }
+
+        let now = Instant::now();
+        let progress = progress + (now - received_at).as_millis() as u32;
+
+        // Analysis is not needed until the fade out can start
+        let earliest = crossfade::earliest_fade_out(duration, config.fade_ms);
+        if let Some(timeout) = Self::time_till(progress, earliest) {
+            return Some(now + timeout);
+        }
+
+        let mix_out_ms = match mix_out {
+            Some((mix_out_uri, mix_out_ms)) if mix_out_uri == uri => *mix_out_ms,
+            _ => {
+                let analysis = match self.client.read().await.get_track_analysis(uri).await {
+                    Ok(analysis) => Some(analysis),
+                    Err(error) => {
+                        warn!("crossfade failed to get track analysis, fading at the end: {}", error);
+                        None
+                    }
+                };
+
+                let mix_out_ms = crossfade::mix_out_point(duration, analysis.as_ref());
+                *mix_out = Some((uri.clone(), mix_out_ms));
+                mix_out_ms
+            }
+        };
+
+        let start = crossfade::fade_out_start(mix_out_ms, config.fade_ms);
+        if let Some(timeout) = Self::time_till(progress, start) {
+            return Some(now + timeout);
+        }
+
+        *faded_out = Some(uri.clone());
+
+        // Seeked past the fade out, or it's too late to do it in full
+        if progress > start + START_AHEAD.as_millis() as u32 {
+            return None;
+        }
+
+        let wait = Duration::from_millis(start.saturating_sub(progress) as u64);
+
+        if let Err(error) = self.crossfade(uri, volume, wait, config).await {
+            warn!("crossfade failed: {}", error);
+
+            if let Err(error) = self.client.read().await.set_volume(volume).await {
+                warn!("failed to restore volume after crossfade: {}", error);
+            }
+        }
+
+        None
+    }
+
+    /// Time to check the position again at if it's not close enough yet
+    fn time_till(progress: u32, position: u32) -> Option<Duration> {
+        let left = Duration::from_millis(position.saturating_sub(progress) as u64);
+
+        if left > START_AHEAD {
+            Some(left - START_AHEAD)
+        } else {
+            None
+        }
+    }
+
+    /// Ramps volume down, skips to the next track and ramps volume back up.
+    /// Volume changes don't wake up playback state polling, only the skip
+    /// does.
+    async fn crossfade(&self, uri: &str, volume: u8, wait: Duration, config: CrossfadeConfig) -> ClientResult<()> {
+        let fade_out_at = Instant::now() + wait;
+
+        // The skip can come before the queue feeder sees the track ending, so
+        // the next queued track is handed over here if it's not yet
+        delay_until(fade_out_at).await;
+        if let Some(next_uri) = self.client.write().await.feed_queue_track(uri).await? {
+            info!("crossfade fed queued track {} to player", next_uri);
+        }
+
+        for (offset, value) in crossfade::fade_steps(volume, config.fade_ms, config.curve, Fade::Out) {
+            delay_until(fade_out_at + Duration::from_millis(offset as u64)).await;
+            self.client.read().await.set_ramp_volume(value).await?;
+        }
+
+        delay_until(fade_out_at + Duration::from_millis(config.fade_ms as u64)).await;
+        self.client.read().await.play_next_track().await?;
+
+        let fade_in_at = Instant::now();
+
+        for (offset, value) in crossfade::fade_steps(volume, config.fade_ms, config.curve, Fade::In) {
+            delay_until(fade_in_at + Duration::from_millis(offset as u64)).await;
+            self.client.read().await.set_ramp_volume(value).await?;
+        }
+
+        Ok(())
+    }
+}
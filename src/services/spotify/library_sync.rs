@@ -0,0 +1,281 @@
+use crate::services::{
+    api::{AlbumsStorageApi, ArtistsStorageApi, PlaylistsStorageApi, TracksStorageApi},
+    mirror::{LibraryMirror, MirroredPlaylist},
+    spotify::SpotifyRef,
+    store::{Collection, StorageError, StorageModel},
+};
+use futures::{Future, TryFutureExt};
+use rspotify::{client::ClientError, model::Page};
+use std::{collections::HashSet, sync::Arc, time::Duration};
+use thiserror::Error;
+use tokio::{runtime::Runtime, task::JoinHandle};
+
+const DEFAULT_SYNC_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+
+const PAGE_SIZE: u32 = 50;
+
+const PLAYLIST_PAGE_SIZE: u32 = 100;
+
+#[derive(Error, Debug)]
+pub enum SyncError {
+    #[error(transparent)]
+    Spotify(#[from] ClientError),
+    #[error("storage error: {0}")]
+    Storage(#[from] StorageError),
+}
+
+/// Mirrors user's library into local storage of the current account,
+/// see [`LibraryMirror`](crate::services::mirror::LibraryMirror)
+pub struct LibrarySyncService {
+    client: SpotifyRef,
+}
+
+/// Sync of a single mirror, the mirror is taken from the client on every
+/// sync, as it's replaced when the account is switched
+struct MirrorSync {
+    client: SpotifyRef,
+    mirror: Arc<LibraryMirror>,
+}
+
+impl LibrarySyncService {
+    pub fn new(client: SpotifyRef) -> LibrarySyncService { LibrarySyncService { client } }
+
+    pub fn spawn(self, runtime: &Runtime) -> JoinHandle<Result<!, ClientError>> {
+        runtime.spawn(self.run().inspect_err(|error| {
+            error!("library sync thread stopped: {:?}", error);
+        }))
+    }
+
+    pub async fn run(self) -> Result<!, ClientError> {
+        let mut timer = tokio::time::interval(DEFAULT_SYNC_TIMEOUT);
+
+        loop {
+            timer.tick().await;
+
+            let mirror = match self.client.read().await.library_mirror() {
+                Some(mirror) => mirror,
+                None => continue,
+            };
+
+            info!("sync library mirror");
+
+            let sync = MirrorSync {
+                client: self.client.clone(),
+                mirror,
+            };
+
+            // Network is expected to be flaky, so just try again later
+            if let Err(error) = sync.sync().await {
+                warn!("library sync failed: {}", error);
+            }
+        }
+    }
+}
+
+impl MirrorSync {
+    async fn sync(&self) -> Result<(), SyncError> {
+        self.sync_saved_tracks().await?;
+        self.sync_saved_albums().await?;
+        self.sync_artists().await?;
+        self.sync_playlists().await?;
+        self.mirror.flush()?;
+        Ok(())
+    }
+
+    async fn sync_saved_tracks(&self) -> Result<(), SyncError> {
+        let client = self.client.clone();
+
+        Self::sync_saved(
+            &self.mirror.tracks,
+            move |offset| {
+                let client = client.clone();
+                async move { client.read().await.get_my_tracks(offset, PAGE_SIZE).await }
+            },
+            |stored, item| stored.added_at == item.added_at,
+        )
+        .await
+    }
+
+    async fn sync_saved_albums(&self) -> Result<(), SyncError> {
+        let client = self.client.clone();
+
+        Self::sync_saved(
+            &self.mirror.albums,
+            move |offset| {
+                let client = client.clone();
+                async move { client.read().await.get_my_albums(offset, PAGE_SIZE).await }
+            },
+            |stored, item| stored.added_at == item.added_at,
+        )
+        .await
+    }
+
+    /// Saved items come newest first, so loading stops at the first item
+    /// already mirrored with the same `added_at`. If the number of mirrored
+    /// items doesn't match the total after that, some items were removed,
+    /// and the collection is fully reloaded.
+    async fn sync_saved<T, F, R>(collection: &Collection<T>, fetch_page: F, is_same: fn(&T, &T) -> bool) -> Result<(), SyncError>
+    where
+        T: StorageModel<Key = str>,
+        F: Fn(u32) -> R,
+        R: Future<Output = Result<Page<T>, ClientError>>,
+    {
+        let mut new_items = Vec::new();
+        let mut offset = 0;
+
+        let (total, complete) = loop {
+            let page = fetch_page(offset).await?;
+            let total = page.total;
+            let has_next = page.next.is_some();
+
+            for item in page.items {
+                match collection.get(item.key())? {
+                    Some(ref stored) if is_same(stored, &item) => {
+                        collection.put_all(new_items)?;
+
+                        if collection.len() == total as usize {
+                            return Ok(());
+                        }
+
+                        new_items = Vec::new();
+                        break;
+                    }
+                    _ => new_items.push(item),
+                }
+            }
+
+            if !has_next || new_items.is_empty() {
+                break (total, !has_next);
+            }
+
+            offset += PAGE_SIZE;
+        };
+
+        if complete && new_items.len() == total as usize {
+            collection.clear()?;
+            collection.put_all(new_items)?;
+            return Ok(());
+        }
+
+        // Something was removed, resync from scratch
+        let mut items = Vec::with_capacity(total as usize);
+        let mut offset = 0;
+
+        loop {
+            let page = fetch_page(offset).await?;
+            let has_next = page.next.is_some();
+
+            items.extend(page.items);
+
+            if !has_next {
+                break;
+            }
+
+            offset += PAGE_SIZE;
+        }
+
+        collection.clear()?;
+        collection.put_all(items)?;
+
+        Ok(())
+    }
+
+    /// Followed artists have no `added_at`, and the list is usually short,
+    /// so it's always reloaded
+    async fn sync_artists(&self) -> Result<(), SyncError> {
+        let mut artists = Vec::new();
+        let mut cursor = None;
+
+        loop {
+            let page = self.client.read().await.get_my_artists(cursor, PAGE_SIZE).await?;
+
+            artists.extend(page.items);
+
+            match page.cursors.after {
+                Some(after) => cursor = Some(after),
+                None => break,
+            }
+        }
+
+        self.mirror.artists.clear()?;
+        self.mirror.artists.put_all(artists)?;
+
+        Ok(())
+    }
+
+    /// Playlist tracks are reloaded only if playlist's `snapshot_id` has
+    /// changed
+    async fn sync_playlists(&self) -> Result<(), SyncError> {
+        let mut playlists = Vec::new();
+        let mut offset = 0;
+
+        loop {
+            let page = self.client.read().await.get_my_playlists(offset, PAGE_SIZE).await?;
+
+            playlists.extend(page.items);
+
+            if page.next.is_none() {
+                break;
+            }
+
+            offset += PAGE_SIZE;
+        }
+
+        for playlist in &playlists {
+            let is_fresh = self
+                .mirror
+                .playlist_tracks
+                .get(&playlist.id)?
+                .map_or(false, |stored| stored.snapshot_id == playlist.snapshot_id);
+
+            if is_fresh {
+                continue;
+            }
+
+            let mut items = Vec::new();
+            let mut offset = 0;
+
+            loop {
+                let page = self
+                    .client
+                    .read()
+                    .await
+                    .get_playlist_tracks(&playlist.uri, offset, PLAYLIST_PAGE_SIZE)
+                    .await?;
+
+                items.extend(page.items);
+
+                if page.next.is_none() {
+                    break;
+                }
+
+                offset += PLAYLIST_PAGE_SIZE;
+            }
+
+            self.mirror.playlist_tracks.put(MirroredPlaylist {
+                id: playlist.id.clone(),
+                snapshot_id: playlist.snapshot_id.clone(),
+                items,
+            })?;
+        }
+
+        let current = playlists.iter().map(|playlist| playlist.id.as_str()).collect::<HashSet<_>>();
+        let removed = self
+            .mirror
+            .playlist_tracks
+            .iter()
+            .filter_map(|stored| match stored {
+                Ok(stored) if current.contains(stored.id.as_str()) => None,
+                Ok(stored) => Some(Ok(stored.id)),
+                Err(error) => Some(Err(error)),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        self.mirror.playlist_tracks.delete_all(removed.iter().map(String::as_str))?;
+
+        self.mirror.playlists.clear()?;
+        self.mirror.playlists.put_all(playlists)?;
+
+        Ok(())
+    }
+}
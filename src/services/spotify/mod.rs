@@ -1,5 +1,15 @@
+mod auth;
+mod crossfade;
+mod library_sync;
+mod playback_state;
+mod queue_feeder;
+mod rate_limit;
 mod refresh_token;
 mod service;
 
+pub use crossfade::CrossfadeService;
+pub use library_sync::{LibrarySyncService, SyncError};
+pub use playback_state::PlaybackStateService;
+pub use queue_feeder::QueueFeederService;
 pub use refresh_token::RefreshTokenService;
 pub use service::{Spotify, SpotifyRef};
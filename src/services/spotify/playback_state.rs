@@ -0,0 +1,155 @@
+use crate::{
+    broadcast,
+    services::{api::PlaybackControlApi, spotify::SpotifyRef},
+    AppEvent,
+};
+use rspotify::model::{CurrentPlaybackContext, PlayingItem};
+use std::{sync::Arc, time::Duration};
+use tokio::{runtime::Runtime, task::JoinHandle};
+
+/// Longest timeout between checks while playing, to notice changes made on
+/// other devices, must be shorter than the queue feeding lead
+const PLAYING_TIMEOUT: Duration = Duration::from_secs(10);
+
+const PAUSED_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Timeout if nothing is played or the state can't be got
+const IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Current track is checked this long after it should end
+const TRACK_END_MARGIN: Duration = Duration::from_millis(500);
+
+/// Spotify needs a moment to apply a playback command
+const COMMAND_DELAY: Duration = Duration::from_millis(500);
+
+/// Commands like device transfer take a while, so the state is checked
+/// a few more times quickly after a command
+const FOLLOW_UP_TIMEOUT: Duration = Duration::from_secs(2);
+
+const FOLLOW_UP_CHECKS: u32 = 3;
+
+/// Parts of playback state each change of which is broadcast on its own
+#[derive(Default)]
+struct Summary {
+    item_uri: Option<String>,
+    is_playing: bool,
+    device_id: Option<String>,
+    volume: Option<u32>,
+}
+
+impl From<&Option<CurrentPlaybackContext>> for Summary {
+    fn from(state: &Option<CurrentPlaybackContext>) -> Self {
+        match state {
+            Some(state) => Summary {
+                item_uri: state.item.as_ref().map(|item| match item {
+                    PlayingItem::Track(track) => track.uri.clone(),
+                    PlayingItem::Episode(episode) => episode.uri.clone(),
+                }),
+                is_playing: state.is_playing,
+                device_id: state.device.id.clone(),
+                volume: state.device.volume_percent,
+            },
+            None => Summary::default(),
+        }
+    }
+}
+
+/// Polls playback state and broadcasts its changes, more often near the end
+/// of a track or after a playback command, rarely while paused
+pub struct PlaybackStateService {
+    client: SpotifyRef,
+}
+
+impl PlaybackStateService {
+    pub fn new(client: SpotifyRef) -> PlaybackStateService { PlaybackStateService { client } }
+
+    pub fn spawn(self, runtime: &Runtime) -> JoinHandle<!> { runtime.spawn(self.run()) }
+
+    pub async fn run(self) -> ! {
+        let playback_changed = self.client.read().await.playback_changed();
+        let mut last = Summary::default();
+        let mut follow_ups = 0;
+
+        loop {
+            let state = self.client.read().await.get_playback_state().await;
+
+            let mut timeout = match state {
+                Ok(state) => {
+                    let timeout = Self::timeout(&state);
+                    last = Self::publish(state, last);
+                    timeout
+                }
+                Err(error) => {
+                    warn!("failed to get playback state: {}", error);
+                    IDLE_TIMEOUT
+                }
+            };
+
+            if follow_ups > 0 {
+                follow_ups -= 1;
+                timeout = timeout.min(FOLLOW_UP_TIMEOUT);
+            }
+
+            tokio::select! {
+                _ = tokio::time::delay_for(timeout) => {}
+                _ = playback_changed.notified() => {
+                    follow_ups = FOLLOW_UP_CHECKS;
+                    tokio::time::delay_for(COMMAND_DELAY).await;
+                }
+            }
+        }
+    }
+
+    fn timeout(state: &Option<CurrentPlaybackContext>) -> Duration {
+        match state {
+            Some(CurrentPlaybackContext {
+                is_playing: true,
+                progress_ms: Some(progress),
+                item: Some(item),
+                ..
+            }) => {
+                let duration = match item {
+                    PlayingItem::Track(track) => track.duration_ms,
+                    PlayingItem::Episode(episode) => episode.duration_ms,
+                };
+                let remaining = Duration::from_millis(duration.saturating_sub(*progress) as u64);
+
+                (remaining + TRACK_END_MARGIN).min(PLAYING_TIMEOUT)
+            }
+            Some(CurrentPlaybackContext { is_playing: true, .. }) => PLAYING_TIMEOUT,
+            Some(_) => PAUSED_TIMEOUT,
+            None => IDLE_TIMEOUT,
+        }
+    }
+
+    /// Broadcasts the state and whatever changed since the last state
+    fn publish(state: Option<CurrentPlaybackContext>, last: Summary) -> Summary {
+        let summary = Summary::from(&state);
+
+        if summary.item_uri != last.item_uri {
+            let _ = broadcast(AppEvent::PlaybackTrackChanged(summary.item_uri.clone()));
+        }
+
+        if summary.is_playing != last.is_playing {
+            let _ = broadcast(if summary.is_playing {
+                AppEvent::PlaybackResumed
+            } else {
+                AppEvent::PlaybackPaused
+            });
+        }
+
+        if summary.device_id != last.device_id {
+            let _ = broadcast(AppEvent::PlaybackDeviceChanged(summary.device_id.clone()));
+        }
+
+        if summary.volume != last.volume {
+            if let Some(volume) = summary.volume {
+                let _ = broadcast(AppEvent::PlaybackVolumeChanged(volume));
+            }
+        }
+
+        let _ = broadcast(AppEvent::PlaybackStateUpdated(Arc::new(state)));
+
+        summary
+    }
+}
@@ -0,0 +1,73 @@
+use crate::{
+    services::{api::PlaybackQueueApi, spotify::SpotifyRef},
+    subscribe, AppEvent,
+};
+use futures::TryFutureExt;
+use rspotify::model::{CurrentPlaybackContext, PlayingItem};
+use std::time::Duration;
+use tokio::{runtime::Runtime, sync::broadcast::RecvError, task::JoinHandle};
+
+/// Next track is handed over to Spotify this long before current track ends,
+/// playback state is checked more often than that while playing
+const FEED_AHEAD: Duration = Duration::from_secs(15);
+
+/// Feeds tracks from app queue to Spotify player queue one by one,
+/// only when the current track is about to end, so that the app queue
+/// can be freely edited until the last moment. Playback state is taken from
+/// [`PlaybackStateService`](crate::services::spotify::PlaybackStateService)
+/// broadcasts.
+pub struct QueueFeederService {
+    client: SpotifyRef,
+}
+
+impl QueueFeederService {
+    pub fn new(client: SpotifyRef) -> QueueFeederService { QueueFeederService { client } }
+
+    pub fn spawn(self, runtime: &Runtime) -> JoinHandle<Result<!, RecvError>> {
+        runtime.spawn(self.run().inspect_err(|error| {
+            error!("queue feeder thread stopped: {:?}", error);
+        }))
+    }
+
+    pub async fn run(self) -> Result<!, RecvError> {
+        let mut events = subscribe();
+
+        loop {
+            match events.recv().await {
+                Ok(AppEvent::PlaybackStateUpdated(state)) => self.poll(&state).await,
+                Ok(_) => {}
+                Err(RecvError::Lagged(count)) => warn!("queue feeder skipped {} events", count),
+                Err(closed @ RecvError::Closed) => return Err(closed),
+            }
+        }
+    }
+
+    /// Feeds the next track if it's time, the queue itself makes sure only
+    /// one track is fed per playing track
+    async fn poll(&self, state: &Option<CurrentPlaybackContext>) {
+        let (uri, remaining) = match state {
+            Some(CurrentPlaybackContext {
+                is_playing: true,
+                progress_ms: Some(progress),
+                item: Some(PlayingItem::Track(track)),
+                ..
+            }) => (
+                &track.uri,
+                Duration::from_millis(track.duration_ms.saturating_sub(*progress) as u64),
+            ),
+            _ => return,
+        };
+
+        if remaining > FEED_AHEAD {
+            return;
+        }
+
+        match self.client.write().await.feed_queue_track(uri).await {
+            Ok(Some(next_uri)) => info!("fed queued track {} to player", next_uri),
+            // Queue is empty, but something can still be added before the track ends,
+            // or the next track is already fed
+            Ok(None) => {}
+            Err(error) => warn!("queue feeder failed to enqueue track: {}", error),
+        }
+    }
+}
@@ -0,0 +1,111 @@
+use crate::{broadcast, AppEvent};
+use futures::Future;
+use rspotify::client::{ClientError, ClientResult};
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::{
+    sync::{Notify, Semaphore},
+    time::{delay_until, Instant},
+};
+
+/// Max number of requests in flight at once
+const MAX_CONCURRENT_REQUESTS: usize = 8;
+
+/// Rate limited request is retried this many times before the error is returned
+const MAX_RETRIES: usize = 5;
+
+/// Timeout used if rate limited response has no `Retry-After` header,
+/// doubled on every retry
+const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(2);
+
+/// Gate all Spotify requests go through.
+///
+/// Once any request gets `429 Too Many Requests`, all the requests are held
+/// until `Retry-After` timeout passes, and the failed ones are sent again.
+/// Requests rejected with `401 Unauthorized` wake up token refresh.
+pub struct RateLimiter {
+    permits: Semaphore,
+    blocked_until: Mutex<Option<Instant>>,
+    unauthorized: Arc<Notify>,
+}
+
+impl Default for RateLimiter {
+    fn default() -> Self {
+        RateLimiter {
+            permits: Semaphore::new(MAX_CONCURRENT_REQUESTS),
+            blocked_until: Mutex::new(None),
+            unauthorized: Arc::new(Notify::new()),
+        }
+    }
+}
+
+impl RateLimiter {
+    /// Notified whenever a request is rejected because of expired access token
+    pub fn unauthorized(&self) -> Arc<Notify> { self.unauthorized.clone() }
+
+    pub async fn run<T, F, R>(&self, mut request: F) -> ClientResult<T>
+    where
+        F: FnMut() -> R,
+        R: Future<Output = ClientResult<T>>,
+    {
+        let mut retries = 0;
+
+        loop {
+            self.wait().await;
+
+            let result = {
+                let _permit = self.permits.acquire().await;
+                request().await
+            };
+
+            match result {
+                Err(ClientError::RateLimited(retry_after)) if retries < MAX_RETRIES => {
+                    let timeout = retry_after.map_or(DEFAULT_RETRY_AFTER * 2u32.pow(retries as u32), |secs| {
+                        Duration::from_secs(secs as u64)
+                    });
+
+                    if self.block(timeout) {
+                        warn!("rate limited, retrying in {:?}", timeout);
+                        let _ = broadcast(AppEvent::SpotifyRateLimited(timeout));
+                    }
+
+                    retries += 1;
+                }
+                Err(ClientError::Unauthorized) => {
+                    self.unauthorized.notify();
+                    break Err(ClientError::Unauthorized);
+                }
+                result => break result,
+            }
+        }
+    }
+
+    /// Waits until requests are allowed again
+    async fn wait(&self) {
+        loop {
+            let blocked_until = *self.blocked_until.lock().unwrap_or_else(|error| error.into_inner());
+
+            match blocked_until {
+                Some(until) if until > Instant::now() => delay_until(until).await,
+                _ => break,
+            }
+        }
+    }
+
+    /// Holds requests for the timeout, returns false if they are already held
+    /// longer
+    fn block(&self, timeout: Duration) -> bool {
+        let until = Instant::now() + timeout;
+        let mut blocked_until = self.blocked_until.lock().unwrap_or_else(|error| error.into_inner());
+
+        match *blocked_until {
+            Some(current) if current >= until => false,
+            _ => {
+                *blocked_until = Some(until);
+                true
+            }
+        }
+    }
+}
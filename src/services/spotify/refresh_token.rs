@@ -1,32 +1,86 @@
-use crate::services::spotify::SpotifyRef;
-use futures::TryFutureExt;
-use rspotify::client::ClientError;
+use crate::{broadcast, services::spotify::SpotifyRef, AppEvent};
+use chrono::Utc;
 use std::time::Duration;
 use tokio::{runtime::Runtime, task::JoinHandle};
 
+/// Access token is refreshed this long before it expires
+const REFRESH_AHEAD: Duration = Duration::from_secs(5 * 60);
+
+/// Timeout to check for a token again if user is not logged in yet
+const NO_TOKEN_TIMEOUT: Duration = Duration::from_secs(60);
+
+const MIN_RETRY_TIMEOUT: Duration = Duration::from_secs(5);
+
+const MAX_RETRY_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+
+/// Keeps access token fresh: refreshes it shortly before it expires,
+/// or right away if Spotify rejects it, and announces every refresh so that
+/// rejected requests can be retried
 pub struct RefreshTokenService {
     client: SpotifyRef,
 }
 
-const DEFAULT_REFRESH_TOKEN_TIMEOUT: Duration = Duration::from_secs(20 * 60);
-
 impl RefreshTokenService {
     pub fn new(client: SpotifyRef) -> RefreshTokenService { RefreshTokenService { client } }
 
-    pub fn spawn(self, runtime: &Runtime) -> JoinHandle<Result<!, ClientError>> {
-        runtime.spawn(self.run().inspect_err(|error| {
-            error!("refresh token thread stopped: {:?}", error);
-        }))
-    }
+    pub fn spawn(self, runtime: &Runtime) -> JoinHandle<!> { runtime.spawn(self.run()) }
 
-    pub async fn run(self) -> Result<!, ClientError> {
-        let mut timer = tokio::time::interval(DEFAULT_REFRESH_TOKEN_TIMEOUT);
+    pub async fn run(self) -> ! {
         let spotify = self.client;
+        let token_rejected = spotify.read().await.token_rejected();
+        let mut failures = 0u32;
 
         loop {
-            timer.tick().await;
+            let timeout = if failures > 0 {
+                (MIN_RETRY_TIMEOUT * 2u32.pow((failures - 1).min(8))).min(MAX_RETRY_TIMEOUT)
+            } else {
+                match spotify.read().await.token_expires_at() {
+                    Some(expires_at) => (expires_at - Utc::now())
+                        .to_std()
+                        .map_or(Duration::from_secs(0), |expires_in| {
+                            expires_in.checked_sub(REFRESH_AHEAD).unwrap_or_default()
+                        }),
+                    None => NO_TOKEN_TIMEOUT,
+                }
+            };
+
+            tokio::select! {
+                _ = tokio::time::delay_for(timeout) => {}
+                _ = token_rejected.notified() => {
+                    info!("access token rejected");
+                }
+            }
+
+            if spotify.read().await.token_expires_at().is_none() {
+                continue;
+            }
+
             info!("refresh access token");
-            spotify.write().await.refresh_user_token().await?;
+
+            // Requests go on while the token is refreshed, the client is
+            // locked for writing only to store it
+            let refresh = spotify.read().await.refresh_user_token();
+            let result = match refresh.await {
+                Ok(refreshed) => spotify.write().await.save_refreshed_token(refreshed).await,
+                Err(error) => Err(error),
+            };
+
+            match result {
+                Ok(()) => {
+                    failures = 0;
+                    let _ = broadcast(AppEvent::SpotifyTokenRefreshed);
+                }
+                Err(error) => {
+                    error!("failed to refresh access token: {}", error);
+
+                    // Report only the first failure in a row, not every retry
+                    if failures == 0 {
+                        let _ = broadcast(AppEvent::SpotifyAuthError(error.to_string()));
+                    }
+
+                    failures += 1;
+                }
+            }
         }
     }
 }
@@ -1,418 +1,763 @@
 use crate::{
+    config::Config,
+    models::{playlist::PlaylistDetails, set::DjSet, track::tracks_for_uris},
     scopes::Scope::{self, *},
-    services::api::*,
+    services::{
+        api::*,
+        cache::TracksCache,
+        mirror::LibraryMirror,
+        queue::PlaybackQueue,
+        sets::SetsStorage,
+        spotify::{
+            auth::{refresh_access_token, AuthRequest, TOKEN_URL},
+            rate_limit::RateLimiter,
+        },
+        store::StorageError,
+    },
     utils::AsyncCell,
 };
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use futures::{stream, Future, StreamExt, TryFutureExt, TryStreamExt};
+use itertools::Itertools;
 use rspotify::{
     client::{ClientError, ClientResult, Spotify as Client},
     model::{offset, *},
+    oauth2::Token,
 };
 use serde_json::{Map, Value};
-use std::{borrow::Cow, collections::VecDeque, ops::Deref, path::PathBuf};
+use std::{
+    borrow::Cow,
+    ops::Deref,
+    path::PathBuf,
+    sync::{Arc, Mutex, MutexGuard},
+};
+use tokio::sync::Notify;
 
 pub type SpotifyRef = AsyncCell<Spotify>;
 
+/// Max number of requests run at once when a call is split into many requests
+const PARALLEL_REQUESTS: usize = 4;
+
+/// Sends `uris` in requests of at most `limit` items each, a few requests at a
+/// time, and concatenates the results in the order of `uris`
+async fn chunked<'a, T, F, R>(uris: &'a [String], limit: usize, request: F) -> ClientResult<Vec<T>>
+where
+    F: FnMut(&'a [String]) -> R,
+    R: Future<Output = ClientResult<Vec<T>>>,
+{
+    stream::iter(uris.chunks(limit).map(request))
+        .buffered(PARALLEL_REQUESTS)
+        .try_concat()
+        .await
+}
+
+/// Same as [`chunked`] for requests with no results
+async fn chunked_each<'a, F, R>(uris: &'a [String], limit: usize, request: F) -> ClientResult<()>
+where
+    F: FnMut(&'a [String]) -> R,
+    R: Future<Output = ClientResult<()>>,
+{
+    stream::iter(uris.chunks(limit).map(request))
+        .buffer_unordered(PARALLEL_REQUESTS)
+        .try_collect()
+        .await
+}
+
+/// Id of the logged in user, kept so that follow checks don't ask for the
+/// user profile every time, cleared whenever the account can change
+#[derive(Default)]
+struct CurrentUserId(Mutex<Option<String>>);
+
+impl CurrentUserId {
+    async fn get_or_fetch<F, R>(&self, fetch: F) -> ClientResult<String>
+    where
+        F: FnOnce() -> R,
+        R: Future<Output = ClientResult<String>>,
+    {
+        if let Some(id) = self.lock().clone() {
+            return Ok(id);
+        }
+
+        let id = fetch().await?;
+        *self.lock() = Some(id.clone());
+
+        Ok(id)
+    }
+
+    fn clear(&self) { *self.lock() = None; }
+
+    fn lock(&self) -> MutexGuard<Option<String>> { self.0.lock().unwrap_or_else(|error| error.into_inner()) }
+}
+
+/// Access token got with [`Spotify::refresh_user_token`]
+pub struct RefreshedToken {
+    refreshed_from: String,
+    token: Token,
+}
+
+/// Permissions requested on authorization
+const SCOPES: &[Scope] = &[
+    UserFollowRead,
+    UserReadRecentlyPlayed,
+    UserReadPlaybackState,
+    UserReadPlaybackPosition,
+    UserTopRead,
+    UserLibraryRead,
+    UserModifyPlaybackState,
+    UserReadCurrentlyPlaying,
+    PlaylistReadPrivate,
+    PlaylistReadCollaborative,
+    UserLibraryModify,
+    PlaylistModifyPrivate,
+    PlaylistModifyPublic,
+    UserFollowModify,
+];
+
 pub struct Spotify {
     cache_path: PathBuf,
     client: Client,
-    queue: VecDeque<String>,
+    client_id: String,
+    /// Empty if only client ID is given, then PKCE flow is used
+    client_secret: String,
+    /// Endpoint tokens are refreshed at, and PKCE tokens are requested from
+    token_url: String,
+    auth_request: Option<AuthRequest>,
+    limiter: RateLimiter,
+    user_id: CurrentUserId,
+    playback_changed: Arc<Notify>,
+    queue: PlaybackQueue,
+    tracks_cache: Option<TracksCache>,
+    library_mirror: Option<Arc<LibraryMirror>>,
+    sets: Option<SetsStorage>,
 }
 
 #[async_trait]
 impl TracksStorageApi for Spotify {
-    async fn get_track(&self, uri: &str) -> ClientResult<FullTrack> { self.client.track(&uri).await }
+    async fn get_track(&self, uri: &str) -> ClientResult<FullTrack> {
+        if self.tracks_cache.is_some() {
+            if let Some(track) = self.get_tracks(&[uri.to_owned()]).await?.pop() {
+                return Ok(track);
+            }
+        }
+
+        self.limiter.run(|| self.client.track(&uri)).await
+    }
 
     async fn get_tracks(&self, uris: &[String]) -> ClientResult<Vec<FullTrack>> {
         if uris.is_empty() {
-            Ok(Vec::new())
-        } else {
-            self.client
-                .tracks(uris.iter().map(Deref::deref), None)
-                .await
-                .map(|FullTracks { tracks }| tracks)
+            return Ok(Vec::new());
+        }
+
+        let fetch = async move |uris: Vec<String>| {
+            chunked(&uris, 50, |chunk| {
+                self.limiter
+                    .run(move || self.client.tracks(chunk.iter().map(Deref::deref), None))
+                    .map_ok(|FullTracks { tracks }| tracks)
+            })
+            .await
+        };
+
+        match self.tracks_cache {
+            Some(ref cache) => cache.get_tracks(uris, fetch).await,
+            None => fetch(uris.to_vec()).await,
         }
     }
 
-    async fn get_track_analysis(&self, uri: &str) -> ClientResult<AudioAnalysis> { self.client.track_analysis(uri).await }
+    async fn get_track_analysis(&self, uri: &str) -> ClientResult<AudioAnalysis> {
+        self.limiter.run(|| self.client.track_analysis(uri)).await
+    }
 
     async fn get_tracks_features(&self, uris: &[String]) -> ClientResult<Vec<AudioFeatures>> {
         if uris.is_empty() {
-            Ok(Vec::new())
-        } else {
-            self.client
-                .tracks_features(uris.iter().map(Deref::deref))
-                .await
-                .map(|feats| feats.map_or_else(Vec::new, |AudioFeaturesPayload { audio_features }| audio_features))
+            return Ok(Vec::new());
+        }
+
+        let fetch = async move |uris: Vec<String>| {
+            chunked(&uris, 100, |chunk| {
+                self.limiter
+                    .run(move || self.client.tracks_features(chunk.iter().map(Deref::deref)))
+                    .map_ok(|feats| feats.map_or_else(Vec::new, |AudioFeaturesPayload { audio_features }| audio_features))
+            })
+            .await
+        };
+
+        match self.tracks_cache {
+            Some(ref cache) => cache.get_tracks_features(uris, fetch).await,
+            None => fetch(uris.to_vec()).await,
         }
     }
 
     async fn get_my_tracks(&self, offset: u32, limit: u32) -> ClientResult<Page<SavedTrack>> {
-        self.client.current_user_saved_tracks(limit, offset).await
+        self.limiter
+            .run(|| self.client.current_user_saved_tracks(limit, offset))
+            .await
     }
 
     async fn get_my_top_tracks(&self, offset: u32, limit: u32) -> ClientResult<Page<FullTrack>> {
-        self.client
-            .current_user_top_tracks(limit, offset, TimeRange::MediumTerm)
+        self.limiter
+            .run(|| self.client.current_user_top_tracks(limit, offset, TimeRange::MediumTerm))
             .await
     }
 
     async fn get_recent_tracks(&self, limit: u32) -> ClientResult<Vec<PlayHistory>> {
-        self.client.current_user_recently_played(limit).await.map(|page| page.items)
+        self.limiter
+            .run(|| self.client.current_user_recently_played(limit))
+            .await
+            .map(|page| page.items)
     }
 
     async fn get_playlist_tracks(&self, uri: &str, offset: u32, limit: u32) -> ClientResult<Page<PlaylistItem>> {
-        self.client.playlist_tracks(uri, None, limit, offset, None).await
+        self.limiter
+            .run(|| self.client.playlist_tracks(uri, None, limit, offset, None))
+            .await
     }
 
     async fn get_album_tracks(&self, uri: &str, offset: u32, limit: u32) -> ClientResult<Page<SimplifiedTrack>> {
-        self.client.album_track(uri, limit, offset).await
+        self.limiter.run(|| self.client.album_track(uri, limit, offset)).await
     }
 
     async fn get_artist_top_tracks(&self, uri: &str) -> ClientResult<Vec<FullTrack>> {
-        self.client
-            .artist_top_tracks(uri, None)
+        self.limiter
+            .run(|| self.client.artist_top_tracks(uri, None))
             .await
             .map(|FullTracks { tracks }| tracks)
     }
 
     async fn add_my_tracks(&self, uris: &[String]) -> ClientResult<()> {
-        if uris.is_empty() {
-            Ok(())
-        } else {
-            self.client.current_user_saved_tracks_add(uris.iter().map(Deref::deref)).await
-        }
+        chunked_each(uris, 50, |chunk| {
+            self.limiter
+                .run(move || self.client.current_user_saved_tracks_add(chunk.iter().map(Deref::deref)))
+        })
+        .await
     }
 
     async fn remove_my_tracks(&self, uris: &[String]) -> ClientResult<()> {
-        if uris.is_empty() {
-            Ok(())
-        } else {
-            self.client
-                .current_user_saved_tracks_delete(uris.iter().map(Deref::deref))
-                .await
-        }
+        chunked_each(uris, 50, |chunk| {
+            self.limiter
+                .run(move || self.client.current_user_saved_tracks_delete(chunk.iter().map(Deref::deref)))
+        })
+        .await
     }
 
     async fn are_my_tracks(&self, uris: &[String]) -> ClientResult<Vec<bool>> {
-        if uris.is_empty() {
-            Ok(Vec::new())
-        } else {
-            self.client
-                .current_user_saved_tracks_contains(uris.iter().map(Deref::deref))
-                .await
-        }
+        chunked(uris, 50, |chunk| {
+            self.limiter
+                .run(move || self.client.current_user_saved_tracks_contains(chunk.iter().map(Deref::deref)))
+        })
+        .await
     }
 }
 
 #[async_trait]
 impl AlbumsStorageApi for Spotify {
-    async fn get_album(&self, uri: &str) -> ClientResult<FullAlbum> { self.client.album(uri).await }
+    async fn get_album(&self, uri: &str) -> ClientResult<FullAlbum> { self.limiter.run(|| self.client.album(uri)).await }
 
     async fn get_albums(&self, uris: &[String]) -> ClientResult<Vec<FullAlbum>> {
-        if uris.is_empty() {
-            Ok(Vec::new())
-        } else {
-            self.client
-                .albums(uris.iter().map(Deref::deref))
-                .await
-                .map(|FullAlbums { albums }| albums)
-        }
+        chunked(uris, 20, |chunk| {
+            self.limiter
+                .run(move || self.client.albums(chunk.iter().map(Deref::deref)))
+                .map_ok(|FullAlbums { albums }| albums)
+        })
+        .await
     }
 
     async fn get_my_albums(&self, offset: u32, limit: u32) -> ClientResult<Page<SavedAlbum>> {
-        self.client.current_user_saved_albums(limit, offset).await
+        self.limiter
+            .run(|| self.client.current_user_saved_albums(limit, offset))
+            .await
     }
 
     async fn get_artist_albums(&self, uri: &str, offset: u32, limit: u32) -> ClientResult<Page<SimplifiedAlbum>> {
-        self.client.artist_albums(uri, None, None, Some(limit), Some(offset)).await
+        self.limiter
+            .run(|| self.client.artist_albums(uri, None, None, Some(limit), Some(offset)))
+            .await
     }
 
     async fn get_new_releases(&self, offset: u32, limit: u32) -> ClientResult<Page<SimplifiedAlbum>> {
-        self.client
-            .new_releases(None, limit, offset)
+        self.limiter
+            .run(|| self.client.new_releases(None, limit, offset))
             .await
             .map(|PageSimpliedAlbums { albums }| albums)
     }
 
     async fn add_my_albums(&self, uris: &[String]) -> ClientResult<()> {
-        if uris.is_empty() {
-            Ok(())
-        } else {
-            self.client.current_user_saved_albums_add(uris.iter().map(Deref::deref)).await
-        }
+        chunked_each(uris, 20, |chunk| {
+            self.limiter
+                .run(move || self.client.current_user_saved_albums_add(chunk.iter().map(Deref::deref)))
+        })
+        .await
     }
 
     async fn remove_my_albums(&self, uris: &[String]) -> ClientResult<()> {
-        if uris.is_empty() {
-            Ok(())
-        } else {
-            self.client
-                .current_user_saved_albums_delete(uris.iter().map(Deref::deref))
-                .await
-        }
+        chunked_each(uris, 20, |chunk| {
+            self.limiter
+                .run(move || self.client.current_user_saved_albums_delete(chunk.iter().map(Deref::deref)))
+        })
+        .await
     }
 
     async fn are_my_albums(&self, uris: &[String]) -> ClientResult<Vec<bool>> {
-        if uris.is_empty() {
-            Ok(Vec::new())
-        } else {
-            self.client
-                .current_user_saved_albums_contains(uris.iter().map(Deref::deref))
-                .await
-        }
+        chunked(uris, 20, |chunk| {
+            self.limiter
+                .run(move || self.client.current_user_saved_albums_contains(chunk.iter().map(Deref::deref)))
+        })
+        .await
     }
 }
 
 #[async_trait]
 impl ArtistsStorageApi for Spotify {
-    async fn get_artist(&self, uri: &str) -> ClientResult<FullArtist> { self.client.artist(uri).await }
+    async fn get_artist(&self, uri: &str) -> ClientResult<FullArtist> { self.limiter.run(|| self.client.artist(uri)).await }
 
     async fn get_artists(&self, uris: &[String]) -> ClientResult<Vec<FullArtist>> {
-        if uris.is_empty() {
-            Ok(Vec::new())
-        } else {
-            self.client
-                .artists(uris.iter().map(Deref::deref))
-                .await
-                .map(|FullArtists { artists }| artists)
-        }
+        chunked(uris, 50, |chunk| {
+            self.limiter
+                .run(move || self.client.artists(chunk.iter().map(Deref::deref)))
+                .map_ok(|FullArtists { artists }| artists)
+        })
+        .await
     }
 
     async fn get_my_artists(&self, cursor: Option<String>, limit: u32) -> ClientResult<CursorBasedPage<FullArtist>> {
-        self.client
-            .current_user_followed_artists(limit, cursor)
+        self.limiter
+            .run(|| self.client.current_user_followed_artists(limit, cursor.clone()))
             .await
             .map(|CursorPageFullArtists { artists }| artists)
     }
 
     async fn get_my_top_artists(&self, offset: u32, limit: u32) -> ClientResult<Page<FullArtist>> {
-        self.client
-            .current_user_top_artists(limit, offset, TimeRange::MediumTerm)
+        self.limiter
+            .run(|| self.client.current_user_top_artists(limit, offset, TimeRange::MediumTerm))
             .await
     }
 
     async fn get_artist_related_artists(&self, uri: &str) -> ClientResult<Vec<FullArtist>> {
-        self.client
-            .artist_related_artists(uri)
+        self.limiter
+            .run(|| self.client.artist_related_artists(uri))
             .await
             .map(|FullArtists { artists }| artists)
     }
 
     async fn add_my_artists(&self, uris: &[String]) -> ClientResult<()> {
-        self.client.user_follow_artists(uris.iter().map(Deref::deref)).await
+        chunked_each(uris, 50, |chunk| {
+            self.limiter
+                .run(move || self.client.user_follow_artists(chunk.iter().map(Deref::deref)))
+        })
+        .await
     }
 
     async fn remove_my_artists(&self, uris: &[String]) -> ClientResult<()> {
-        self.client.user_unfollow_artists(uris.iter().map(Deref::deref)).await
+        chunked_each(uris, 50, |chunk| {
+            self.limiter
+                .run(move || self.client.user_unfollow_artists(chunk.iter().map(Deref::deref)))
+        })
+        .await
     }
 
     async fn are_my_artists(&self, uris: &[String]) -> ClientResult<Vec<bool>> {
-        self.client.user_artist_check_follow(uris.iter().map(Deref::deref)).await
+        chunked(uris, 50, |chunk| {
+            self.limiter
+                .run(move || self.client.user_artist_check_follow(chunk.iter().map(Deref::deref)))
+        })
+        .await
     }
 }
 
 #[async_trait]
 impl PlaybackControlApi for Spotify {
-    async fn get_my_devices(&self) -> ClientResult<Vec<Device>> { self.client.device().await.map(|reply| reply.devices) }
+    async fn get_my_devices(&self) -> ClientResult<Vec<Device>> {
+        self.limiter.run(|| self.client.device()).await.map(|reply| reply.devices)
+    }
 
-    async fn use_device(&self, id: &str, play: bool) -> ClientResult<()> { self.client.transfer_playback(id, play).await }
+    async fn use_device(&self, id: &str, play: bool) -> ClientResult<()> {
+        self.control(|| self.client.transfer_playback(id, play)).await
+    }
 
     async fn play_tracks(&self, uris: &[String]) -> ClientResult<()> {
         if uris.is_empty() {
             return Ok(());
         }
 
-        self.client.start_playback(None, None, Some(uris.to_vec()), None, None).await
+        self.control(|| self.client.start_playback(None, None, Some(uris.to_vec()), None, None))
+            .await
     }
 
     async fn play_context(&self, uri: String, start_uri: Option<String>) -> ClientResult<()> {
         if uri.starts_with("spotify:artist:") {
-            self.client
-                .start_playback(None, Some(uri), start_uri.map(|uri| vec![uri]), None, None)
-                .await
+            self.control(|| {
+                self.client
+                    .start_playback(None, Some(uri.clone()), start_uri.clone().map(|uri| vec![uri]), None, None)
+            })
+            .await
         } else {
-            self.client
-                .start_playback(None, Some(uri), None, start_uri.and_then(offset::for_uri), None)
-                .await
+            self.control(|| {
+                self.client.start_playback(
+                    None,
+                    Some(uri.clone()),
+                    None,
+                    start_uri.clone().and_then(offset::for_uri),
+                    None,
+                )
+            })
+            .await
         }
     }
 
     async fn get_playback_state(&self) -> ClientResult<Option<CurrentPlaybackContext>> {
-        self.client
-            .current_playback(None, Some(vec![AdditionalType::Track, AdditionalType::Episode]))
+        self.limiter
+            .run(|| {
+                self.client
+                    .current_playback(None, Some(vec![AdditionalType::Track, AdditionalType::Episode]))
+            })
             .await
     }
 
-    async fn start_playback(&self) -> ClientResult<()> { self.client.start_playback(None, None, None, None, None).await }
+    async fn start_playback(&self) -> ClientResult<()> {
+        self.control(|| self.client.start_playback(None, None, None, None, None))
+            .await
+    }
 
-    async fn pause_playback(&self) -> ClientResult<()> { self.client.pause_playback(None).await }
+    async fn pause_playback(&self) -> ClientResult<()> { self.control(|| self.client.pause_playback(None)).await }
 
-    async fn play_next_track(&self) -> ClientResult<()> { self.client.next_track(None).await }
+    async fn play_next_track(&self) -> ClientResult<()> { self.control(|| self.client.next_track(None)).await }
 
-    async fn play_prev_track(&self) -> ClientResult<()> { self.client.previous_track(None).await }
+    async fn play_prev_track(&self) -> ClientResult<()> { self.control(|| self.client.previous_track(None)).await }
 
-    async fn seek_track(&self, pos: u32) -> ClientResult<()> { self.client.seek_track(pos, None).await }
+    async fn seek_track(&self, pos: u32) -> ClientResult<()> { self.control(|| self.client.seek_track(pos, None)).await }
 
-    async fn set_volume(&self, value: u8) -> ClientResult<()> { self.client.volume(value, None).await }
+    async fn set_volume(&self, value: u8) -> ClientResult<()> { self.control(|| self.client.volume(value, None)).await }
 
-    async fn set_shuffle(&self, value: bool) -> ClientResult<()> { self.client.shuffle(value, None).await }
+    async fn set_shuffle(&self, value: bool) -> ClientResult<()> { self.control(|| self.client.shuffle(value, None)).await }
 
-    async fn set_repeat_mode(&self, mode: RepeatState) -> ClientResult<()> { self.client.repeat(mode, None).await }
+    async fn set_repeat_mode(&self, mode: RepeatState) -> ClientResult<()> {
+        self.control(|| self.client.repeat(mode, None)).await
+    }
 }
 
 #[async_trait]
 impl ShowsStorageApi for Spotify {
-    async fn get_show(&self, uri: &str) -> ClientResult<FullShow> { self.client.get_a_show(uri.to_owned(), None).await }
+    async fn get_show(&self, uri: &str) -> ClientResult<FullShow> {
+        self.limiter.run(|| self.client.get_a_show(uri.to_owned(), None)).await
+    }
 
     async fn get_shows(&self, uris: &[String]) -> ClientResult<Vec<SimplifiedShow>> {
-        if uris.is_empty() {
-            Ok(Vec::new())
-        } else {
-            self.client
-                .get_several_shows(uris.iter().map(Deref::deref), None)
-                .await
-                .map(|SeversalSimplifiedShows { shows }| shows)
-        }
+        chunked(uris, 50, |chunk| {
+            self.limiter
+                .run(move || self.client.get_several_shows(chunk.iter().map(Deref::deref), None))
+                .map_ok(|SeversalSimplifiedShows { shows }| shows)
+        })
+        .await
     }
 
     async fn get_my_shows(&self, offset: u32, limit: u32) -> ClientResult<Page<Show>> {
-        self.client.get_saved_show(limit, offset).await
+        self.limiter.run(|| self.client.get_saved_show(limit, offset)).await
     }
 
     async fn add_my_shows(&self, uris: &[String]) -> ClientResult<()> {
-        if uris.is_empty() {
-            Ok(())
-        } else {
-            self.client.save_shows(uris.iter().map(Deref::deref)).await
-        }
+        chunked_each(uris, 50, |chunk| {
+            self.limiter
+                .run(move || self.client.save_shows(chunk.iter().map(Deref::deref)))
+        })
+        .await
     }
 
     async fn remove_my_shows(&self, uris: &[String]) -> ClientResult<()> {
-        if uris.is_empty() {
-            Ok(())
-        } else {
-            self.client
-                .remove_users_saved_shows(uris.iter().map(Deref::deref), None)
-                .await
-        }
+        chunked_each(uris, 50, |chunk| {
+            self.limiter
+                .run(move || self.client.remove_users_saved_shows(chunk.iter().map(Deref::deref), None))
+        })
+        .await
     }
 
     async fn are_my_shows(&self, uris: &[String]) -> ClientResult<Vec<bool>> {
-        if uris.is_empty() {
-            Ok(Vec::new())
-        } else {
-            self.client.check_users_saved_shows(uris.iter().map(Deref::deref)).await
-        }
+        chunked(uris, 50, |chunk| {
+            self.limiter
+                .run(move || self.client.check_users_saved_shows(chunk.iter().map(Deref::deref)))
+        })
+        .await
     }
 }
 
 #[async_trait]
 impl PlaylistsStorageApi for Spotify {
-    async fn get_playlist(&self, uri: &str) -> ClientResult<FullPlaylist> { self.client.playlist(uri, None, None).await }
+    async fn get_playlist(&self, uri: &str) -> ClientResult<FullPlaylist> {
+        self.limiter.run(|| self.client.playlist(uri, None, None)).await
+    }
 
     async fn get_playlists(&self, uris: &[String]) -> ClientResult<Vec<FullPlaylist>> {
-        if uris.is_empty() {
-            Ok(Vec::new())
-        } else {
-            futures::future::try_join_all(uris.iter().map(|uri| self.client.playlist(&uri, None, None))).await
-        }
+        stream::iter(
+            uris.iter()
+                .map(|uri| self.limiter.run(move || self.client.playlist(&uri, None, None))),
+        )
+        .buffered(PARALLEL_REQUESTS)
+        .try_collect()
+        .await
     }
 
     async fn get_my_playlists(&self, offset: u32, limit: u32) -> ClientResult<Page<SimplifiedPlaylist>> {
-        self.client.current_user_playlists(limit, offset).await
+        self.limiter.run(|| self.client.current_user_playlists(limit, offset)).await
     }
 
     async fn get_user_playlists(&self, uri: &str, offset: u32, limit: u32) -> ClientResult<Page<SimplifiedPlaylist>> {
         let user_id = Id::from_id_or_uri(Type::User, uri)?;
-        self.client.user_playlists(user_id.id(), limit, offset).await
+        self.limiter
+            .run(|| self.client.user_playlists(user_id.id(), limit, offset))
+            .await
     }
 
     async fn get_featured_playlists(&self, offset: u32, limit: u32) -> ClientResult<Page<SimplifiedPlaylist>> {
-        self.client
-            .featured_playlists(None, None, None, limit, offset)
+        self.limiter
+            .run(|| self.client.featured_playlists(None, None, None, limit, offset))
             .await
             .map(|FeaturedPlaylists { playlists, .. }| playlists)
     }
 
     async fn get_categories(&self, offset: u32, limit: u32) -> ClientResult<Page<Category>> {
-        self.client
-            .categories(None, None, limit, offset)
+        self.limiter
+            .run(|| self.client.categories(None, None, limit, offset))
             .await
             .map(|PageCategory { categories }| categories)
     }
 
     async fn get_category_playlists(&self, category_id: &str, offset: u32, limit: u32) -> ClientResult<Page<SimplifiedPlaylist>> {
-        self.client.category_playlists(category_id, None, limit, offset).await
+        self.limiter
+            .run(|| self.client.category_playlists(category_id, None, limit, offset))
+            .await
     }
 
     async fn add_my_playlists(&self, uris: &[String], public: bool) -> ClientResult<()> {
-        futures::future::try_join_all(uris.iter().map(|uri| self.client.playlist_follow(&uri, public)))
-            .await
-            .map(|_| ())
+        stream::iter(
+            uris.iter()
+                .map(|uri| self.limiter.run(move || self.client.playlist_follow(&uri, public))),
+        )
+        .buffer_unordered(PARALLEL_REQUESTS)
+        .try_collect()
+        .await
     }
 
     async fn remove_my_playlists(&self, uris: &[String]) -> ClientResult<()> {
-        futures::future::try_join_all(uris.iter().map(|uri| self.client.playlist_unfollow(&uri)))
-            .await
-            .map(|_| ())
+        stream::iter(
+            uris.iter()
+                .map(|uri| self.limiter.run(move || self.client.playlist_unfollow(&uri))),
+        )
+        .buffer_unordered(PARALLEL_REQUESTS)
+        .try_collect()
+        .await
     }
 
     async fn are_my_playlists(&self, uris: &[String]) -> ClientResult<Vec<bool>> {
-        // TODO: dummy implementation
-        Ok(std::iter::repeat(true).take(uris.len()).collect())
+        if uris.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let user_ids = &[self.my_user_id().await?];
+
+        // Follow check endpoint takes a single playlist at a time
+        stream::iter(
+            uris.iter()
+                .map(|uri| self.limiter.run(move || self.client.playlist_check_follow(uri, user_ids))),
+        )
+        .buffered(PARALLEL_REQUESTS)
+        .map_ok(|follows| follows.first().copied().unwrap_or(false))
+        .try_collect()
+        .await
+    }
+
+    async fn create_my_playlist(&self, details: &PlaylistDetails) -> ClientResult<FullPlaylist> {
+        let user_id = self.my_user_id().await?;
+        let description = Some(details.description.clone()).filter(|description| !description.is_empty());
+
+        // Collaborative playlists can't be public
+        let mut playlist = self
+            .limiter
+            .run(|| {
+                self.client.user_playlist_create(
+                    &user_id,
+                    &details.name,
+                    details.public && !details.collaborative,
+                    description.clone(),
+                )
+            })
+            .await?;
+
+        // Playlists can be made collaborative only after creation
+        if details.collaborative {
+            self.limiter
+                .run(|| {
+                    self.client
+                        .playlist_change_detail(&playlist.uri, None, None, None, Some(true))
+                })
+                .await?;
+            playlist.collaborative = true;
+        }
+
+        Ok(playlist)
+    }
+
+    async fn update_playlist_details(&self, uri: &str, details: &PlaylistDetails) -> ClientResult<()> {
+        self.limiter
+            .run(|| {
+                self.client.playlist_change_detail(
+                    uri,
+                    Some(&details.name),
+                    Some(details.public && !details.collaborative),
+                    Some(details.description.clone()),
+                    Some(details.collaborative),
+                )
+            })
+            .await?;
+
+        Ok(())
+    }
+
+    async fn add_playlist_tracks(&self, uri: &str, uris: &[String], position: Option<u32>) -> ClientResult<String> {
+        let mut snapshot_id = String::new();
+
+        // Spotify accepts at most 100 tracks per request
+        for (index, chunk) in uris.chunks(100).enumerate() {
+            let position = position.map(|position| (position as usize + index * 100) as i32);
+
+            snapshot_id = self
+                .limiter
+                .run(|| self.client.playlist_add_tracks(uri, chunk.iter().map(Deref::deref), position))
+                .await?
+                .snapshot_id;
+        }
+
+        Ok(snapshot_id)
+    }
+
+    async fn remove_playlist_tracks(
+        &self,
+        uri: &str,
+        tracks: &[(String, u32)],
+        snapshot_id: Option<&str>,
+    ) -> ClientResult<String> {
+        let mut snapshot_id = snapshot_id.map(String::from);
+
+        // Every next chunk is removed from the snapshot left by the previous one,
+        // so the last positions go first to keep positions of the rest valid
+        let tracks = tracks.iter().sorted_by(|a, b| b.1.cmp(&a.1)).collect::<Vec<_>>();
+
+        for chunk in tracks.chunks(100) {
+            let positions = chunk
+                .iter()
+                .map(|(uri, position)| (uri, *position))
+                .into_group_map()
+                .into_iter()
+                .map(|(uri, positions)| {
+                    let mut track = Map::new();
+                    track.insert("uri".to_owned(), Value::from(uri.clone()));
+                    track.insert("positions".to_owned(), Value::from(positions));
+                    track
+                })
+                .collect::<Vec<_>>();
+
+            snapshot_id = Some(
+                self.limiter
+                    .run(|| {
+                        self.client
+                            .playlist_remove_specific_occurrences_of_tracks(uri, positions.clone(), snapshot_id.clone())
+                    })
+                    .await?
+                    .snapshot_id,
+            );
+        }
+
+        Ok(snapshot_id.unwrap_or_default())
+    }
+
+    async fn reorder_playlist_tracks(
+        &self,
+        uri: &str,
+        range_start: u32,
+        range_length: u32,
+        insert_before: u32,
+        snapshot_id: Option<&str>,
+    ) -> ClientResult<String> {
+        self.limiter
+            .run(|| {
+                self.client.playlist_reorder_tracks(
+                    uri,
+                    range_start as i32,
+                    Some(range_length as i32),
+                    insert_before as i32,
+                    snapshot_id.map(String::from),
+                )
+            })
+            .await
+            .map(|result| result.snapshot_id)
+    }
+
+    async fn replace_playlist_tracks(&self, uri: &str, uris: &[String]) -> ClientResult<()> {
+        // Replace request takes at most 100 tracks as well, the rest is appended
+        let (head, tail) = uris.split_at(uris.len().min(100));
+
+        self.limiter
+            .run(|| self.client.playlist_replace_tracks(uri, head.iter().map(Deref::deref)))
+            .await?;
+        self.add_playlist_tracks(uri, tail, None).await?;
+
+        Ok(())
     }
 }
 
 #[async_trait]
 impl EpisodesStorageApi for Spotify {
-    async fn get_episode(&self, uri: &str) -> ClientResult<FullEpisode> { self.client.get_an_episode(uri.to_owned(), None).await }
+    async fn get_episode(&self, uri: &str) -> ClientResult<FullEpisode> {
+        self.limiter.run(|| self.client.get_an_episode(uri.to_owned(), None)).await
+    }
 
     async fn get_episodes(&self, uris: &[String]) -> ClientResult<Vec<FullEpisode>> {
-        if uris.is_empty() {
-            Ok(Vec::new())
-        } else {
-            self.client
-                .get_several_episodes(uris.iter().map(Deref::deref), None)
-                .await
-                .map(|SeveralEpisodes { episodes }| episodes)
-        }
+        chunked(uris, 50, |chunk| {
+            self.limiter
+                .run(move || self.client.get_several_episodes(chunk.iter().map(Deref::deref), None))
+                .map_ok(|SeveralEpisodes { episodes }| episodes)
+        })
+        .await
     }
 
     async fn get_show_episodes(&self, uri: &str, offset: u32, limit: u32) -> ClientResult<Page<SimplifiedEpisode>> {
-        self.client
-            .get_shows_episodes(Id::from_id_or_uri(Type::Show, uri)?.id().to_owned(), limit, offset, None)
+        let show_id = Id::from_id_or_uri(Type::Show, uri)?;
+
+        self.limiter
+            .run(|| self.client.get_shows_episodes(show_id.id().to_owned(), limit, offset, None))
             .await
     }
 }
 
 #[async_trait]
 impl UsersStorageApi for Spotify {
-    async fn get_my_profile(&self) -> ClientResult<PrivateUser> { self.client.me().await }
+    async fn get_my_profile(&self) -> ClientResult<PrivateUser> { self.limiter.run(|| self.client.me()).await }
 
     async fn get_user_profile(&self, uri: &str) -> ClientResult<PublicUser> {
-        self.client.user(Id::from_id_or_uri(Type::User, uri)?.id()).await
+        let user_id = Id::from_id_or_uri(Type::User, uri)?;
+        self.limiter.run(|| self.client.user(user_id.id())).await
     }
 
     async fn add_my_users(&self, uris: &[String]) -> ClientResult<()> {
-        if uris.is_empty() {
-            Ok(())
-        } else {
-            self.client.user_follow_users(uris.iter().map(Deref::deref)).await
-        }
+        chunked_each(uris, 50, |chunk| {
+            self.limiter
+                .run(move || self.client.user_follow_users(chunk.iter().map(Deref::deref)))
+        })
+        .await
     }
 
     async fn remove_my_users(&self, uris: &[String]) -> ClientResult<()> {
-        if uris.is_empty() {
-            Ok(())
-        } else {
-            self.client.user_unfollow_users(uris.iter().map(Deref::deref)).await
-        }
+        chunked_each(uris, 50, |chunk| {
+            self.limiter
+                .run(move || self.client.user_unfollow_users(chunk.iter().map(Deref::deref)))
+        })
+        .await
     }
 
     async fn are_my_users(&self, uris: &[String]) -> ClientResult<Vec<bool>> {
-        // TODO: dummy implementation
-        Ok(std::iter::repeat(false).take(uris.len()).collect())
+        chunked(uris, 50, |chunk| {
+            self.limiter
+                .run(move || self.client.user_check_follow_users(chunk.iter().map(Deref::deref)))
+        })
+        .await
     }
 }
 
@@ -426,28 +771,56 @@ impl SearchApi for Spotify {
         tunables: Map<String, Value>,
         limit: u32,
     ) -> ClientResult<Vec<SimplifiedTrack>> {
-        self.client
-            .recommendations(seed_artists, seed_genres, seed_tracks, limit, None, &tunables)
+        self.limiter
+            .run(|| {
+                self.client.recommendations(
+                    seed_artists.clone(),
+                    seed_genres.clone(),
+                    seed_tracks.clone(),
+                    limit,
+                    None,
+                    &tunables,
+                )
+            })
             .await
             .map(|recommended| recommended.tracks)
     }
+
+    async fn search(&self, query: &str, kind: SearchType, offset: u32, limit: u32) -> ClientResult<SearchResult> {
+        self.limiter
+            .run(|| self.client.search(query, kind, limit, offset, None, None))
+            .await
+    }
 }
 
 #[async_trait]
 impl PlaybackQueueApi for Spotify {
-    async fn dequeue_tracks(&mut self, uris: &[String]) -> ClientResult<()> {
-        self.queue.retain(|uri| !uris.contains(uri));
-
+    async fn dequeue_tracks(&mut self, positions: &[usize]) -> ClientResult<()> {
+        log_queue_error(self.queue.remove(positions));
         Ok(())
     }
 
     async fn enqueue_tracks(&mut self, uris: &[String]) -> ClientResult<()> {
-        if uris.is_empty() {
-            return Ok(());
-        }
+        log_queue_error(self.queue.push(uris));
+        Ok(())
+    }
+
+    async fn insert_queue_tracks(&mut self, position: usize, uris: &[String]) -> ClientResult<()> {
+        log_queue_error(self.queue.insert(position, uris));
+        Ok(())
+    }
 
-        futures::future::try_join_all(uris.iter().cloned().map(|uri| self.client.add_item_to_queue(uri, None))).await?;
-        self.queue.extend(uris.into_iter().cloned());
+    async fn move_queue_track(&mut self, from: usize, to: usize) -> ClientResult<()> {
+        log_queue_error(self.queue.move_track(from, to));
+        Ok(())
+    }
+
+    async fn reorder_queue_tracks(&mut self, uris: &[String]) -> ClientResult<()> {
+        match self.queue.reorder(uris) {
+            Ok(true) => {}
+            Ok(false) => warn!("queue reorder ignored: tracks don't match the queue"),
+            Err(error) => log_queue_error(Err(error)),
+        }
 
         Ok(())
     }
@@ -457,47 +830,173 @@ impl PlaybackQueueApi for Spotify {
             return Ok(Vec::new());
         }
 
-        let uris = self.queue.iter().map(|uri| uri.as_str()).collect::<Vec<_>>();
+        // Tracks are edited by position, so unknown ones still need their rows
+        let uris = self.queue.uris().iter().cloned().collect::<Vec<_>>();
+        Ok(tracks_for_uris(&uris, self.get_tracks(&uris).await?))
+    }
+
+    async fn feed_queue_track(&mut self, playing_uri: &str) -> ClientResult<Option<String>> {
+        let uri = match self.queue.pop_during(playing_uri) {
+            Ok(Some(uri)) => uri,
+            Ok(None) => return Ok(None),
+            Err(error) => {
+                log_queue_error(Err(error));
+                return Ok(None);
+            }
+        };
 
-        self.client.tracks(uris, None).await.map(|FullTracks { tracks }| tracks)
+        if let Err(error) = self.limiter.run(|| self.client.add_item_to_queue(uri.clone(), None)).await {
+            log_queue_error(self.queue.unpop(uri));
+            return Err(error);
+        }
+
+        Ok(Some(uri))
+    }
+}
+
+/// Queue is always updated in memory, so storage failures
+/// only mean the change won't survive restart
+fn log_queue_error(result: Result<(), StorageError>) {
+    if let Err(error) = result {
+        warn!("queue storage error: {}", error);
+    }
+}
+
+impl SetsStorageApi for Spotify {
+    fn get_set_names(&self) -> Result<Vec<String>, StorageError> { self.sets_storage()?.names() }
+
+    fn get_set(&self, name: &str) -> Result<Option<DjSet>, StorageError> { self.sets_storage()?.get(name) }
+
+    fn save_set(&self, set: DjSet) -> Result<(), StorageError> { self.sets_storage()?.save(set) }
+
+    fn delete_set(&self, name: &str) -> Result<(), StorageError> { self.sets_storage()?.delete(name) }
+}
+
+impl LibraryMirrorApi for Spotify {
+    fn mirrored_my_tracks(&self, offset: u32) -> Option<Page<SavedTrack>> {
+        self.read_mirror(|mirror| mirror.saved_tracks(offset).map(Some))
+    }
+
+    fn mirrored_my_albums(&self, offset: u32) -> Option<Page<SavedAlbum>> {
+        self.read_mirror(|mirror| mirror.saved_albums(offset).map(Some))
+    }
+
+    fn mirrored_my_artists(&self) -> Option<CursorBasedPage<FullArtist>> {
+        self.read_mirror(|mirror| mirror.followed_artists().map(Some))
+    }
+
+    fn mirrored_my_playlists(&self, offset: u32) -> Option<Page<SimplifiedPlaylist>> {
+        self.read_mirror(|mirror| mirror.saved_playlists(offset).map(Some))
+    }
+
+    fn mirrored_playlist_tracks(&self, uri: &str, offset: u32) -> Option<Page<PlaylistItem>> {
+        self.read_mirror(|mirror| mirror.playlist_tracks(uri, offset))
     }
 }
 
 impl Spotify {
     pub fn new(id: String, secret: String, cache_path: PathBuf) -> Self {
         Spotify {
-            client: Self::create_client(id, secret, cache_path.clone()),
-            queue: VecDeque::new(),
+            client: Self::create_client(id.clone(), secret.clone(), cache_path.clone()),
+            client_secret: secret,
+            client_id: id,
+            token_url: TOKEN_URL.to_owned(),
+            auth_request: None,
+            limiter: RateLimiter::default(),
+            user_id: CurrentUserId::default(),
+            playback_changed: Arc::new(Notify::new()),
+            queue: PlaybackQueue::default(),
+            tracks_cache: None,
+            library_mirror: None,
+            sets: None,
             cache_path,
         }
     }
 
+    pub fn set_tracks_cache(&mut self, cache: TracksCache) { self.tracks_cache = Some(cache); }
+
+    pub fn set_playback_queue(&mut self, queue: PlaybackQueue) { self.queue = queue; }
+
+    pub fn set_sets_storage(&mut self, sets: SetsStorage) { self.sets = Some(sets); }
+
+    fn sets_storage(&self) -> Result<&SetsStorage, StorageError> { self.sets.as_ref().ok_or(StorageError::Unavailable) }
+
+    pub fn set_library_mirror(&mut self, mirror: Arc<LibraryMirror>) { self.library_mirror = Some(mirror); }
+
+    pub fn library_mirror(&self) -> Option<Arc<LibraryMirror>> { self.library_mirror.clone() }
+
+    /// Opens playback queue, sets and library mirror of the account, the ones
+    /// of the previous account are closed first, storages failed to open are
+    /// left unavailable
+    pub fn open_account_storage(&mut self, config: &Config, account: &str) {
+        self.queue = PlaybackQueue::default();
+        self.sets = None;
+        self.library_mirror = None;
+
+        match PlaybackQueue::open(config.playback_queue_dir(account)) {
+            Ok(queue) => self.set_playback_queue(queue),
+            Err(error) => error!("failed to open playback queue: {}", error),
+        }
+
+        match SetsStorage::open(config.sets_dir(account)) {
+            Ok(sets) => self.set_sets_storage(sets),
+            Err(error) => error!("failed to open sets storage: {}", error),
+        }
+
+        match LibraryMirror::open(config.library_mirror_dir(account)) {
+            Ok(mirror) => self.set_library_mirror(Arc::new(mirror)),
+            Err(error) => error!("failed to open library mirror: {}", error),
+        }
+    }
+
+    fn read_mirror<T>(&self, read: impl FnOnce(&LibraryMirror) -> Result<Option<T>, StorageError>) -> Option<T> {
+        let mirror = self.library_mirror.as_ref()?;
+
+        read(mirror).unwrap_or_else(|error| {
+            warn!("library mirror read error: {}", error);
+            None
+        })
+    }
+
+    pub fn clear_tracks_cache(&self) -> Result<(), StorageError> {
+        match self.tracks_cache {
+            Some(ref cache) => cache.clear(),
+            None => Ok(()),
+        }
+    }
+
     pub async fn load_token_from_cache(&mut self) { self.client.token = self.client.read_token_cache().await; }
 
+    pub fn has_token(&self) -> bool { self.client.token.is_some() }
+
     pub fn setup_client(&mut self, id: String, secret: String) -> ClientResult<String> {
-        self.client = Self::create_client(id, secret, self.cache_path.clone());
+        let redirect_uri = self.redirect_uri();
+
+        self.client = Self::create_client(id.clone(), secret.clone(), self.cache_path.clone());
+        self.client_secret = secret;
+        self.user_id.clear();
+        // Login attempt started with the old client can't be finished
+        self.auth_request = None;
+        self.client_id = id;
+        self.set_redirect_uri(redirect_uri);
+
+        self.get_authorize_url()
+    }
+
+    /// Rebuilds the client for another account, its token is loaded from its
+    /// own cache file
+    pub async fn switch_account(&mut self, id: String, secret: String, cache_path: PathBuf) -> ClientResult<String> {
+        self.cache_path = cache_path;
+
+        let auth_url = self.setup_client(id, secret)?;
+        self.load_token_from_cache().await;
 
-        self.client.get_authorize_url(false)
+        Ok(auth_url)
     }
 
     fn create_client(id: String, secret: String, cache_path: PathBuf) -> rspotify::client::Spotify {
         let oauth: rspotify::oauth2::OAuth = rspotify::oauth2::OAuthBuilder::default()
-            .scope(Scope::stringify(&[
-                UserFollowRead,
-                UserReadRecentlyPlayed,
-                UserReadPlaybackState,
-                UserReadPlaybackPosition,
-                UserTopRead,
-                UserLibraryRead,
-                UserModifyPlaybackState,
-                UserReadCurrentlyPlaying,
-                PlaylistReadPrivate,
-                PlaylistReadCollaborative,
-                UserLibraryModify,
-                PlaylistModifyPrivate,
-                PlaylistModifyPublic,
-                UserFollowModify,
-            ]))
+            .scope(Scope::stringify(SCOPES))
             .redirect_uri("http://localhost:8888/callback")
             .build()
             .unwrap();
@@ -517,32 +1016,221 @@ impl Spotify {
     }
 
     pub async fn authorize_user(&mut self, code: String) -> ClientResult<()> {
-        if code.starts_with("http") {
-            if let Some(code) = self.client.parse_response_code(&code) {
-                self.client.request_user_token(&code).await
-            } else {
-                Err(ClientError::InvalidAuth("Invalid code URL".into()))
-            }
+        let code = if code.starts_with("http") {
+            self.client
+                .parse_response_code(&code)
+                .ok_or_else(|| ClientError::InvalidAuth("Invalid code URL".into()))?
         } else {
-            self.client.request_user_token(&code).await
+            code
+        };
+
+        // Another user could log in
+        self.user_id.clear();
+
+        match self.auth_request.take() {
+            Some(request) if request.is_pkce() => {
+                let token = request
+                    .request_token(&self.token_url, &self.client_id, &self.redirect_uri(), &code)
+                    .await?;
+                self.save_token(token).await
+            }
+            _ => self.client.request_user_token(&code).await,
         }
     }
 
-    pub fn get_authorize_url(&self) -> ClientResult<String> { self.client.get_authorize_url(false) }
+    /// Authorization URL of the login attempt in progress, a new attempt is
+    /// started only if there is none, so that the URL opened before keeps
+    /// working until the login succeeds or fails
+    pub fn get_authorize_url(&mut self) -> ClientResult<String> {
+        let (pkce, redirect_uri) = (self.client_secret.is_empty(), self.redirect_uri());
+        let request = self.auth_request.get_or_insert_with(|| AuthRequest::new(pkce));
+
+        request.authorize_url(&self.client_id, &redirect_uri, &Scope::stringify(SCOPES))
+    }
 
-    pub async fn refresh_user_token(&mut self) -> ClientResult<()> {
-        if let Some(refresh_token) = self.client.token.as_ref().and_then(|t| t.refresh_token.as_deref()) {
-            let token = refresh_token.to_owned();
+    /// Ends the login attempt in progress, the next authorization URL starts
+    /// a new one with a new state
+    pub fn end_authorization(&mut self) { self.auth_request = None; }
 
-            self.client.refresh_user_token(&token).await
-        } else {
-            Err(ClientError::InvalidAuth("Missing refresh token".into()))
+    async fn my_user_id(&self) -> ClientResult<String> {
+        self.user_id
+            .get_or_fetch(|| self.limiter.run(|| self.client.me()).map_ok(|user| user.id))
+            .await
+    }
+
+    /// Time to refresh access token at, `None` if there is no token to refresh
+    pub fn token_expires_at(&self) -> Option<DateTime<Utc>> {
+        let token = self.client.token.as_ref().filter(|token| token.refresh_token.is_some())?;
+        Some(token.expires_at.unwrap_or_else(Utc::now))
+    }
+
+    /// Notified when Spotify rejects access token, so it needs to be refreshed
+    /// right away
+    pub fn token_rejected(&self) -> Arc<Notify> { self.limiter.unauthorized() }
+
+    /// Notified whenever a playback command is sent, so playback state should
+    /// be checked soon
+    pub fn playback_changed(&self) -> Arc<Notify> { self.playback_changed.clone() }
+
+    /// Sends a playback command and wakes up playback state polling
+    async fn control<F, R>(&self, request: F) -> ClientResult<()>
+    where
+        F: FnMut() -> R,
+        R: Future<Output = ClientResult<()>>,
+    {
+        let result = self.limiter.run(request).await;
+
+        if result.is_ok() {
+            self.playback_changed.notify();
+        }
+
+        result
+    }
+
+    /// Sets volume without waking up playback state polling, for volume ramps
+    /// sending many changes in a row
+    pub async fn set_ramp_volume(&self, value: u8) -> ClientResult<()> {
+        self.limiter.run(|| self.client.volume(value, None)).await
+    }
+
+    pub fn is_auth_state(&self, state: &str) -> bool {
+        self.auth_request.as_ref().map_or(false, |request| request.state == state)
+    }
+
+    /// Requests a fresh access token. The request doesn't borrow the client,
+    /// so that it isn't locked while the request is sent, the result is
+    /// stored with [`save_refreshed_token`](Self::save_refreshed_token).
+    pub fn refresh_user_token(&self) -> impl Future<Output = ClientResult<RefreshedToken>> + 'static {
+        let refreshed_from = self.client.token.as_ref().and_then(|token| token.refresh_token.clone());
+        let (token_url, client_id, client_secret) = (self.token_url.clone(), self.client_id.clone(), self.client_secret.clone());
+
+        async move {
+            let refreshed_from = refreshed_from.ok_or_else(|| ClientError::InvalidAuth("Missing refresh token".into()))?;
+            let token = refresh_access_token(&token_url, &client_id, &client_secret, &refreshed_from).await?;
+
+            Ok(RefreshedToken { refreshed_from, token })
+        }
+    }
+
+    /// Stores refreshed access token, unless another user logged in or the
+    /// account was switched while it was refreshed
+    pub async fn save_refreshed_token(&mut self, refreshed: RefreshedToken) -> ClientResult<()> {
+        let current = self.client.token.as_ref().and_then(|token| token.refresh_token.as_deref());
+
+        if current != Some(&*refreshed.refreshed_from) {
+            info!("token changed while it was refreshed, dropping refreshed token");
+            return Ok(());
+        }
+
+        self.save_token(refreshed.token).await
+    }
+
+    /// Tokens got without `rspotify` are cached the same way it does
+    async fn save_token(&mut self, token: Token) -> ClientResult<()> {
+        match serde_json::to_vec(&token) {
+            Ok(data) => {
+                if let Err(error) = tokio::fs::write(&self.cache_path, data).await {
+                    warn!("failed to write token cache: {}", error);
+                }
+            }
+            Err(error) => warn!("failed to serialize token: {}", error),
         }
+
+        self.client.token = Some(token);
+
+        Ok(())
     }
 
+    fn redirect_uri(&self) -> String {
+        self.client
+            .oauth
+            .as_ref()
+            .map(|oauth| oauth.redirect_uri.clone())
+            .unwrap_or_default()
+    }
+
+    /// Sends token requests to another endpoint, to test logins offline
+    #[cfg(test)]
+    pub fn set_token_url(&mut self, url: String) { self.token_url = url; }
+
     pub fn set_redirect_uri<'a>(&mut self, url: impl Into<Cow<'a, str>>) {
         if let Some(ref mut oauth) = self.client.oauth {
             oauth.redirect_uri = url.into().into_owned();
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    async fn fetch_user(calls: &AtomicUsize, result: ClientResult<&str>) -> ClientResult<String> {
+        calls.fetch_add(1, Ordering::SeqCst);
+        result.map(String::from)
+    }
+
+    #[tokio::test]
+    async fn user_id_is_fetched_once() {
+        let user_id = CurrentUserId::default();
+        let calls = AtomicUsize::new(0);
+
+        assert_eq!(
+            user_id.get_or_fetch(|| fetch_user(&calls, Ok("alice"))).await.unwrap(),
+            "alice"
+        );
+        assert_eq!(user_id.get_or_fetch(|| fetch_user(&calls, Ok("bob"))).await.unwrap(), "alice");
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn user_id_is_fetched_again_after_clear() {
+        let user_id = CurrentUserId::default();
+        let calls = AtomicUsize::new(0);
+
+        user_id.get_or_fetch(|| fetch_user(&calls, Ok("alice"))).await.unwrap();
+        user_id.clear();
+
+        assert_eq!(user_id.get_or_fetch(|| fetch_user(&calls, Ok("bob"))).await.unwrap(), "bob");
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn failed_user_id_fetch_is_not_cached() {
+        let user_id = CurrentUserId::default();
+        let calls = AtomicUsize::new(0);
+
+        let failed = user_id
+            .get_or_fetch(|| fetch_user(&calls, Err(ClientError::Unauthorized)))
+            .await;
+        assert!(failed.is_err());
+
+        assert_eq!(
+            user_id.get_or_fetch(|| fetch_user(&calls, Ok("alice"))).await.unwrap(),
+            "alice"
+        );
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn switching_account_forgets_user_id() {
+        let mut spotify = Spotify::new(
+            "client".to_owned(),
+            String::new(),
+            std::env::temp_dir().join("spodjfy-test-token.json"),
+        );
+        let calls = AtomicUsize::new(0);
+
+        spotify
+            .user_id
+            .get_or_fetch(|| fetch_user(&calls, Ok("alice")))
+            .await
+            .unwrap();
+        spotify.setup_client("other".to_owned(), String::new()).unwrap();
+
+        assert_eq!(
+            spotify.user_id.get_or_fetch(|| fetch_user(&calls, Ok("bob"))).await.unwrap(),
+            "bob"
+        );
+    }
+}
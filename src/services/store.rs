@@ -10,6 +10,8 @@ pub enum StorageError {
     Bincode(#[from] bincode::Error),
     #[error("database error: {0}")]
     Sled(#[from] sled::Error),
+    #[error("storage is not available")]
+    Unavailable,
 }
 
 pub trait StorageModel: Serialize + DeserializeOwned + Sized {
@@ -83,6 +85,19 @@ impl<T: StorageModel> Collection<T> {
         Ok(())
     }
 
+    pub fn iter(&self) -> impl Iterator<Item = Result<T, StorageError>> {
+        self.tree
+            .iter()
+            .values()
+            .map(|model| model.map_err(StorageError::from).and_then(T::decode))
+    }
+
+    pub fn clear(&self) -> Result<(), StorageError> { Ok(self.tree.clear()?) }
+
+    pub fn len(&self) -> usize { self.tree.len() }
+
+    pub fn is_empty(&self) -> bool { self.tree.is_empty() }
+
     pub async fn flush(&self) -> Result<usize, StorageError> { Ok(self.tree.flush_async().await?) }
 }
 
@@ -95,4 +110,6 @@ impl Storage {
             phantom: PhantomData,
         })
     }
+
+    pub fn flush(&self) -> Result<usize, StorageError> { Ok(self.db.flush()?) }
 }
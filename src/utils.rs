@@ -1,16 +1,31 @@
-use crate::models::{COL_ITEM_NAME, COL_ITEM_URI};
+use crate::{
+    models::{COL_ITEM_NAME, COL_ITEM_URI},
+    services::store::StorageError,
+    subscribe, AppEvent,
+};
 use glib::{
     bitflags::_core::{future::Future, time::Duration},
     MainContext,
 };
 use gtk::TreeModelExt;
 use rspotify::client::ClientError;
-use std::sync::Arc;
+use std::{borrow::Cow, sync::Arc};
 use thiserror::Error;
-use tokio::{runtime::Handle, sync::RwLock, task::JoinError};
+use tokio::{
+    runtime::Handle,
+    sync::{
+        broadcast::{Receiver, RecvError},
+        RwLock,
+    },
+    task::JoinError,
+};
 
 pub type AsyncCell<T> = Arc<RwLock<T>>;
 
+/// Request rejected because of expired access token is sent again once the
+/// token is refreshed, or after this timeout if it's not
+const TOKEN_REFRESH_TIMEOUT_MS: u32 = 10_000;
+
 pub fn humanize_time(time_ms: u32) -> String {
     let seconds = time_ms / 1000;
     let (minutes, seconds) = (seconds / 60, seconds % 60);
@@ -23,6 +38,20 @@ pub fn humanize_time(time_ms: u32) -> String {
     }
 }
 
+pub fn parse_time(time: &str) -> Option<u32> {
+    time.split(':')
+        .try_fold((0u32, 0usize), |(acc, parts), part| {
+            let value = part.trim().parse::<u32>().ok()?;
+            if parts > 0 && value >= 60 {
+                None
+            } else {
+                Some((acc * 60 + value, parts + 1))
+            }
+        })
+        .filter(|&(_, parts)| (1..=3).contains(&parts))
+        .map(|(seconds, _)| seconds * 1000)
+}
+
 pub fn humanize_inexact_time(time_ms: u32) -> String {
     let seconds = time_ms / 1000;
 
@@ -37,6 +66,15 @@ pub fn humanize_inexact_time(time_ms: u32) -> String {
     }
 }
 
+/// Quotes CSV field if it contains a separator, a quote or a line break
+pub fn csv_field(value: &str) -> Cow<str> {
+    if value.contains(&[',', '"', '\n', '\r'][..]) {
+        Cow::Owned(format!("\"{}\"", value.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(value)
+    }
+}
+
 #[inline]
 pub fn rate_to_stars(rate: u32) -> String {
     let stars = rate / 21 + 1;
@@ -144,6 +182,8 @@ pub enum SpawnError {
     Join(#[from] JoinError),
     #[error(transparent)]
     Spotify(#[from] ClientError),
+    #[error("storage error: {0}")]
+    Storage(#[from] StorageError),
 }
 
 pub trait Extract<T: 'static> {
@@ -196,10 +236,17 @@ pub trait Spawn {
 
         self.gcontext().spawn_local(async move {
             let mut retry_count = 0;
+            // Subscribed before the first attempt not to miss token refresh
+            let mut events = subscribe();
 
             loop {
                 match body(pool.clone(), scope.clone(), args.clone()).await {
                     Ok(_) => break,
+                    Err(SpawnError::Spotify(ClientError::Unauthorized)) if retry_count == 0 => {
+                        info!("access token rejected, retry after it's refreshed...");
+
+                        token_refreshed(&mut events).await;
+                    }
                     Err(error) => {
                         error!("spawn error: {}", error);
 
@@ -229,3 +276,18 @@ pub trait Spawn {
     fn pool(&self) -> Handle;
     fn retry_policy(error: SpawnError, _retry_count: usize) -> RetryPolicy<SpawnError> { RetryPolicy::ForwardError(error) }
 }
+
+/// Waits until access token is refreshed, but no longer than
+/// [`TOKEN_REFRESH_TIMEOUT_MS`]
+async fn token_refreshed(events: &mut Receiver<AppEvent>) {
+    let refreshed = async {
+        loop {
+            match events.recv().await {
+                Ok(AppEvent::SpotifyTokenRefreshed) | Ok(AppEvent::SpotifyAuthError(_)) | Err(RecvError::Closed) => break,
+                Ok(_) | Err(RecvError::Lagged(_)) => {}
+            }
+        }
+    };
+
+    futures::future::select(Box::pin(refreshed), glib::timeout_future(TOKEN_REFRESH_TIMEOUT_MS)).await;
+}